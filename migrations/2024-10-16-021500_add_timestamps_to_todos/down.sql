@@ -0,0 +1,22 @@
+-- Recreate the todos table without the timestamp columns
+CREATE TABLE todos_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT,
+    priority INTEGER,
+    due_date DATE,
+    completed BOOL NOT NULL,
+    user_id INTEGER NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Copy the data back from the current todos table into the old structure
+INSERT INTO todos_new (id, title, description, priority, due_date, completed, user_id)
+SELECT id, title, description, priority, due_date, completed, user_id
+FROM todos;
+
+-- Drop the current todos table
+DROP TABLE todos;
+
+-- Rename the new table to todos
+ALTER TABLE todos_new RENAME TO todos;
@@ -0,0 +1,25 @@
+-- Create a new todos table that records when each item was created and completed
+CREATE TABLE todos_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT,
+    priority INTEGER,
+    due_date DATE,
+    completed BOOL NOT NULL,
+    user_id INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    completed_at TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Copy data from the old todos table, treating already completed items as completed now
+INSERT INTO todos_new (id, title, description, priority, due_date, completed, user_id, completed_at)
+SELECT id, title, description, priority, due_date, completed, user_id,
+       CASE WHEN completed THEN CURRENT_TIMESTAMP END
+FROM todos;
+
+-- Drop the old todos table
+DROP TABLE todos;
+
+-- Rename the new table to todos
+ALTER TABLE todos_new RENAME TO todos;
@@ -0,0 +1,8 @@
+CREATE TABLE user_settings (
+    user_id INTEGER PRIMARY KEY NOT NULL,
+    timezone TEXT NOT NULL DEFAULT 'UTC',
+    week_start TEXT NOT NULL DEFAULT 'monday',
+    date_format TEXT NOT NULL DEFAULT '%Y-%m-%d',
+    default_priority INTEGER,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
@@ -0,0 +1,2 @@
+-- Optional precise deadline stored in UTC; existing due_date values are left untouched
+ALTER TABLE todos ADD COLUMN due_at TIMESTAMP;
@@ -0,0 +1,39 @@
+-- Recreate the todos table with an unconstrained, nullable priority
+CREATE TABLE todos_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT,
+    priority INTEGER,
+    due_date DATE,
+    completed BOOL NOT NULL,
+    user_id INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    completed_at TIMESTAMP,
+    due_at TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+INSERT INTO todos_new (id, title, description, priority, due_date, completed, user_id, created_at, completed_at, due_at)
+SELECT id, title, description, NULLIF(priority, 0), due_date, completed, user_id, created_at, completed_at, due_at
+FROM todos;
+
+DROP TABLE todos;
+
+ALTER TABLE todos_new RENAME TO todos;
+
+CREATE TABLE user_settings_new (
+    user_id INTEGER PRIMARY KEY NOT NULL,
+    timezone TEXT NOT NULL DEFAULT 'UTC',
+    week_start TEXT NOT NULL DEFAULT 'monday',
+    date_format TEXT NOT NULL DEFAULT '%Y-%m-%d',
+    default_priority INTEGER,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+INSERT INTO user_settings_new (user_id, timezone, week_start, date_format, default_priority)
+SELECT user_id, timezone, week_start, date_format, default_priority
+FROM user_settings;
+
+DROP TABLE user_settings;
+
+ALTER TABLE user_settings_new RENAME TO user_settings;
@@ -0,0 +1,44 @@
+-- Priorities are stored as 0 (none) through 4 (urgent)
+CREATE TABLE todos_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT,
+    priority INTEGER NOT NULL DEFAULT 0 CHECK (priority BETWEEN 0 AND 4),
+    due_date DATE,
+    completed BOOL NOT NULL,
+    user_id INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    completed_at TIMESTAMP,
+    due_at TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- Copy data from the old todos table, clamping existing priorities into range
+INSERT INTO todos_new (id, title, description, priority, due_date, completed, user_id, created_at, completed_at, due_at)
+SELECT id, title, description,
+       CASE WHEN priority IS NULL OR priority < 0 THEN 0 WHEN priority > 4 THEN 4 ELSE priority END,
+       due_date, completed, user_id, created_at, completed_at, due_at
+FROM todos;
+
+DROP TABLE todos;
+
+ALTER TABLE todos_new RENAME TO todos;
+
+-- Apply the same range to the users' default priority
+CREATE TABLE user_settings_new (
+    user_id INTEGER PRIMARY KEY NOT NULL,
+    timezone TEXT NOT NULL DEFAULT 'UTC',
+    week_start TEXT NOT NULL DEFAULT 'monday',
+    date_format TEXT NOT NULL DEFAULT '%Y-%m-%d',
+    default_priority INTEGER CHECK (default_priority BETWEEN 0 AND 4),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+INSERT INTO user_settings_new (user_id, timezone, week_start, date_format, default_priority)
+SELECT user_id, timezone, week_start, date_format,
+       CASE WHEN default_priority < 0 THEN 0 WHEN default_priority > 4 THEN 4 ELSE default_priority END
+FROM user_settings;
+
+DROP TABLE user_settings;
+
+ALTER TABLE user_settings_new RENAME TO user_settings;
@@ -0,0 +1,3 @@
+ALTER TABLE todos DROP COLUMN status_id;
+
+DROP TABLE statuses;
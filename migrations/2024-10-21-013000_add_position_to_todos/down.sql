@@ -0,0 +1,3 @@
+DROP INDEX todos_user_position;
+
+ALTER TABLE todos DROP COLUMN position;
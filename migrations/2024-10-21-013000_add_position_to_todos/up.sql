@@ -0,0 +1,7 @@
+-- Lexicographic sort key for manual ordering within each user's list
+ALTER TABLE todos ADD COLUMN position TEXT NOT NULL DEFAULT '';
+
+-- Start existing todos off in creation order, using fixed-width base-62 digits without trailing zeros
+UPDATE todos SET position = rtrim(printf('%08d', id), '0');
+
+CREATE INDEX todos_user_position ON todos (user_id, position);
@@ -0,0 +1,2 @@
+-- Bumped on every write so clients can detect concurrent edits
+ALTER TABLE todos ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,11 @@
+-- Responses saved against client-supplied Idempotency-Key headers; status is NULL while the first request is running
+CREATE TABLE idempotency_keys (
+    idempotency_key TEXT PRIMARY KEY NOT NULL,
+    fingerprint TEXT NOT NULL,
+    status INTEGER,
+    content_type TEXT,
+    body TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idempotency_keys_created_at ON idempotency_keys (created_at);
@@ -0,0 +1,11 @@
+-- Append-only log of writes to todos; the id is the sync token handed to clients
+CREATE TABLE todo_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    todo_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('created', 'updated', 'deleted')),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX todo_changes_user_id ON todo_changes (user_id, id);
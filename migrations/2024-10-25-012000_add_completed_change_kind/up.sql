@@ -0,0 +1,17 @@
+-- SQLite can't alter a CHECK constraint, so rebuild the log with the new kind allowed
+CREATE TABLE todo_changes_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    todo_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('created', 'updated', 'completed', 'deleted')),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+INSERT INTO todo_changes_new (id, todo_id, user_id, kind, created_at)
+SELECT id, todo_id, user_id, kind, created_at FROM todo_changes;
+
+DROP TABLE todo_changes;
+ALTER TABLE todo_changes_new RENAME TO todo_changes;
+
+CREATE INDEX todo_changes_user_id ON todo_changes (user_id, id);
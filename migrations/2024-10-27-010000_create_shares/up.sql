@@ -0,0 +1,14 @@
+-- Access one user grants another to a single todo, or to their whole list when todo_id is null
+CREATE TABLE shares (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    owner_id INTEGER NOT NULL,
+    grantee_id INTEGER NOT NULL,
+    todo_id INTEGER,
+    role TEXT NOT NULL CHECK (role IN ('viewer', 'editor')),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (owner_id) REFERENCES users(id),
+    FOREIGN KEY (grantee_id) REFERENCES users(id),
+    FOREIGN KEY (todo_id) REFERENCES todos(id)
+);
+
+CREATE INDEX shares_grantee_id ON shares (grantee_id);
@@ -0,0 +1,2 @@
+-- The user expected to do the todo, who need not be its owner
+ALTER TABLE todos ADD COLUMN assignee_id INTEGER REFERENCES users(id);
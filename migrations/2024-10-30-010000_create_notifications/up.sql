@@ -0,0 +1,28 @@
+-- In-app notifications; `kind` says what happened and the other ids say to whom and where
+CREATE TABLE notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('assigned', 'mentioned', 'shared', 'due_soon')),
+    actor_id INTEGER,
+    todo_id INTEGER,
+    comment_id INTEGER,
+    message TEXT NOT NULL,
+    read_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id),
+    FOREIGN KEY (actor_id) REFERENCES users(id),
+    FOREIGN KEY (todo_id) REFERENCES todos(id),
+    FOREIGN KEY (comment_id) REFERENCES comments(id)
+);
+
+CREATE INDEX notifications_user_id ON notifications (user_id);
+
+-- Which kinds of notification a user wants; users without a row get all of them
+CREATE TABLE notification_preferences (
+    user_id INTEGER PRIMARY KEY NOT NULL,
+    assigned BOOLEAN NOT NULL DEFAULT 1,
+    mentioned BOOLEAN NOT NULL DEFAULT 1,
+    shared BOOLEAN NOT NULL DEFAULT 1,
+    due_soon BOOLEAN NOT NULL DEFAULT 1,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
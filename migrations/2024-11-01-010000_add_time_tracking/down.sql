@@ -0,0 +1,2 @@
+DROP TABLE time_entries;
+ALTER TABLE todos DROP COLUMN estimate_minutes;
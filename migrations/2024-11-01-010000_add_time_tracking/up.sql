@@ -0,0 +1,19 @@
+ALTER TABLE todos ADD COLUMN estimate_minutes INTEGER;
+
+-- Time spent on todos; an entry without an end is a running timer
+CREATE TABLE time_entries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    todo_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    started_at TIMESTAMP NOT NULL,
+    ended_at TIMESTAMP,
+    note TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (todo_id) REFERENCES todos(id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX time_entries_todo_id ON time_entries (todo_id);
+CREATE INDEX time_entries_user_id_started_at ON time_entries (user_id, started_at);
+-- Each user has at most one running timer
+CREATE UNIQUE INDEX time_entries_running ON time_entries (user_id) WHERE ended_at IS NULL;
@@ -0,0 +1,2 @@
+DROP TABLE template_items;
+DROP TABLE templates;
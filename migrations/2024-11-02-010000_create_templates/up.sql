@@ -0,0 +1,23 @@
+-- Reusable checklists: each item becomes a todo when the template is instantiated
+CREATE TABLE templates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- due_offset_days is counted from the anchor date given when instantiating
+CREATE TABLE template_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    template_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT,
+    priority INTEGER CHECK (priority BETWEEN 0 AND 4),
+    due_offset_days INTEGER,
+    estimate_minutes INTEGER,
+    FOREIGN KEY (template_id) REFERENCES templates(id)
+);
+
+CREATE INDEX template_items_template_id ON template_items (template_id);
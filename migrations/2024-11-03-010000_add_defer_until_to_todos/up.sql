@@ -0,0 +1,2 @@
+-- Hidden from default listings and views until this moment
+ALTER TABLE todos ADD COLUMN defer_until TIMESTAMP;
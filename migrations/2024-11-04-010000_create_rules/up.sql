@@ -0,0 +1,14 @@
+-- Automation rules; conditions and actions are stored as JSON
+CREATE TABLE rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    trigger TEXT NOT NULL CHECK (trigger IN ('created', 'updated', 'completed')),
+    conditions TEXT NOT NULL,
+    actions TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX rules_user_id ON rules (user_id);
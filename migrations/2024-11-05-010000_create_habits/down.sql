@@ -0,0 +1,2 @@
+DROP TABLE habit_checkins;
+DROP TABLE habits;
@@ -0,0 +1,23 @@
+-- Habits are met by checking in `target` times a day or a week
+CREATE TABLE habits (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    frequency TEXT NOT NULL CHECK (frequency IN ('daily', 'weekly')),
+    target INTEGER NOT NULL DEFAULT 1 CHECK (target > 0),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- One row per habit and local calendar date, counting that day's check-ins
+CREATE TABLE habit_checkins (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    habit_id INTEGER NOT NULL,
+    date DATE NOT NULL,
+    count INTEGER NOT NULL DEFAULT 1 CHECK (count > 0),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (habit_id) REFERENCES habits(id),
+    UNIQUE (habit_id, date)
+);
+
+CREATE INDEX habits_user_id ON habits (user_id);
@@ -0,0 +1,2 @@
+-- The seeded columns can't be told apart from ones users made themselves, so they stay
+SELECT 1;
@@ -0,0 +1,11 @@
+-- Statuses used to be created the first time a board was read; give every
+-- user who has none yet the default columns now that users start with them
+INSERT INTO statuses (user_id, name, category, position)
+SELECT users.id, defaults.name, defaults.category, defaults.position
+FROM users
+CROSS JOIN (
+    SELECT 'To Do' AS name, 'todo' AS category, 0 AS position
+    UNION ALL SELECT 'In Progress', 'in_progress', 1
+    UNION ALL SELECT 'Done', 'done', 2
+) AS defaults
+WHERE NOT EXISTS (SELECT 1 FROM statuses WHERE statuses.user_id = users.id);
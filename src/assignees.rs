@@ -0,0 +1,120 @@
+use rocket::http::Status;
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::etag::{check_version, IfMatch, Tagged, PRECONDITION_FAILED};
+use crate::events::EventBus;
+use crate::notifications::{notify, username, NewNotification, NotificationKind};
+use crate::schema::{todos, users};
+use crate::sharing::{authorize, permission, visible_to, Permission};
+use crate::sync::{record_change, ChangeKind};
+use crate::todos::{load_details, not_deferred, TodoItem};
+use diesel::prelude::*;
+use log::{error, info};
+use chrono::Utc;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Assignment {
+    pub user_id: i32,
+    pub assignee_id: i32,
+}
+
+// Set or clear a todo's assignee, returning its new version
+fn set_assignee(connection: &mut SqliteConnection, todo: &TodoItem, if_match: IfMatch, assignee_id: Option<i32>) -> Result<i32, (Status, &'static str)> {
+    check_version(if_match, todo.version)?;
+
+    let updated = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+        let updated = diesel::update(todos::table.find(todo.id).filter(todos::dsl::version.eq(todo.version)))
+            .set((
+                todos::dsl::assignee_id.eq(assignee_id),
+                todos::dsl::version.eq(todo.version + 1),
+            ))
+            .execute(connection)?;
+        if updated > 0 {
+            record_change(connection, todo.id, ChangeKind::Updated)?;
+        }
+        Ok(updated)
+    }).map_err(|_| (Status::InternalServerError, "Failed to assign todo"))?;
+
+    if updated == 0 {
+        return Err((Status::PreconditionFailed, PRECONDITION_FAILED));
+    }
+
+    Ok(todo.version + 1)
+}
+
+// Assign a todo to someone who can already see it
+#[put("/todos/<id>/assignee", format = "json", data = "<assignment>")]
+pub fn assign_todo(pool: &State<DbPool>, events: &State<EventBus>, id: i32, if_match: IfMatch, assignment: Json<Assignment>) -> Result<Tagged<&'static str>, (Status, &'static str)> {
+    info!("Assigning to-do item {} to user {}", id, assignment.assignee_id);
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let todo = authorize(&mut connection, assignment.user_id, id, Permission::Edit)?;
+
+    let assignee_exists: i64 = users::table
+        .find(assignment.assignee_id)
+        .count()
+        .get_result(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch user"))?;
+    if assignee_exists == 0 {
+        return Err((Status::NotFound, "User not found"));
+    }
+
+    let assignee_access = permission(&mut connection, assignment.assignee_id, &todo)
+        .map_err(|_| (Status::InternalServerError, "Failed to check todo access"))?;
+    if assignee_access.is_none() {
+        return Err((Status::BadRequest, "Assignee does not have access to this todo"));
+    }
+
+    let version = set_assignee(&mut connection, &todo, if_match, Some(assignment.assignee_id))?;
+    events.publish(&mut connection);
+
+    if assignment.assignee_id != assignment.user_id {
+        let notified = username(&mut connection, assignment.user_id).and_then(|actor| notify(&mut connection, NewNotification {
+            user_id: assignment.assignee_id,
+            kind: NotificationKind::Assigned,
+            actor_id: Some(assignment.user_id),
+            todo_id: Some(todo.id),
+            comment_id: None,
+            message: format!("{} assigned you \"{}\"", actor, todo.title),
+        }));
+        if let Err(err) = notified {
+            error!("Failed to notify assignee: {:?}", err);
+        }
+    }
+
+    Ok(Tagged { version, inner: "Todo assigned successfully!" })
+}
+
+// Clear a todo's assignee
+#[delete("/todos/<id>/assignee?<user_id>")]
+pub fn unassign_todo(pool: &State<DbPool>, events: &State<EventBus>, id: i32, user_id: i32, if_match: IfMatch) -> Result<Tagged<&'static str>, (Status, &'static str)> {
+    info!("Unassigning to-do item {}", id);
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let todo = authorize(&mut connection, user_id, id, Permission::Edit)?;
+    let version = set_assignee(&mut connection, &todo, if_match, None)?;
+    events.publish(&mut connection);
+
+    Ok(Tagged { version, inner: "Todo unassigned successfully!" })
+}
+
+// Open todos assigned to a user, across every list they can see
+#[get("/todos/assigned?<user_id>")]
+pub fn get_assigned(pool: &State<DbPool>, user_id: i32) -> Result<Json<Vec<TodoItem>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let mut results: Vec<TodoItem> = todos::table
+        .filter(todos::dsl::assignee_id.eq(user_id))
+        .filter(todos::dsl::completed.eq(false))
+        .filter(visible_to(user_id))
+        .filter(not_deferred(Utc::now().naive_utc()))
+        .order((todos::dsl::due_date.is_null(), todos::dsl::due_date, todos::dsl::position, todos::dsl::id))
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+    load_details(&mut connection, &mut results)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+
+    Ok(Json(results))
+}
@@ -0,0 +1,372 @@
+//! File attachments on todos.
+//!
+//! Uploads are multipart forms with the acting `user_id` and a `file` part.
+//! File contents go to an `AttachmentStore` keyed by their SHA-256 digest, so
+//! the same file attached twice is stored once; the `attachments` table keeps
+//! each attachment's name, type and size. A file is removed from the store
+//! when the last attachment referring to it is deleted, including when its
+//! todo is deleted. Every attachment counts its full size against the quota
+//! of the user who uploaded it. Storing a file and releasing one both hold
+//! `BLOBS`, so an upload can't reuse a file that a concurrent delete is about
+//! to remove.
+//!
+//! The store and quota are read from the `attachments` config key at ignite:
+//!
+//! ```toml
+//! [default.attachments]
+//! dir = "attachments"
+//! quota_bytes = 104857600
+//! ```
+
+use rocket::fairing::AdHoc;
+use rocket::form::{Form, FromForm};
+use rocket::fs::TempFile;
+use rocket::http::{ContentType, Header, Status};
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use rocket::tokio::io::AsyncReadExt;
+use rocket::{Request, State};
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::etag::IfMatch;
+use crate::idempotency::{IdempotencyKey, StoredResponse};
+use crate::schema::attachments;
+use crate::sharing::{authorize, Permission};
+use crate::todos::remove_todo;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel::sql_types::BigInt;
+use log::{error, info};
+use chrono::NaiveDateTime;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Cursor, ErrorKind};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+// Held while a file is stored or released, across the query that decides it
+static BLOBS: Mutex<()> = Mutex::new(());
+
+fn lock_blobs() -> MutexGuard<'static, ()> {
+    BLOBS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Where attachment contents are kept, by SHA-256 digest
+pub trait AttachmentStore: Send + Sync {
+    // Store `contents` under `digest`, doing nothing if it is already stored
+    fn put(&self, digest: &str, contents: &[u8]) -> io::Result<()>;
+    fn get(&self, digest: &str) -> io::Result<Vec<u8>>;
+    // Removing something that isn't stored is not an error
+    fn remove(&self, digest: &str) -> io::Result<()>;
+}
+
+// Files in a directory, fanned out into subdirectories by the first two digits of the digest
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(LocalStore { root })
+    }
+
+    fn path(&self, digest: &str) -> io::Result<PathBuf> {
+        if digest.len() < 3 || !digest.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "invalid digest"));
+        }
+        Ok(self.root.join(&digest[..2]).join(digest))
+    }
+}
+
+impl AttachmentStore for LocalStore {
+    fn put(&self, digest: &str, contents: &[u8]) -> io::Result<()> {
+        let path = self.path(digest)?;
+        if path.exists() {
+            return Ok(());
+        }
+
+        // Write beside the final path first so a half-written file is never served
+        fs::create_dir_all(path.parent().unwrap_or(&self.root))?;
+        let partial = path.with_extension(format!("{}.partial", rand::random::<u32>()));
+        fs::write(&partial, contents)?;
+        fs::rename(&partial, &path)
+    }
+
+    fn get(&self, digest: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.path(digest)?)
+    }
+
+    fn remove(&self, digest: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(digest)?) {
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AttachmentConfig {
+    pub dir: PathBuf,
+    pub quota_bytes: i64,
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        AttachmentConfig {
+            dir: PathBuf::from("attachments"),
+            quota_bytes: 100 * 1024 * 1024,
+        }
+    }
+}
+
+pub struct Storage {
+    pub store: Box<dyn AttachmentStore>,
+    pub quota_bytes: i64,
+}
+
+impl Storage {
+    // Manage a `Storage` backed by a local store, as configured
+    pub fn fairing() -> AdHoc {
+        AdHoc::try_on_ignite("Attachment storage", |rocket| async {
+            let config: AttachmentConfig = rocket.figment().extract_inner("attachments").unwrap_or_default();
+            match LocalStore::new(&config.dir) {
+                Ok(store) => Ok(rocket.manage(Storage { store: Box::new(store), quota_bytes: config.quota_bytes })),
+                Err(err) => {
+                    error!("Failed to open attachment store at {}: {}", config.dir.display(), err);
+                    Err(rocket)
+                }
+            }
+        })
+    }
+}
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct Attachment {
+    pub id: i32,
+    pub todo_id: i32,
+    pub user_id: i32,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    pub digest: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(FromForm)]
+pub struct Upload<'r> {
+    pub user_id: i32,
+    pub file: TempFile<'r>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StorageUsage {
+    pub used_bytes: i64,
+    pub quota_bytes: i64,
+}
+
+// An attachment's contents, sent with its type and name
+pub struct Download {
+    content_type: ContentType,
+    filename: String,
+    contents: Vec<u8>,
+}
+
+impl<'r> Responder<'r, 'static> for Download {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let filename = self.filename.replace(['"', '\\'], "_");
+        Response::build()
+            .header(self.content_type)
+            .header(Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+            .sized_body(self.contents.len(), Cursor::new(self.contents))
+            .ok()
+    }
+}
+
+// The name a file was uploaded with, without any directories
+fn upload_filename(file: &TempFile<'_>) -> String {
+    file.raw_name()
+        .map(|name| name.dangerous_unsafe_unsanitized_raw().as_str())
+        .and_then(|name| name.rsplit(['/', '\\']).next())
+        .map(|name| name.trim().chars().filter(|c| !c.is_control()).collect::<String>())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "attachment".to_string())
+}
+
+pub fn used_bytes(connection: &mut SqliteConnection, user_id: i32) -> QueryResult<i64> {
+    attachments::table
+        .filter(attachments::dsl::user_id.eq(user_id))
+        .select(sql::<BigInt>("COALESCE(SUM(attachments.size), 0)"))
+        .first(connection)
+}
+
+// Remove stored files that no attachment refers to any more
+pub fn release(connection: &mut SqliteConnection, store: &dyn AttachmentStore, digests: Vec<String>) {
+    let _blobs = lock_blobs();
+    for digest in digests {
+        let referenced = attachments::table
+            .filter(attachments::dsl::digest.eq(&digest))
+            .count()
+            .get_result::<i64>(connection);
+        let removed = match referenced {
+            Ok(0) => store.remove(&digest).map_err(|err| err.to_string()),
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        };
+        if let Err(err) = removed {
+            error!("Failed to release attachment {}: {}", digest, err);
+        }
+    }
+}
+
+// The files a todo's attachments point at, to release once the todo is gone
+pub fn attached_digests(connection: &mut SqliteConnection, todo_id: i32) -> QueryResult<Vec<String>> {
+    attachments::table
+        .filter(attachments::dsl::todo_id.eq(todo_id))
+        .select(attachments::dsl::digest)
+        .distinct()
+        .load(connection)
+}
+
+// Delete a todo along with its attachments, removing any files left unreferenced
+pub fn remove_todo_and_files(connection: &mut SqliteConnection, store: &dyn AttachmentStore, id: i32, if_match: IfMatch) -> Result<bool, (Status, &'static str)> {
+    let digests = attached_digests(connection, id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch attachments"))?;
+
+    let removed = remove_todo(connection, id, if_match)?;
+    if removed {
+        release(connection, store, digests);
+    }
+    Ok(removed)
+}
+
+fn find_attachment(connection: &mut SqliteConnection, todo_id: i32, id: i32) -> Result<Attachment, (Status, &'static str)> {
+    attachments::table
+        .find(id)
+        .filter(attachments::dsl::todo_id.eq(todo_id))
+        .first(connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch attachment"))?
+        .ok_or((Status::NotFound, "Attachment not found"))
+}
+
+// Attach a file to a todo the user can edit
+#[post("/todos/<id>/attachments", data = "<upload>")]
+pub async fn upload_attachment(pool: &State<DbPool>, storage: &State<Storage>, id: i32, key: IdempotencyKey, upload: Form<Upload<'_>>) -> Result<StoredResponse, (Status, &'static str)> {
+    let size = upload.file.len() as i64;
+    if size == 0 {
+        return Err((Status::BadRequest, "File is empty"));
+    }
+
+    let mut contents = Vec::with_capacity(size as usize);
+    upload.file.open().await
+        .map_err(|_| (Status::InternalServerError, "Failed to read upload"))?
+        .read_to_end(&mut contents).await
+        .map_err(|_| (Status::InternalServerError, "Failed to read upload"))?;
+
+    let digest = hex::encode(Sha256::digest(&contents));
+    let filename = upload_filename(&upload.file);
+    let content_type = upload.file.content_type()
+        .map(|content_type| content_type.to_string())
+        .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_string());
+    let user_id = upload.user_id;
+
+    // The whole body has been read, so nothing below waits while holding a connection
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    let body = format!("user_id={}\nfilename={}\ncontent_type={}\ndigest={}", user_id, filename, content_type, digest);
+    key.respond(&mut connection, &body, |connection| {
+        authorize(connection, user_id, id, Permission::Edit)?;
+
+        info!("Attaching {} ({} bytes) to to-do item {}", filename, size, id);
+        let _blobs = lock_blobs();
+        // Taking the write lock up front keeps concurrent uploads from both passing the quota check
+        let attached = connection.immediate_transaction::<_, Error, _>(|connection| {
+            if used_bytes(connection, user_id)? + size > storage.quota_bytes {
+                return Ok(None);
+            }
+
+            diesel::insert_into(attachments::table)
+                .values((
+                    attachments::dsl::todo_id.eq(id),
+                    attachments::dsl::user_id.eq(user_id),
+                    attachments::dsl::filename.eq(&filename),
+                    attachments::dsl::content_type.eq(&content_type),
+                    attachments::dsl::size.eq(size),
+                    attachments::dsl::digest.eq(&digest),
+                ))
+                .execute(connection)?;
+            let attachment = attachments::table.order(attachments::dsl::id.desc()).first::<Attachment>(connection)?;
+
+            storage.store.put(&digest, &contents).map_err(|err| {
+                error!("Failed to store attachment {}: {}", digest, err);
+                Error::RollbackTransaction
+            })?;
+            Ok(Some(attachment))
+        }).map_err(|_| (Status::InternalServerError, "Failed to add attachment"))?;
+
+        let attachment = attached.ok_or((Status::PayloadTooLarge, "Storage quota exceeded"))?;
+        StoredResponse::json(Status::Ok, &attachment)
+    })
+}
+
+#[get("/todos/<id>/attachments?<user_id>")]
+pub fn get_attachments(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<Json<Vec<Attachment>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    authorize(&mut connection, user_id, id, Permission::View)?;
+
+    let results = attachments::table
+        .filter(attachments::dsl::todo_id.eq(id))
+        .order(attachments::dsl::id)
+        .load::<Attachment>(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch attachments"))?;
+
+    Ok(Json(results))
+}
+
+// Download an attachment with the type it was uploaded as
+#[get("/todos/<id>/attachments/<attachment_id>?<user_id>")]
+pub fn download_attachment(pool: &State<DbPool>, storage: &State<Storage>, id: i32, attachment_id: i32, user_id: i32) -> Result<Download, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    authorize(&mut connection, user_id, id, Permission::View)?;
+    let attachment = find_attachment(&mut connection, id, attachment_id)?;
+
+    let contents = storage.store.get(&attachment.digest)
+        .map_err(|_| (Status::InternalServerError, "Failed to read attachment"))?;
+
+    Ok(Download {
+        content_type: ContentType::parse_flexible(&attachment.content_type).unwrap_or(ContentType::Binary),
+        filename: attachment.filename,
+        contents,
+    })
+}
+
+#[delete("/todos/<id>/attachments/<attachment_id>?<user_id>")]
+pub fn delete_attachment(pool: &State<DbPool>, storage: &State<Storage>, id: i32, attachment_id: i32, user_id: i32) -> Result<&'static str, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    authorize(&mut connection, user_id, id, Permission::Edit)?;
+    let attachment = find_attachment(&mut connection, id, attachment_id)?;
+
+    info!("Deleting attachment {} from to-do item {}", attachment_id, id);
+    diesel::delete(attachments::table.find(attachment.id))
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to delete attachment"))?;
+    release(&mut connection, storage.store.as_ref(), vec![attachment.digest]);
+
+    Ok("Attachment deleted successfully!")
+}
+
+// How much of their quota a user's uploads take up
+#[get("/attachments/usage?<user_id>")]
+pub fn get_storage_usage(pool: &State<DbPool>, storage: &State<Storage>, user_id: i32) -> Result<Json<StorageUsage>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let used_bytes = used_bytes(&mut connection, user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to check storage usage"))?;
+
+    Ok(Json(StorageUsage { used_bytes, quota_bytes: storage.quota_bytes }))
+}
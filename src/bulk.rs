@@ -0,0 +1,234 @@
+use rocket::http::Status;
+use rocket::State;
+use serde::{Serialize, Deserialize};
+use crate::attachments::{attached_digests, release, AttachmentStore, Storage};
+use crate::db::DbPool;
+use crate::events::EventBus;
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::priority::Priority;
+use crate::rules::run_rules;
+use crate::schema::todos;
+use crate::sync::{record_change, ChangeKind};
+use crate::todos::{remove_dependents, TodoItem};
+use diesel::prelude::*;
+use diesel::result::Error;
+use log::info;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+
+// Todos have no projects or tags, so there is nothing to move them into or label them with
+#[derive(Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BulkAction {
+    Complete,
+    Reopen,
+    Delete,
+    SetPriority { priority: Priority },
+    SetDueDate { due_date: Option<NaiveDate> },
+}
+
+// Selects the user's todos matching every given field
+#[derive(Deserialize, Debug, Default)]
+pub struct BulkFilter {
+    pub completed: Option<bool>,
+    pub priority: Option<Priority>,
+    pub status_id: Option<i32>,
+    pub due_before: Option<NaiveDate>,
+    pub query: Option<String>,
+}
+
+// One action applied to either explicit ids or every todo matching a filter
+#[derive(Deserialize, Debug)]
+pub struct BulkOperation {
+    #[serde(flatten)]
+    pub action: BulkAction,
+    pub ids: Option<Vec<i32>>,
+    pub filter: Option<BulkFilter>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BulkRequest {
+    pub user_id: i32,
+    pub operations: Vec<BulkOperation>,
+    #[serde(default)]
+    pub all_or_nothing: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BulkItemResult {
+    pub operation: usize,
+    pub id: i32,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BulkResponse {
+    pub applied: bool,
+    pub results: Vec<BulkItemResult>,
+}
+
+// Resolve an operation's targets to todo ids, flagging explicit ids outside the user's list
+fn target_ids(connection: &mut SqliteConnection, user_id: i32, operation: &BulkOperation) -> QueryResult<Vec<(i32, bool)>> {
+    if let Some(ids) = &operation.ids {
+        let owned: Vec<i32> = todos::table
+            .filter(todos::dsl::user_id.eq(user_id))
+            .filter(todos::dsl::id.eq_any(ids))
+            .select(todos::dsl::id)
+            .load(connection)?;
+        return Ok(ids.iter().map(|id| (*id, owned.contains(id))).collect());
+    }
+
+    let filter = operation.filter.as_ref().expect("operations are validated to have a target");
+    let mut query = todos::table
+        .filter(todos::dsl::user_id.eq(user_id))
+        .select(todos::dsl::id)
+        .order((todos::dsl::position, todos::dsl::id))
+        .into_boxed();
+    if let Some(completed) = filter.completed {
+        query = query.filter(todos::dsl::completed.eq(completed));
+    }
+    if let Some(priority) = filter.priority {
+        query = query.filter(todos::dsl::priority.eq(priority));
+    }
+    if let Some(status_id) = filter.status_id {
+        query = query.filter(todos::dsl::status_id.eq(status_id));
+    }
+    if let Some(due_before) = filter.due_before {
+        query = query.filter(todos::dsl::due_date.lt(due_before));
+    }
+    if let Some(text) = &filter.query {
+        query = query.filter(todos::dsl::title.like(format!("%{}%", text)));
+    }
+
+    let ids: Vec<i32> = query.load(connection)?;
+    Ok(ids.into_iter().map(|id| (id, true)).collect())
+}
+
+// Targets are resolved inside the same transaction, so every id here still exists
+fn apply(connection: &mut SqliteConnection, user_id: i32, id: i32, action: &BulkAction) -> QueryResult<()> {
+    let target = todos::table.find(id);
+    let bump = todos::dsl::version.eq(todos::dsl::version + 1);
+
+    let updated = match action {
+        BulkAction::Complete => diesel::update(target.filter(todos::dsl::completed.eq(false)))
+            .set((
+                todos::dsl::completed.eq(true),
+                todos::dsl::completed_at.eq(Utc::now().naive_utc()),
+                todos::dsl::status_id.eq(None::<i32>),
+                bump,
+            ))
+            .execute(connection),
+        BulkAction::Reopen => diesel::update(target.filter(todos::dsl::completed.eq(true)))
+            .set((
+                todos::dsl::completed.eq(false),
+                todos::dsl::completed_at.eq(None::<NaiveDateTime>),
+                todos::dsl::status_id.eq(None::<i32>),
+                bump,
+            ))
+            .execute(connection),
+        BulkAction::Delete => diesel::delete(target).execute(connection),
+        BulkAction::SetPriority { priority } => diesel::update(target)
+            .set((todos::dsl::priority.eq(priority), bump))
+            .execute(connection),
+        BulkAction::SetDueDate { due_date } => diesel::update(target)
+            .set((
+                todos::dsl::due_date.eq(due_date),
+                todos::dsl::due_at.eq(None::<NaiveDateTime>),
+                bump,
+            ))
+            .execute(connection),
+    }?;
+
+    if updated > 0 {
+        let kind = match action {
+            BulkAction::Delete => return remove_dependents(connection, id, user_id),
+            BulkAction::Complete => ChangeKind::Completed,
+            _ => ChangeKind::Updated,
+        };
+        record_change(connection, id, kind)?;
+
+        // Bulk changes set off the owner's rules just as single ones do
+        let todo: TodoItem = todos::table.find(id).first(connection)?;
+        run_rules(connection, todo, kind);
+    }
+
+    Ok(())
+}
+
+// Apply several operations to many todos in a single transaction
+#[post("/todos/bulk", format = "json", data = "<request>")]
+pub fn bulk_todos(pool: &State<DbPool>, events: &State<EventBus>, storage: &State<Storage>, request: Idempotent<'_, BulkRequest>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let response = request.respond(&mut connection, |connection, request| run_bulk(connection, storage.store.as_ref(), require_payload(request)?.into_inner()));
+
+    events.publish(&mut connection);
+    response
+}
+
+fn run_bulk(connection: &mut SqliteConnection, store: &dyn AttachmentStore, request: BulkRequest) -> Result<StoredResponse, (Status, &'static str)> {
+    if request.operations.is_empty() {
+        return Err((Status::BadRequest, "At least one operation is required"));
+    }
+
+    if request.operations.iter().any(|operation| operation.ids.is_some() == operation.filter.is_some()) {
+        return Err((Status::BadRequest, "Each operation needs either ids or a filter"));
+    }
+
+    info!("Running {} bulk operations for user {}", request.operations.len(), request.user_id);
+
+    let mut results = Vec::new();
+    // Files of deleted todos are only released once the deletes are committed
+    let mut digests = Vec::new();
+    let outcome = connection.transaction::<_, Error, _>(|connection| {
+        for (index, operation) in request.operations.iter().enumerate() {
+            for (id, found) in target_ids(connection, request.user_id, operation)? {
+                if !found {
+                    results.push(BulkItemResult { operation: index, id, success: false, error: Some("Todo item not found".to_string()) });
+                    continue;
+                }
+
+                // Each item gets its own savepoint, so a failure partway through leaves none of its writes behind
+                let applied = connection.transaction::<_, Error, _>(|connection| {
+                    let released = if matches!(operation.action, BulkAction::Delete) {
+                        attached_digests(connection, id)?
+                    } else {
+                        Vec::new()
+                    };
+                    apply(connection, request.user_id, id, &operation.action)?;
+                    Ok(released)
+                });
+
+                let result = match applied {
+                    Ok(released) => {
+                        digests.extend(released);
+                        BulkItemResult { operation: index, id, success: true, error: None }
+                    }
+                    Err(err) => {
+                        error!("Bulk operation {} failed for todo {}: {:?}", index, id, err);
+                        BulkItemResult { operation: index, id, success: false, error: Some("Failed to update todo".to_string()) }
+                    }
+                };
+                results.push(result);
+            }
+        }
+
+        if request.all_or_nothing && results.iter().any(|result| !result.success) {
+            return Err(Error::RollbackTransaction);
+        }
+
+        Ok(())
+    });
+
+    match outcome {
+        Ok(()) => {
+            release(connection, store, digests);
+            StoredResponse::json(Status::Ok, &BulkResponse { applied: true, results })
+        }
+        Err(Error::RollbackTransaction) => StoredResponse::json(Status::UnprocessableEntity, &BulkResponse { applied: false, results }),
+        Err(err) => {
+            error!("Bulk operations failed: {:?}", err);
+            Err((Status::InternalServerError, "Failed to run bulk operations"))
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Real-time collaboration over WebSockets.
+//!
+//! A client connects to `/ws?user_id=<id>` and subscribes to the lists it
+//! wants to follow, where a list is the todos owned by one user: its own, or
+//! one that has been shared with it. Subscribers are sent every change to
+//! those lists, can send mutations that go through the same functions as the
+//! REST handlers, and are told who else is viewing each list whenever that
+//! changes.
+//!
+//! Messages in both directions are JSON objects tagged with a `type` field.
+
+use rocket::futures::{SinkExt, StreamExt};
+use rocket::data::{IoHandler, IoStream};
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+use rocket::State;
+use serde::{Serialize, Deserialize};
+use crate::attachments::{remove_todo_and_files, Storage};
+use crate::db::DbPool;
+use crate::etag::IfMatch;
+use crate::events::{load_events, EventBus, TodoEvent};
+use crate::sharing::{authorize, list_permission, Permission};
+use crate::sync::{current_token, SyncTodo};
+use crate::todos::{insert_todo, mark_completed, modify_todo};
+use diesel::prelude::*;
+use log::info;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+const CHANNEL_CAPACITY: usize = 64;
+
+// The viewers of a list after `connection` joined or left it
+#[derive(Debug, Clone)]
+struct PresenceUpdate {
+    connection: u64,
+    list_id: i32,
+    viewers: Vec<i32>,
+}
+
+// Which connections are viewing which lists
+pub struct Presence {
+    // list id -> connection id -> viewing user
+    viewers: Mutex<HashMap<i32, HashMap<u64, i32>>>,
+    next_connection: AtomicU64,
+    sender: broadcast::Sender<PresenceUpdate>,
+}
+
+impl Default for Presence {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Presence { viewers: Mutex::new(HashMap::new()), next_connection: AtomicU64::new(1), sender }
+    }
+}
+
+impl Presence {
+    fn connect(&self) -> u64 {
+        self.next_connection.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<PresenceUpdate> {
+        self.sender.subscribe()
+    }
+
+    // Each viewing user once, however many connections they have open
+    pub fn viewers(&self, list_id: i32) -> Vec<i32> {
+        let viewers = self.viewers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let users: BTreeSet<i32> = viewers.get(&list_id)
+            .map(|connections| connections.values().copied().collect())
+            .unwrap_or_default();
+        users.into_iter().collect()
+    }
+
+    fn update(&self, list_id: i32, connection: u64, change: impl FnOnce(&mut HashMap<u64, i32>)) {
+        {
+            let mut viewers = self.viewers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let connections = viewers.entry(list_id).or_default();
+            change(connections);
+            if connections.is_empty() {
+                viewers.remove(&list_id);
+            }
+        }
+
+        // Sending only fails when nobody is listening
+        let _ = self.sender.send(PresenceUpdate { connection, list_id, viewers: self.viewers(list_id) });
+    }
+
+    fn join(&self, list_id: i32, connection: u64, user_id: i32) {
+        self.update(list_id, connection, |connections| {
+            connections.insert(connection, user_id);
+        });
+    }
+
+    fn leave(&self, list_id: i32, connection: u64) {
+        self.update(list_id, connection, |connections| {
+            connections.remove(&connection);
+        });
+    }
+}
+
+// Messages a client sends; `request_id` is echoed back in the reply
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Subscribe { list_id: i32 },
+    Unsubscribe { list_id: i32 },
+    Create { request_id: Option<String>, list_id: i32, todo: SyncTodo },
+    Update { request_id: Option<String>, id: i32, version: Option<i32>, todo: SyncTodo },
+    Complete { request_id: Option<String>, id: i32, version: Option<i32> },
+    Delete { request_id: Option<String>, id: i32, version: Option<i32> },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Subscribed { list_id: i32, viewers: Vec<i32> },
+    Unsubscribed { list_id: i32 },
+    Presence { list_id: i32, viewers: Vec<i32> },
+    Change { list_id: i32, event: Box<TodoEvent> },
+    Ack { request_id: Option<String>, id: i32 },
+    Error { request_id: Option<String>, status: u16, message: String },
+}
+
+impl ServerMessage {
+    fn error(request_id: Option<String>, (status, message): (Status, &str)) -> Self {
+        ServerMessage::Error { request_id, status: status.code, message: message.to_string() }
+    }
+
+    fn to_message(&self) -> Message {
+        Message::Text(serde_json::to_string(self).expect("server messages always serialize"))
+    }
+}
+
+fn list_access(connection: &mut SqliteConnection, user_id: i32, list_id: i32) -> Result<Option<Permission>, (Status, &'static str)> {
+    list_permission(connection, user_id, list_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to check list access"))
+}
+
+// One client's connection
+struct Session<'r> {
+    id: u64,
+    user_id: i32,
+    pool: &'r DbPool,
+    events: &'r EventBus,
+    presence: &'r Presence,
+    storage: &'r Storage,
+    lists: HashSet<i32>,
+}
+
+impl Session<'_> {
+    // Apply a mutation, publishing the change to every subscriber
+    fn mutate(&self, mutation: impl FnOnce(&mut SqliteConnection) -> Result<i32, (Status, &'static str)>) -> Result<i32, (Status, &'static str)> {
+        let mut connection = self.pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+        let id = mutation(&mut connection)?;
+        self.events.publish(&mut connection);
+        Ok(id)
+    }
+
+    fn handle(&mut self, message: ClientMessage) -> ServerMessage {
+        match message {
+            ClientMessage::Subscribe { list_id } => {
+                let allowed = self.pool.get()
+                    .map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))
+                    .and_then(|mut connection| list_access(&mut connection, self.user_id, list_id));
+                match allowed {
+                    Ok(Some(_)) => {
+                        self.lists.insert(list_id);
+                        self.presence.join(list_id, self.id, self.user_id);
+                        ServerMessage::Subscribed { list_id, viewers: self.presence.viewers(list_id) }
+                    }
+                    Ok(None) => ServerMessage::error(None, (Status::Forbidden, "You cannot view this list")),
+                    Err(err) => ServerMessage::error(None, err),
+                }
+            }
+            ClientMessage::Unsubscribe { list_id } => {
+                if self.lists.remove(&list_id) {
+                    self.presence.leave(list_id, self.id);
+                }
+                ServerMessage::Unsubscribed { list_id }
+            }
+            ClientMessage::Create { request_id, list_id, todo } => {
+                let user_id = self.user_id;
+                let result = self.mutate(|connection| {
+                    if list_access(connection, user_id, list_id)? < Some(Permission::Edit) {
+                        return Err((Status::Forbidden, "You cannot change this list"));
+                    }
+                    insert_todo(connection, &todo.for_user(list_id)).map(|todo| todo.id)
+                });
+                reply(request_id, result)
+            }
+            ClientMessage::Update { request_id, id, version, todo } => {
+                let user_id = self.user_id;
+                let result = self.mutate(|connection| {
+                    let current = authorize(connection, user_id, id, Permission::Edit)?;
+                    modify_todo(connection, id, IfMatch(version), &todo.for_user(current.user_id)).map(|_| id)
+                });
+                reply(request_id, result)
+            }
+            ClientMessage::Complete { request_id, id, version } => {
+                let user_id = self.user_id;
+                let result = self.mutate(|connection| {
+                    authorize(connection, user_id, id, Permission::Edit)?;
+                    mark_completed(connection, id, IfMatch(version)).map(|_| id)
+                });
+                reply(request_id, result)
+            }
+            ClientMessage::Delete { request_id, id, version } => {
+                let user_id = self.user_id;
+                let store = self.storage.store.as_ref();
+                let result = self.mutate(|connection| {
+                    authorize(connection, user_id, id, Permission::Own)?;
+                    remove_todo_and_files(connection, store, id, IfMatch(version)).map(|_| id)
+                });
+                reply(request_id, result)
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        for list_id in self.lists.drain() {
+            self.presence.leave(list_id, self.id);
+        }
+    }
+
+    // A change to pass on, if it is to a list the session follows
+    fn change(&self, event: TodoEvent) -> Option<ServerMessage> {
+        self.lists.contains(&event.user_id).then(|| ServerMessage::Change { list_id: event.user_id, event: Box::new(event) })
+    }
+}
+
+fn reply(request_id: Option<String>, result: Result<i32, (Status, &'static str)>) -> ServerMessage {
+    match result {
+        Ok(id) => ServerMessage::Ack { request_id, id },
+        Err(err) => ServerMessage::error(request_id, err),
+    }
+}
+
+// A request asking to be upgraded to a WebSocket, identified by its handshake key
+pub struct WebSocketKey(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WebSocketKey {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        let upgrade = headers.get_one("Upgrade").map(|value| value.eq_ignore_ascii_case("websocket"));
+        match (upgrade, headers.get_one("Sec-WebSocket-Key")) {
+            (Some(true), Some(key)) => request::Outcome::Success(WebSocketKey(key.to_string())),
+            _ => request::Outcome::Error((Status::BadRequest, "Expected a WebSocket upgrade request")),
+        }
+    }
+}
+
+pub struct WebSocket<'r> {
+    key: WebSocketKey,
+    session: Session<'r>,
+}
+
+impl<'r> Responder<'r, 'r> for WebSocket<'r> {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'r> {
+        Response::build()
+            .raw_header("Sec-WebSocket-Accept", derive_accept_key(self.key.0.as_bytes()))
+            .upgrade("websocket", self.session)
+            .ok()
+    }
+}
+
+#[rocket::async_trait]
+impl IoHandler for Session<'_> {
+    async fn io(self: Pin<Box<Self>>, io: IoStream) -> io::Result<()> {
+        let mut session = Pin::into_inner(self);
+        let (mut sink, mut stream) = WebSocketStream::from_raw_socket(io, Role::Server, None).await.split();
+        let mut changes = session.events.subscribe();
+        let mut presence = session.presence.subscribe();
+        // The last change this session has heard about, to catch up from if it falls behind
+        let mut last_seen = session.pool.get().ok()
+            .and_then(|mut connection| current_token(&mut connection).ok())
+            .unwrap_or_default();
+
+        'session: loop {
+            let replies = select! {
+                message = stream.next() => match message {
+                    Some(Ok(Message::Text(text))) => match serde_json::from_str::<ClientMessage>(&text) {
+                        Ok(message) => vec![session.handle(message)],
+                        Err(_) => vec![ServerMessage::error(None, (Status::UnprocessableEntity, "Invalid message"))],
+                    },
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    // Pings are answered by the protocol layer
+                    Some(Ok(_)) => Vec::new(),
+                },
+                event = changes.recv() => match event {
+                    Ok(event) if event.id <= last_seen => Vec::new(),
+                    Ok(event) => {
+                        last_seen = event.id;
+                        session.change(event).into_iter().collect()
+                    }
+                    // Too far behind the channel, so catch up from the log instead
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("WebSocket session {} missed {} changes, replaying them", session.id, missed);
+                        let missed = session.pool.get().ok()
+                            .and_then(|mut connection| load_events(&mut connection, last_seen, Some(session.user_id)).ok())
+                            .unwrap_or_default();
+                        last_seen = missed.last().map_or(last_seen, |event| event.id);
+                        missed.into_iter().filter_map(|event| session.change(event)).collect()
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                update = presence.recv() => match update {
+                    // A session hears about its own joins in its subscribe reply
+                    Ok(update) if update.connection != session.id && session.lists.contains(&update.list_id) => vec![ServerMessage::Presence { list_id: update.list_id, viewers: update.viewers }],
+                    _ => Vec::new(),
+                },
+            };
+
+            for reply in replies {
+                if sink.send(reply.to_message()).await.is_err() {
+                    break 'session;
+                }
+            }
+        }
+
+        info!("WebSocket session {} for user {} closed", session.id, session.user_id);
+        session.disconnect();
+        Ok(())
+    }
+}
+
+// Open a collaboration session for a user
+#[get("/ws?<user_id>")]
+pub fn connect<'r>(pool: &'r State<DbPool>, events: &'r State<EventBus>, presence: &'r State<Presence>, storage: &'r State<Storage>, user_id: i32, key: WebSocketKey) -> WebSocket<'r> {
+    let id = presence.connect();
+    info!("Opening WebSocket session {} for user {}", id, user_id);
+
+    WebSocket {
+        key,
+        session: Session { id, user_id, pool, events, presence, storage, lists: HashSet::new() },
+    }
+}
@@ -0,0 +1,266 @@
+//! Comment threads on todos.
+//!
+//! Anyone who can see a todo can read and add to its thread; only a
+//! comment's author can edit or delete it. Bodies are stored as the Markdown
+//! the author wrote and returned alongside an HTML rendering, with any raw
+//! HTML in the source escaped rather than passed through. Users `@mentioned`
+//! in a new comment are notified if they can see the todo.
+
+use rocket::http::Status;
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::notifications::{mentions, notify, username, NewNotification, NotificationKind};
+use crate::schema::{comments, notifications, users};
+use crate::sharing::{authorize, permission, Permission};
+use crate::todos::TodoItem;
+use crate::user::PublicUser;
+use diesel::prelude::*;
+use log::{error, info};
+use chrono::{NaiveDateTime, Utc};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+
+const MAX_BODY_LENGTH: usize = 10_000;
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct Comment {
+    pub id: i32,
+    pub todo_id: i32,
+    pub user_id: i32,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+// Used both to add a comment and to replace its body
+#[derive(Deserialize, Debug)]
+pub struct NewComment {
+    pub user_id: i32,
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RenderedComment {
+    #[serde(flatten)]
+    pub comment: Comment,
+    pub author: PublicUser,
+    pub html: String,
+}
+
+// Pass `next` back as `after` to fetch the following page; it is absent on the last one
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CommentPage {
+    pub comments: Vec<RenderedComment>,
+    pub next: Option<i32>,
+}
+
+// Links that would run script when clicked
+fn is_unsafe_url(url: &str) -> bool {
+    let url = url.trim().to_ascii_lowercase();
+    ["javascript:", "vbscript:", "data:"].iter().any(|scheme| url.starts_with(scheme))
+}
+
+// Render a comment body to HTML, showing any HTML in it as text
+pub fn render_markdown(body: &str) -> String {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS;
+    let parser = Parser::new_ext(body, options).map(|event| match event {
+        Event::Html(text) | Event::InlineHtml(text) => Event::Text(text),
+        Event::Start(Tag::Link { link_type, dest_url, title, id }) if is_unsafe_url(&dest_url) => {
+            Event::Start(Tag::Link { link_type, dest_url: CowStr::Borrowed("#"), title, id })
+        }
+        Event::Start(Tag::Image { link_type, dest_url, title, id }) if is_unsafe_url(&dest_url) => {
+            Event::Start(Tag::Image { link_type, dest_url: CowStr::Borrowed("#"), title, id })
+        }
+        event => event,
+    });
+
+    let mut output = String::new();
+    html::push_html(&mut output, parser);
+    output
+}
+
+fn validate(comment: &NewComment) -> Result<(), (Status, &'static str)> {
+    if comment.body.trim().is_empty() {
+        return Err((Status::BadRequest, "Comment cannot be empty"));
+    }
+    if comment.body.chars().count() > MAX_BODY_LENGTH {
+        return Err((Status::BadRequest, "Comment is too long"));
+    }
+    Ok(())
+}
+
+// Attach each comment's author and rendered body
+fn render(connection: &mut SqliteConnection, comments: Vec<Comment>) -> QueryResult<Vec<RenderedComment>> {
+    let author_ids: Vec<i32> = comments.iter().map(|comment| comment.user_id).collect();
+    let authors: Vec<PublicUser> = users::table
+        .filter(users::dsl::id.eq_any(&author_ids))
+        .select((users::dsl::id, users::dsl::username))
+        .load(connection)?;
+
+    Ok(comments.into_iter().map(|comment| {
+        let author = authors.iter()
+            .find(|author| author.id == comment.user_id)
+            .cloned()
+            .unwrap_or(PublicUser { id: comment.user_id, username: String::new() });
+        let html = render_markdown(&comment.body);
+        RenderedComment { comment, author, html }
+    }).collect())
+}
+
+// Fetch a comment on a todo the user can still see, if they wrote it
+fn authored_comment(connection: &mut SqliteConnection, todo_id: i32, id: i32, user_id: i32) -> Result<Comment, (Status, &'static str)> {
+    authorize(connection, user_id, todo_id, Permission::View)?;
+
+    let comment: Comment = comments::table
+        .find(id)
+        .filter(comments::dsl::todo_id.eq(todo_id))
+        .first(connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch comment"))?
+        .ok_or((Status::NotFound, "Comment not found"))?;
+
+    if comment.user_id != user_id {
+        return Err((Status::Forbidden, "Only the author can change a comment"));
+    }
+
+    Ok(comment)
+}
+
+// Let the users a new comment mentions know about it, if they can see the todo
+fn notify_mentioned(connection: &mut SqliteConnection, todo: &TodoItem, comment_id: i32, comment: &NewComment) -> QueryResult<()> {
+    let names = mentions(&comment.body);
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let mentioned: Vec<i32> = users::table
+        .filter(users::dsl::username.eq_any(&names))
+        .filter(users::dsl::id.ne(comment.user_id))
+        .select(users::dsl::id)
+        .load(connection)?;
+    let author = username(connection, comment.user_id)?;
+
+    for user_id in mentioned {
+        if permission(connection, user_id, todo)?.is_none() {
+            continue;
+        }
+        notify(connection, NewNotification {
+            user_id,
+            kind: NotificationKind::Mentioned,
+            actor_id: Some(comment.user_id),
+            todo_id: Some(todo.id),
+            comment_id: Some(comment_id),
+            message: format!("{} mentioned you on \"{}\"", author, todo.title),
+        })?;
+    }
+
+    Ok(())
+}
+
+fn rendered_comment(connection: &mut SqliteConnection, id: i32) -> Result<RenderedComment, (Status, &'static str)> {
+    comments::table
+        .find(id)
+        .first::<Comment>(connection)
+        .and_then(|comment| render(connection, vec![comment]))
+        .map(|mut rendered| rendered.remove(0))
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch comment"))
+}
+
+// A page of a todo's comments, oldest first
+#[get("/todos/<id>/comments?<user_id>&<after>&<limit>")]
+pub fn get_comments(pool: &State<DbPool>, id: i32, user_id: i32, after: Option<i32>, limit: Option<i64>) -> Result<Json<CommentPage>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    authorize(&mut connection, user_id, id, Permission::View)?;
+
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    // One extra row tells us whether there is another page
+    let mut results: Vec<Comment> = comments::table
+        .filter(comments::dsl::todo_id.eq(id))
+        .filter(comments::dsl::id.gt(after.unwrap_or(0)))
+        .order(comments::dsl::id)
+        .limit(limit + 1)
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch comments"))?;
+
+    let next = if results.len() as i64 > limit {
+        results.truncate(limit as usize);
+        results.last().map(|comment| comment.id)
+    } else {
+        None
+    };
+
+    let comments = render(&mut connection, results)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch comments"))?;
+
+    Ok(Json(CommentPage { comments, next }))
+}
+
+// Add a comment to a todo the author can see
+#[post("/todos/<id>/comments", format = "json", data = "<new_comment>")]
+pub fn add_comment(pool: &State<DbPool>, id: i32, new_comment: Idempotent<'_, NewComment>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    new_comment.respond(&mut connection, |connection, new_comment| {
+        let new_comment = require_payload(new_comment)?;
+        validate(&new_comment)?;
+        let todo = authorize(connection, new_comment.user_id, id, Permission::View)?;
+
+        info!("User {} commenting on to-do item {}", new_comment.user_id, id);
+        let comment_id = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+            diesel::insert_into(comments::table)
+                .values((
+                    comments::dsl::todo_id.eq(id),
+                    comments::dsl::user_id.eq(new_comment.user_id),
+                    comments::dsl::body.eq(&new_comment.body),
+                ))
+                .execute(connection)?;
+
+            comments::table.select(comments::dsl::id).order(comments::dsl::id.desc()).first::<i32>(connection)
+        }).map_err(|_| (Status::InternalServerError, "Failed to add comment"))?;
+
+        if let Err(err) = notify_mentioned(connection, &todo, comment_id, &new_comment) {
+            error!("Failed to notify mentioned users: {:?}", err);
+        }
+
+        StoredResponse::json(Status::Ok, &rendered_comment(connection, comment_id)?)
+    })
+}
+
+// Replace the body of a comment; only its author can
+#[put("/todos/<id>/comments/<comment_id>", format = "json", data = "<edit>")]
+pub fn update_comment(pool: &State<DbPool>, id: i32, comment_id: i32, edit: Json<NewComment>) -> Result<Json<RenderedComment>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    validate(&edit)?;
+    authored_comment(&mut connection, id, comment_id, edit.user_id)?;
+
+    info!("Updating comment {} on to-do item {}", comment_id, id);
+    diesel::update(comments::table.find(comment_id))
+        .set((
+            comments::dsl::body.eq(&edit.body),
+            comments::dsl::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to update comment"))?;
+
+    rendered_comment(&mut connection, comment_id).map(Json)
+}
+
+// Delete a comment; only its author can
+#[delete("/todos/<id>/comments/<comment_id>?<user_id>")]
+pub fn delete_comment(pool: &State<DbPool>, id: i32, comment_id: i32, user_id: i32) -> Result<&'static str, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    authored_comment(&mut connection, id, comment_id, user_id)?;
+
+    info!("Deleting comment {} on to-do item {}", comment_id, id);
+    // Mentions in the comment go with it
+    connection.transaction::<_, diesel::result::Error, _>(|connection| {
+        diesel::delete(notifications::table.filter(notifications::dsl::comment_id.eq(comment_id))).execute(connection)?;
+        diesel::delete(comments::table.find(comment_id)).execute(connection)
+    }).map_err(|_| (Status::InternalServerError, "Failed to delete comment"))?;
+
+    Ok("Comment deleted successfully!")
+}
@@ -2,25 +2,32 @@ use diesel::prelude::*;
 use diesel::SqliteConnection;
 use dotenv::dotenv;
 use std::env;
-use diesel::r2d2::{self, Pool, ConnectionManager};
+use diesel::r2d2::{self, Pool, ConnectionManager, CustomizeConnection};
 
 pub type DbPool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
 
+// Set PRAGMA busy_timeout on every pooled connection so writers from other
+// connections (such as the webhook worker) are waited on instead of failing
+#[derive(Debug)]
+pub struct ConnectionOptions;
+
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, connection: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        diesel::sql_query("PRAGMA busy_timeout = 3000;")  // Retry for 3 seconds
+            .execute(connection)
+            .map(|_| ())
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
 pub fn establish_connection() -> Pool<ConnectionManager<SqliteConnection>> {
     dotenv().ok();
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let manager = ConnectionManager::<SqliteConnection>::new(database_url.clone());
     info!("Establishing database connection with {}", database_url);
 
-    let pool = r2d2::Pool::builder()
+    r2d2::Pool::builder()
+        .connection_customizer(Box::new(ConnectionOptions))
         .build(manager)
-        .expect("Failed to create pool.");
-
-    // Set PRAGMA busy_timeout to retry if the database is locked
-    let mut connection = pool.get().expect("Failed to get connection from pool.");
-    diesel::sql_query("PRAGMA busy_timeout = 3000;")  // Retry for 3 seconds
-        .execute(&mut connection)
-        .expect("Failed to set PRAGMA busy_timeout");
-
-    pool
+        .expect("Failed to create pool.")
 }
\ No newline at end of file
@@ -0,0 +1,118 @@
+//! Duplicating todos. A copy is always a fresh open todo owned by whoever
+//! made it, so it carries no status, assignee, shares, comments or time
+//! entries. Todos have no subtasks or tags to bring along.
+
+use rocket::http::Status;
+use rocket::State;
+use serde::Deserialize;
+use crate::attachments::{used_bytes, Attachment, Storage};
+use crate::db::DbPool;
+use crate::events::EventBus;
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::schema::attachments;
+use crate::sharing::{authorize, Permission};
+use crate::todos::{insert_todo, load_details, NewTodoItem};
+use diesel::prelude::*;
+use diesel::result::Error;
+use log::info;
+
+const COPY_SUFFIX: &str = " (copy)";
+
+fn default_true() -> bool {
+    true
+}
+
+// What to carry over to the copy. Attachments are shared with the original
+// rather than stored again, but still count against the copier's quota.
+#[derive(Deserialize, Debug)]
+pub struct DuplicateTodo {
+    pub user_id: i32,
+    pub title: Option<String>,
+    #[serde(default = "default_true")]
+    pub description: bool,
+    #[serde(default)]
+    pub attachments: bool,
+}
+
+// Copy a todo the user can see into a new open todo at the end of their own list
+#[post("/todos/<id>/duplicate", format = "json", data = "<request>")]
+pub fn duplicate_todo(pool: &State<DbPool>, events: &State<EventBus>, storage: &State<Storage>, id: i32, request: Idempotent<'_, DuplicateTodo>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let response = request.respond(&mut connection, |connection, request| {
+        let request = require_payload(request)?;
+        let original = authorize(connection, request.user_id, id, Permission::View)?;
+
+        let files: Vec<Attachment> = if request.attachments {
+            attachments::table
+                .filter(attachments::dsl::todo_id.eq(id))
+                .order(attachments::dsl::id)
+                .load(connection)
+                .map_err(|_| (Status::InternalServerError, "Failed to fetch attachments"))?
+        } else {
+            Vec::new()
+        };
+
+        let size: i64 = files.iter().map(|file| file.size).sum();
+        if size > 0 {
+            let used = used_bytes(connection, request.user_id)
+                .map_err(|_| (Status::InternalServerError, "Failed to check storage usage"))?;
+            if used + size > storage.quota_bytes {
+                return Err((Status::PayloadTooLarge, "Storage quota exceeded"));
+            }
+        }
+
+        let title = request.title.clone().unwrap_or_else(|| format!("{}{}", original.title, COPY_SUFFIX));
+        let copy = NewTodoItem {
+            title: &title,
+            description: original.description.as_deref().filter(|_| request.description),
+            priority: Some(original.priority),
+            due_date: original.due_date,
+            completed: false,
+            user_id: request.user_id,
+            due_at: original.due_at,
+            position: None,
+            estimate_minutes: original.estimate_minutes,
+            defer_until: None,
+        };
+
+        info!("Duplicating to-do item {} for user {}", id, request.user_id);
+        let mut failure = None;
+        let todo = connection.transaction::<_, Error, _>(|connection| {
+            let todo = match insert_todo(connection, &copy) {
+                Ok(todo) => todo,
+                Err(err) => {
+                    failure = Some(err);
+                    return Err(Error::RollbackTransaction);
+                }
+            };
+
+            for file in &files {
+                diesel::insert_into(attachments::table)
+                    .values((
+                        attachments::dsl::todo_id.eq(todo.id),
+                        attachments::dsl::user_id.eq(request.user_id),
+                        attachments::dsl::filename.eq(&file.filename),
+                        attachments::dsl::content_type.eq(&file.content_type),
+                        attachments::dsl::size.eq(file.size),
+                        attachments::dsl::digest.eq(&file.digest),
+                    ))
+                    .execute(connection)?;
+            }
+            Ok(todo)
+        });
+
+        let mut todo = match (todo, failure) {
+            (Ok(todo), _) => todo,
+            (Err(_), Some(err)) => return Err(err),
+            (Err(_), None) => return Err((Status::InternalServerError, "Failed to duplicate todo")),
+        };
+        load_details(connection, std::slice::from_mut(&mut todo))
+            .map_err(|_| (Status::InternalServerError, "Failed to fetch todo"))?;
+
+        StoredResponse::json(Status::Ok, &todo)
+    });
+
+    events.publish(&mut connection);
+    response
+}
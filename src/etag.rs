@@ -0,0 +1,98 @@
+//! Optimistic concurrency for todos.
+//!
+//! Every write bumps a todo's `version`, which is handed out as a strong ETag
+//! (`"3"`). Clients echo it back in `If-Match`; a write made against an older
+//! version is refused with 412 instead of silently overwriting the newer one.
+//! Requests without `If-Match` are still accepted. If-Match needs a strong
+//! comparison, so a weak tag (`W/"3"`) never matches.
+
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, Responder, Response};
+use crate::schema::todos;
+use diesel::prelude::*;
+
+pub const PRECONDITION_FAILED: &str = "Todo has been modified since it was fetched";
+pub const INVALID_IF_MATCH: &str = "If-Match must be a todo ETag or *";
+pub const WEAK_IF_MATCH: &str = "If-Match cannot use a weak ETag";
+
+// The version a client expects to be overwriting; `None` when any version will do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IfMatch(pub Option<i32>);
+
+impl IfMatch {
+    pub fn matches(&self, version: i32) -> bool {
+        self.0.is_none_or(|expected| expected == version)
+    }
+}
+
+// Parse `"3"` or `*`; a weak `W/"3"` fails the precondition outright
+fn parse_if_match(value: &str) -> Result<IfMatch, (Status, &'static str)> {
+    let value = value.trim();
+    if value == "*" {
+        return Ok(IfMatch(None));
+    }
+    if value.starts_with("W/") {
+        return Err((Status::PreconditionFailed, WEAK_IF_MATCH));
+    }
+
+    value.strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .and_then(|version| version.parse().ok())
+        .map(|version| IfMatch(Some(version)))
+        .ok_or((Status::BadRequest, INVALID_IF_MATCH))
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match request.headers().get_one("If-Match") {
+            None => request::Outcome::Success(IfMatch(None)),
+            Some(value) => match parse_if_match(value) {
+                Ok(if_match) => request::Outcome::Success(if_match),
+                Err(error) => request::Outcome::Error(error),
+            },
+        }
+    }
+}
+
+pub fn etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+// A response carrying the ETag of the todo version it describes
+#[derive(Debug)]
+pub struct Tagged<R> {
+    pub version: i32,
+    pub inner: R,
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Tagged<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        Response::build_from(self.inner.respond_to(request)?)
+            .raw_header("ETag", etag(self.version))
+            .ok()
+    }
+}
+
+// The current version of a todo, or 404 if it does not exist
+pub fn current_version(connection: &mut SqliteConnection, id: i32) -> Result<i32, (Status, &'static str)> {
+    todos::table
+        .find(id)
+        .select(todos::dsl::version)
+        .first(connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todo"))?
+        .ok_or((Status::NotFound, "Todo item not found"))
+}
+
+// Refuse the write with 412 when the client's copy is out of date
+pub fn check_version(if_match: IfMatch, version: i32) -> Result<(), (Status, &'static str)> {
+    if if_match.matches(version) {
+        Ok(())
+    } else {
+        Err((Status::PreconditionFailed, PRECONDITION_FAILED))
+    }
+}
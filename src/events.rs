@@ -0,0 +1,204 @@
+//! Live todo change notifications over Server-Sent Events.
+//!
+//! Mutating handlers call `EventBus::publish` once their write has gone
+//! through. Publishing reads whatever has been added to the change log since
+//! the last publish and broadcasts it, so events go out in change-log order
+//! and each event id is the change id. A client that reconnects with
+//! `Last-Event-ID` is first sent the changes it missed from the log.
+
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::stream::{Event, EventStream};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+use rocket::{Shutdown, State};
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::schema::{todo_changes, todos};
+use crate::sharing::grantees;
+use crate::sync::{current_token, visible_changes, ChangeKind, TodoChange};
+use crate::todos::{load_details, TodoItem};
+use diesel::prelude::*;
+use log::info;
+use std::sync::Mutex;
+
+// Events a slow subscriber can fall behind by before it has to catch up from the log
+const CHANNEL_CAPACITY: usize = 256;
+
+// `todo` is the todo as it is now, or `None` once it has been deleted.
+// `viewers` are the users who could see it when the event was loaded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TodoEvent {
+    pub id: i32,
+    pub user_id: i32,
+    pub kind: ChangeKind,
+    pub todo_id: i32,
+    pub todo: Option<TodoItem>,
+    #[serde(skip)]
+    pub viewers: Vec<i32>,
+}
+
+impl TodoEvent {
+    fn to_sse(&self) -> Event {
+        Event::json(self).id(self.id.to_string()).event(self.kind.as_str())
+    }
+
+    // Whether a user's stream carries this event, by the same rule as `visible_changes`
+    fn reaches(&self, user_id: i32) -> bool {
+        match self.kind {
+            ChangeKind::Deleted => self.user_id == user_id,
+            _ => self.viewers.contains(&user_id),
+        }
+    }
+}
+
+pub struct EventBus {
+    sender: broadcast::Sender<TodoEvent>,
+    published: Mutex<i32>,
+}
+
+// Load the changes after `since` as events, optionally only those one user can see
+pub(crate) fn load_events(connection: &mut SqliteConnection, since: i32, user_id: Option<i32>) -> QueryResult<Vec<TodoEvent>> {
+    let mut query = todo_changes::table
+        .filter(todo_changes::dsl::id.gt(since))
+        .order(todo_changes::dsl::id)
+        .into_boxed();
+    if let Some(user_id) = user_id {
+        query = query.filter(visible_changes(user_id));
+    }
+    let changes: Vec<TodoChange> = query.load(connection)?;
+
+    let ids: Vec<i32> = changes.iter().map(|change| change.todo_id).collect();
+    let mut current: Vec<TodoItem> = todos::table
+        .filter(todos::dsl::id.eq_any(&ids))
+        .load(connection)?;
+    load_details(connection, &mut current)?;
+
+    changes.into_iter()
+        .map(|change| {
+            let todo = match change.kind {
+                ChangeKind::Deleted => None,
+                _ => current.iter().find(|todo| todo.id == change.todo_id).cloned(),
+            };
+            let viewers = match &todo {
+                Some(todo) => {
+                    let mut viewers = grantees(connection, todo.id, todo.user_id)?;
+                    viewers.push(todo.user_id);
+                    viewers
+                }
+                None => Vec::new(),
+            };
+            Ok(TodoEvent {
+                id: change.id,
+                user_id: change.user_id,
+                kind: change.kind,
+                todo_id: change.todo_id,
+                todo,
+                viewers,
+            })
+        })
+        .collect()
+}
+
+impl EventBus {
+    // Start publishing from the current end of the change log
+    pub fn new(pool: &DbPool) -> Self {
+        let mut connection = pool.get().expect("Failed to get connection from pool.");
+        let token = current_token(&mut connection).expect("Failed to read the change log");
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        EventBus { sender, published: Mutex::new(token) }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TodoEvent> {
+        self.sender.subscribe()
+    }
+
+    // Broadcast every change logged since the last publish. Failing to notify
+    // listeners never fails the write that triggered it.
+    pub fn publish(&self, connection: &mut SqliteConnection) {
+        let mut published = self.published.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let events = match load_events(connection, *published, None) {
+            Ok(events) => events,
+            Err(err) => {
+                error!("Failed to load todo changes to publish: {:?}", err);
+                return;
+            }
+        };
+
+        for event in events {
+            *published = event.id;
+            // Sending only fails when nobody is listening
+            let _ = self.sender.send(event);
+        }
+    }
+}
+
+// The id of the last event a reconnecting client received
+pub struct LastEventId(pub Option<i32>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match request.headers().get_one("Last-Event-ID") {
+            None => request::Outcome::Success(LastEventId(None)),
+            Some(value) => match value.trim().parse() {
+                Ok(id) => request::Outcome::Success(LastEventId(Some(id))),
+                Err(_) => request::Outcome::Error((Status::BadRequest, "Last-Event-ID must be an event id")),
+            },
+        }
+    }
+}
+
+// Stream changes to the todos a user can see as they happen, after any the client missed
+#[get("/events?<user_id>")]
+pub fn get_events(pool: &State<DbPool>, events: &State<EventBus>, user_id: i32, last_event_id: LastEventId, mut shutdown: Shutdown) -> Result<EventStream![], (Status, &'static str)> {
+    info!("Streaming events for user {} after {:?}", user_id, last_event_id.0);
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    // Subscribe before reading the backlog so nothing published in between is lost
+    let mut receiver = events.subscribe();
+    let mut last_sent = match last_event_id.0 {
+        Some(id) => id,
+        None => current_token(&mut connection).map_err(|_| (Status::InternalServerError, "Failed to fetch changes"))?,
+    };
+    let backlog = load_events(&mut connection, last_sent, Some(user_id))
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch changes"))?;
+    drop(connection);
+
+    let pool = pool.inner().clone();
+    Ok(EventStream! {
+        for event in backlog {
+            last_sent = event.id;
+            yield event.to_sse();
+        }
+
+        loop {
+            let event = select! {
+                received = receiver.recv() => received,
+                _ = &mut shutdown => break,
+            };
+
+            match event {
+                Ok(event) if event.id > last_sent && event.reaches(user_id) => {
+                    last_sent = event.id;
+                    yield event.to_sse();
+                }
+                Ok(_) => continue,
+                // Too far behind the channel, so catch up from the log instead
+                Err(RecvError::Lagged(_)) => {
+                    let missed = pool.get().ok()
+                        .and_then(|mut connection| load_events(&mut connection, last_sent, Some(user_id)).ok())
+                        .unwrap_or_default();
+                    for event in missed {
+                        last_sent = event.id;
+                        yield event.to_sse();
+                    }
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
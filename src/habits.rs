@@ -0,0 +1,433 @@
+//! Habits.
+//!
+//! A habit is something a user means to do `target` times a day or a week,
+//! rather than a todo that gets done once. Check-ins are recorded against the
+//! user's local calendar date, and a period (a day, or a week starting on the
+//! user's `week_start`) is met once its check-ins reach the target.
+//!
+//! Streaks count consecutive met periods. The current period only adds to
+//! the current streak once it is met; until then the streak running up to it
+//! still stands.
+
+use rocket::http::Status;
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::schema::{habit_checkins, habits};
+use crate::stats::parse_date;
+use crate::user::load_user_settings;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use log::info;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Weekday};
+use std::collections::BTreeMap;
+
+// History covers the last 30 days unless the client asks otherwise, and at most two years
+const DEFAULT_HISTORY_DAYS: i64 = 30;
+const MAX_HISTORY_DAYS: i64 = 731;
+// The heatmap shows a year of full weeks by default, and at most two
+const DEFAULT_HEATMAP_WEEKS: i64 = 52;
+const MAX_HEATMAP_WEEKS: i64 = 104;
+// Heatmap days are shaded from 0 (no check-ins) to this level
+const HEATMAP_LEVELS: i32 = 4;
+// Most check-ins a single day can hold
+const MAX_DAILY_CHECKINS: i32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[diesel(sql_type = Text)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+}
+
+impl Frequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Daily => "daily",
+            Frequency::Weekly => "weekly",
+        }
+    }
+
+    // The first day of the period a date falls in
+    pub fn period_start(&self, date: NaiveDate, week_start: Weekday) -> NaiveDate {
+        match self {
+            Frequency::Daily => date,
+            Frequency::Weekly => date - Duration::days(date.weekday().days_since(week_start) as i64),
+        }
+    }
+
+    pub fn period_length(&self) -> Duration {
+        match self {
+            Frequency::Daily => Duration::days(1),
+            Frequency::Weekly => Duration::days(7),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for Frequency {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.as_str());
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for Frequency {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "daily" => Ok(Frequency::Daily),
+            "weekly" => Ok(Frequency::Weekly),
+            other => Err(format!("Unknown habit frequency: {}", other).into()),
+        }
+    }
+}
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct Habit {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub frequency: Frequency,
+    pub target: i32,  // Check-ins needed per period
+    pub created_at: NaiveDateTime,
+}
+
+fn default_count() -> i32 {
+    1
+}
+
+#[derive(Insertable, Deserialize, Debug)]
+#[diesel(table_name = habits)]
+pub struct NewHabit {
+    pub user_id: i32,
+    pub name: String,
+    pub frequency: Frequency,
+    #[serde(default = "default_count")]
+    pub target: i32,
+}
+
+// Check-ins on one local date
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckIn {
+    pub date: NaiveDate,
+    pub count: i32,
+}
+
+// Check in once or more; `date` defaults to today in the user's timezone
+#[derive(Deserialize, Debug)]
+pub struct NewCheckIn {
+    pub user_id: i32,
+    pub date: Option<NaiveDate>,
+    #[serde(default = "default_count")]
+    pub count: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Streaks {
+    pub current: i32,
+    pub longest: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HabitSummary {
+    #[serde(flatten)]
+    pub habit: Habit,
+    pub period_start: NaiveDate,
+    pub period_count: i32,  // Check-ins so far in the current period
+    pub streaks: Streaks,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct HabitPeriod {
+    pub start: NaiveDate,
+    pub count: i32,
+    pub met: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HeatmapDay {
+    pub date: NaiveDate,
+    pub count: i32,
+    pub level: i32,
+}
+
+// One entry per day from the start of the first week through today
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Heatmap {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub max: i32,
+    pub days: Vec<HeatmapDay>,
+}
+
+// Check-in totals keyed by the start of their period
+fn period_counts(frequency: Frequency, checkins: &[CheckIn], week_start: Weekday) -> BTreeMap<NaiveDate, i32> {
+    let mut counts = BTreeMap::new();
+    for checkin in checkins {
+        *counts.entry(frequency.period_start(checkin.date, week_start)).or_insert(0) += checkin.count;
+    }
+    counts
+}
+
+// Current and longest runs of met periods as of `today`
+pub fn streaks(frequency: Frequency, target: i32, checkins: &[CheckIn], today: NaiveDate, week_start: Weekday) -> Streaks {
+    let counts = period_counts(frequency, checkins, week_start);
+    let met = |period: &NaiveDate| counts.get(period).is_some_and(|count| *count >= target);
+    let step = frequency.period_length();
+
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for period in counts.keys().filter(|period| met(period)) {
+        run = if previous.is_some_and(|previous| previous + step == *period) { run + 1 } else { 1 };
+        longest = longest.max(run);
+        previous = Some(*period);
+    }
+
+    let mut period = frequency.period_start(today, week_start);
+    if !met(&period) {
+        period -= step;
+    }
+    let mut current = 0;
+    while met(&period) {
+        current += 1;
+        period -= step;
+    }
+
+    Streaks { current, longest }
+}
+
+fn owned_habit(connection: &mut SqliteConnection, id: i32, user_id: i32) -> Result<Habit, (Status, &'static str)> {
+    habits::table
+        .find(id)
+        .filter(habits::dsl::user_id.eq(user_id))
+        .first(connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch habit"))?
+        .ok_or((Status::NotFound, "Habit not found"))
+}
+
+fn load_checkins(connection: &mut SqliteConnection, habit_id: i32) -> QueryResult<Vec<CheckIn>> {
+    habit_checkins::table
+        .filter(habit_checkins::dsl::habit_id.eq(habit_id))
+        .order(habit_checkins::dsl::date)
+        .select((habit_checkins::dsl::date, habit_checkins::dsl::count))
+        .load(connection)
+}
+
+fn summarize(connection: &mut SqliteConnection, habit: Habit) -> Result<HabitSummary, (Status, &'static str)> {
+    let settings = load_user_settings(connection, habit.user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))?;
+    let checkins = load_checkins(connection, habit.id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch check-ins"))?;
+
+    let week_start = settings.week_start();
+    let period_start = habit.frequency.period_start(settings.today(), week_start);
+    let period_count = checkins.iter().filter(|checkin| checkin.date >= period_start).map(|checkin| checkin.count).sum();
+    let streaks = streaks(habit.frequency, habit.target, &checkins, settings.today(), week_start);
+
+    Ok(HabitSummary { habit, period_start, period_count, streaks })
+}
+
+#[post("/habits", format = "json", data = "<new_habit>")]
+pub fn add_habit(pool: &State<DbPool>, new_habit: Idempotent<'_, NewHabit>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    new_habit.respond(&mut connection, |connection, new_habit| {
+        let new_habit = require_payload(new_habit)?;
+        if new_habit.name.trim().is_empty() {
+            return Err((Status::BadRequest, "Habit name cannot be empty"));
+        }
+        if new_habit.target < 1 {
+            return Err((Status::BadRequest, "Target must be at least 1"));
+        }
+
+        info!("Adding habit {:?} for user {}", new_habit.name, new_habit.user_id);
+        let habit: Habit = connection.transaction::<_, Error, _>(|connection| {
+            diesel::insert_into(habits::table).values(&*new_habit).execute(connection)?;
+            habits::table.order(habits::dsl::id.desc()).first(connection)
+        }).map_err(|_| (Status::InternalServerError, "Failed to add habit"))?;
+
+        StoredResponse::json(Status::Ok, &summarize(connection, habit)?)
+    })
+}
+
+#[get("/habits?<user_id>")]
+pub fn get_habits(pool: &State<DbPool>, user_id: i32) -> Result<Json<Vec<HabitSummary>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let results: Vec<Habit> = habits::table
+        .filter(habits::dsl::user_id.eq(user_id))
+        .order(habits::dsl::name)
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch habits"))?;
+
+    results.into_iter().map(|habit| summarize(&mut connection, habit)).collect::<Result<_, _>>().map(Json)
+}
+
+#[get("/habits/<id>?<user_id>")]
+pub fn get_habit(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<Json<HabitSummary>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    let habit = owned_habit(&mut connection, id, user_id)?;
+    summarize(&mut connection, habit).map(Json)
+}
+
+#[delete("/habits/<id>?<user_id>")]
+pub fn delete_habit(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<&'static str, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    owned_habit(&mut connection, id, user_id)?;
+
+    connection.transaction::<_, Error, _>(|connection| {
+        diesel::delete(habit_checkins::table.filter(habit_checkins::dsl::habit_id.eq(id))).execute(connection)?;
+        diesel::delete(habits::table.find(id)).execute(connection)
+    }).map_err(|_| (Status::InternalServerError, "Failed to delete habit"))?;
+
+    Ok("Habit deleted successfully!")
+}
+
+// Record check-ins for a day, adding to any already made that day
+#[post("/habits/<id>/checkins", format = "json", data = "<checkin>")]
+pub fn check_in(pool: &State<DbPool>, id: i32, checkin: Idempotent<'_, NewCheckIn>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    checkin.respond(&mut connection, |connection, checkin| {
+        let checkin = require_payload(checkin)?;
+        let habit = owned_habit(connection, id, checkin.user_id)?;
+
+        if !(1..=MAX_DAILY_CHECKINS).contains(&checkin.count) {
+            return Err((Status::BadRequest, "Count must be between 1 and 1000"));
+        }
+        let today = load_user_settings(connection, checkin.user_id)
+            .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))?
+            .today();
+        let date = checkin.date.unwrap_or(today);
+        if date > today {
+            return Err((Status::BadRequest, "Cannot check in on a future date"));
+        }
+
+        info!("Checking in {} time(s) on habit {} for {}", checkin.count, id, date);
+        let recorded = connection.transaction::<_, Error, _>(|connection| {
+            let existing: i32 = habit_checkins::table
+                .filter(habit_checkins::dsl::habit_id.eq(id))
+                .filter(habit_checkins::dsl::date.eq(date))
+                .select(habit_checkins::dsl::count)
+                .first(connection)
+                .optional()?
+                .unwrap_or(0);
+            if existing + checkin.count > MAX_DAILY_CHECKINS {
+                return Ok(false);
+            }
+
+            diesel::insert_into(habit_checkins::table)
+                .values((
+                    habit_checkins::dsl::habit_id.eq(id),
+                    habit_checkins::dsl::date.eq(date),
+                    habit_checkins::dsl::count.eq(checkin.count),
+                ))
+                .on_conflict((habit_checkins::dsl::habit_id, habit_checkins::dsl::date))
+                .do_update()
+                .set(habit_checkins::dsl::count.eq(habit_checkins::dsl::count + checkin.count))
+                .execute(connection)?;
+            Ok(true)
+        }).map_err(|_| (Status::InternalServerError, "Failed to check in"))?;
+        if !recorded {
+            return Err((Status::BadRequest, "A day can hold at most 1000 check-ins"));
+        }
+
+        StoredResponse::json(Status::Ok, &summarize(connection, habit)?)
+    })
+}
+
+// Undo all of a day's check-ins
+#[delete("/habits/<id>/checkins/<date>?<user_id>")]
+pub fn delete_checkin(pool: &State<DbPool>, id: i32, date: String, user_id: i32) -> Result<Json<HabitSummary>, (Status, &'static str)> {
+    let date = parse_date(Some(date))?.ok_or((Status::BadRequest, "Dates must be formatted as YYYY-MM-DD"))?;
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    let habit = owned_habit(&mut connection, id, user_id)?;
+
+    let deleted = diesel::delete(
+        habit_checkins::table
+            .filter(habit_checkins::dsl::habit_id.eq(id))
+            .filter(habit_checkins::dsl::date.eq(date)),
+    )
+    .execute(&mut connection)
+    .map_err(|_| (Status::InternalServerError, "Failed to delete check-in"))?;
+    if deleted == 0 {
+        return Err((Status::NotFound, "Check-in not found"));
+    }
+
+    summarize(&mut connection, habit).map(Json)
+}
+
+// Every period overlapping `from` through `to`, oldest first, with whether it was met
+#[get("/habits/<id>/history?<user_id>&<from>&<to>")]
+pub fn get_habit_history(pool: &State<DbPool>, id: i32, user_id: i32, from: Option<String>, to: Option<String>) -> Result<Json<Vec<HabitPeriod>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    let habit = owned_habit(&mut connection, id, user_id)?;
+    let settings = load_user_settings(&mut connection, user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))?;
+
+    let to = parse_date(to)?.unwrap_or(settings.today());
+    let from = parse_date(from)?.unwrap_or(to - Duration::days(DEFAULT_HISTORY_DAYS - 1));
+    if from > to {
+        return Err((Status::BadRequest, "From date must not be after to date"));
+    }
+    if (to - from).num_days() >= MAX_HISTORY_DAYS {
+        return Err((Status::BadRequest, "History can cover at most 731 days"));
+    }
+
+    let checkins = load_checkins(&mut connection, id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch check-ins"))?;
+    let counts = period_counts(habit.frequency, &checkins, settings.week_start());
+
+    let mut periods = Vec::new();
+    let mut start = habit.frequency.period_start(from, settings.week_start());
+    while start <= to {
+        let count = counts.get(&start).copied().unwrap_or(0);
+        periods.push(HabitPeriod { start, count, met: count >= habit.target });
+        start += habit.frequency.period_length();
+    }
+
+    Ok(Json(periods))
+}
+
+// Daily check-in counts for a calendar grid, shaded relative to the busiest day
+#[get("/habits/<id>/heatmap?<user_id>&<weeks>")]
+pub fn get_habit_heatmap(pool: &State<DbPool>, id: i32, user_id: i32, weeks: Option<i64>) -> Result<Json<Heatmap>, (Status, &'static str)> {
+    let weeks = weeks.unwrap_or(DEFAULT_HEATMAP_WEEKS);
+    if !(1..=MAX_HEATMAP_WEEKS).contains(&weeks) {
+        return Err((Status::BadRequest, "Weeks must be between 1 and 104"));
+    }
+
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    owned_habit(&mut connection, id, user_id)?;
+    let settings = load_user_settings(&mut connection, user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))?;
+
+    let to = settings.today();
+    let from = Frequency::Weekly.period_start(to, settings.week_start()) - Duration::weeks(weeks - 1);
+
+    let checkins: Vec<CheckIn> = habit_checkins::table
+        .filter(habit_checkins::dsl::habit_id.eq(id))
+        .filter(habit_checkins::dsl::date.between(from, to))
+        .select((habit_checkins::dsl::date, habit_checkins::dsl::count))
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch check-ins"))?;
+    let max = checkins.iter().map(|checkin| checkin.count).max().unwrap_or(0);
+
+    let days = from.iter_days().take_while(|date| *date <= to).map(|date| {
+        let count = checkins.iter().find(|checkin| checkin.date == date).map_or(0, |checkin| checkin.count);
+        // Any check-in at all shows at least the lightest shade
+        let level = if count == 0 { 0 } else { (count * HEATMAP_LEVELS + max - 1) / max };
+        HeatmapDay { date, count, level }
+    }).collect();
+
+    Ok(Json(Heatmap { from, to, max, days }))
+}
@@ -1,11 +1,33 @@
 use diesel::prelude::*;
 use diesel::SqliteConnection;
+use rocket::http::{ContentType, Status};
 use rocket::local::blocking::Client;
-use rocket::{self, routes};
-use crate::todos::{get_todos, add_todo, delete_todo, update_todo, complete_todo, search_todos};
-use crate::user::{create_user, get_user_by_id};
+use rocket::{self, routes, Build, Rocket};
+use crate::todos::{get_todos, add_todo, delete_todo, update_todo, complete_todo, search_todos, move_todo, get_todo};
+use crate::user::{create_user, get_user_by_id, get_user_settings, update_user_settings};
+use crate::views::{get_today, get_overdue, get_upcoming, get_someday};
+use crate::stats::get_stats;
+use crate::bulk::bulk_todos;
+use crate::sync::{get_changes, push_changes};
+use crate::events::{get_events, EventBus};
+use crate::collab::{connect, Presence};
+use crate::sharing::{add_share, get_shares, delete_share};
+use crate::assignees::{assign_todo, unassign_todo, get_assigned};
+use crate::comments::{get_comments, add_comment, update_comment, delete_comment};
+use crate::notifications::{get_notifications, mark_read, mark_all_read, get_notification_preferences, update_notification_preferences};
+use crate::attachments::{upload_attachment, get_attachments, download_attachment, delete_attachment, get_storage_usage, Storage};
+use crate::time_tracking::{start_timer, get_timer, stop_timer, add_time_entry, get_time_entries, delete_time_entry, get_timesheet};
+use crate::templates::{add_template, save_as_template, get_templates, get_template, delete_template, instantiate_template};
+use crate::duplicate::duplicate_todo;
+use crate::snooze::{snooze_todo, unsnooze_todo};
+use crate::rules::{add_rule, get_rules, get_rule, update_rule, delete_rule, dry_run_rule};
+use crate::habits::{add_habit, get_habits, get_habit, delete_habit, check_in, delete_checkin, get_habit_history, get_habit_heatmap};
+use crate::webhooks::{add_webhook, get_webhooks, delete_webhook, enable_webhook, get_deliveries, WebhookWorker};
+use crate::statuses::{get_statuses, add_status, delete_status, transition_todo, get_board};
 use diesel::sql_query;
 use diesel::r2d2::{self, ConnectionManager};
+use crate::db::ConnectionOptions;
+use serde_json::json;
 
 type DbPool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
 
@@ -14,29 +36,57 @@ pub fn establish_test_connection() -> DbPool{
     let manager = ConnectionManager::<SqliteConnection>::new(database_url.clone());
     info!("Establishing database connection with {}", database_url);
 
-    let pool = r2d2::Pool::builder()
+    r2d2::Pool::builder()
+        .connection_customizer(Box::new(ConnectionOptions))
         .build(manager)
-        .expect("Failed to create pool.");
-
-    // Set PRAGMA busy_timeout to retry if the database is locked
-    let mut connection = pool.get().expect("Failed to get connection from pool.");
-    diesel::sql_query("PRAGMA busy_timeout = 3000;")  // Retry for 3 seconds
-        .execute(&mut connection)
-        .expect("Failed to set PRAGMA busy_timeout");
-
-    pool
+        .expect("Failed to create pool.")
 }
 
-pub fn setup_rocket() -> Client {
+// The app as the tests run it, against the test database
+pub fn build_rocket() -> Rocket<Build> {
     let manager = ConnectionManager::<SqliteConnection>::new("test.sqlite");
     let pool = r2d2::Pool::builder()
+        .connection_customizer(Box::new(ConnectionOptions))
         .build(manager)
         .expect("Failed to create pool.");
 
-    let rocket = rocket::build()
+    rocket::build()
+        .attach(WebhookWorker::fairing())
+        .attach(Storage::fairing())
+        .manage(EventBus::new(&pool))
+        .manage(Presence::default())
         .manage(pool)
-        .mount("/", routes![get_todos, add_todo, delete_todo, update_todo, complete_todo, create_user, get_user_by_id, search_todos]);
-    Client::tracked(rocket).expect("valid rocket instance")
+        .mount("/", routes![get_todos, get_todo, add_todo, delete_todo, update_todo, complete_todo, create_user, get_user_by_id, get_user_settings, update_user_settings, search_todos, move_todo, bulk_todos, get_changes, push_changes, get_events, connect, get_today, get_overdue, get_upcoming, get_someday, get_stats, get_statuses, add_status, delete_status, transition_todo, get_board, add_webhook, get_webhooks, delete_webhook, enable_webhook, get_deliveries, add_share, get_shares, delete_share, assign_todo, unassign_todo, get_assigned, get_comments, add_comment, update_comment, delete_comment, get_notifications, mark_read, mark_all_read, get_notification_preferences, update_notification_preferences, upload_attachment, get_attachments, download_attachment, delete_attachment, get_storage_usage, start_timer, get_timer, stop_timer, add_time_entry, get_time_entries, delete_time_entry, get_timesheet, add_template, save_as_template, get_templates, get_template, delete_template, instantiate_template, duplicate_todo, snooze_todo, unsnooze_todo, add_rule, get_rules, get_rule, update_rule, delete_rule, dry_run_rule, add_habit, get_habits, get_habit, delete_habit, check_in, delete_checkin, get_habit_history, get_habit_heatmap])
+}
+
+pub fn setup_rocket() -> Client {
+    Client::tracked(build_rocket()).expect("valid rocket instance")
+}
+
+// A client against a freshly cleaned and seeded test database
+pub fn setup_seeded() -> Client {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    setup_rocket()
+}
+
+// A seeded client with a second user, who gets id 2
+pub fn setup_with_user(username: &str) -> Client {
+    let client = setup_seeded();
+    let status = client.post("/users")
+        .header(ContentType::JSON)
+        .body(json!({ "username": username, "password_hash": "hashed_password" }).to_string())
+        .dispatch()
+        .status();
+    assert_eq!(status, Status::Ok);
+    client
+}
+
+// A seeded client with a second user named "friend"
+pub fn setup_with_friend() -> Client {
+    setup_with_user("friend")
 }
 
 pub fn run_seed_script(pool: &DbPool) -> Result<(), diesel::result::Error> {
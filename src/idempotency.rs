@@ -0,0 +1,262 @@
+//! Safe retries for POST requests.
+//!
+//! A client may send an `Idempotency-Key` header with any POST. The first
+//! request with a key runs normally and its response is saved alongside a
+//! fingerprint of the request; a retry with the same key and the same request
+//! gets the saved response back instead of running again. Reusing a key for a
+//! different request is rejected with 422. Keys are forgotten after
+//! `RETENTION_HOURS`, and server errors are not saved so they can be retried.
+//!
+//! JSON bodies come through `Idempotent`. Routes with any other kind of body
+//! take an `IdempotencyKey` and pass in a description of the body themselves.
+
+use rocket::data::{self, Data, FromData, Limits};
+use rocket::http::{ContentType, Status};
+use rocket::request::{self, local_cache, FromRequest, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::{self, Json};
+use serde::{Deserialize, Serialize};
+use crate::schema::idempotency_keys;
+use diesel::prelude::*;
+use chrono::{Duration, NaiveDateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+// How long a key and its response are kept
+pub const RETENTION_HOURS: i64 = 24;
+const MAX_KEY_LENGTH: usize = 255;
+
+pub const INVALID_KEY: &str = "Idempotency-Key must be between 1 and 255 characters";
+pub const KEY_REUSED: &str = "Idempotency-Key was already used for a different request";
+pub const KEY_IN_PROGRESS: &str = "A request with this Idempotency-Key is still being processed";
+
+#[derive(Queryable, Debug)]
+pub struct IdempotencyRecord {
+    pub idempotency_key: String,
+    pub fingerprint: String,
+    pub status: Option<i32>,
+    pub content_type: Option<String>,
+    pub body: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+// A response that can be saved against a key and sent again verbatim
+#[derive(Debug)]
+pub struct StoredResponse {
+    pub status: Status,
+    pub content_type: ContentType,
+    pub body: String,
+    pub replayed: bool,
+}
+
+impl StoredResponse {
+    pub fn text(status: Status, body: &str) -> Self {
+        StoredResponse { status, content_type: ContentType::Plain, body: body.to_string(), replayed: false }
+    }
+
+    pub fn json<T: Serialize>(status: Status, value: &T) -> Result<Self, (Status, &'static str)> {
+        let body = serde_json::to_string(value)
+            .map_err(|_| (Status::InternalServerError, "Failed to serialize response"))?;
+        Ok(StoredResponse { status, content_type: ContentType::JSON, body, replayed: false })
+    }
+}
+
+impl<'r> Responder<'r, 'static> for StoredResponse {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build();
+        response
+            .status(self.status)
+            .header(self.content_type)
+            .sized_body(self.body.len(), Cursor::new(self.body));
+        if self.replayed {
+            response.raw_header(REPLAYED_HEADER, "true");
+        }
+        response.ok()
+    }
+}
+
+// A JSON request body along with the idempotency key it was sent with
+pub struct Idempotent<'r, T> {
+    pub key: Option<String>,
+    pub fingerprint: String,
+    pub payload: Result<Json<T>, json::Error<'r>>,
+}
+
+// The key a request was sent with, for routes whose body isn't JSON
+pub struct IdempotencyKey {
+    pub key: Option<String>,
+    request_line: String,
+}
+
+// The method, path and query a request was sent to
+fn request_line(request: &Request<'_>) -> String {
+    match request.uri().query() {
+        Some(query) => format!("{} {}?{}", request.method(), request.uri().path(), query),
+        None => format!("{} {}", request.method(), request.uri().path()),
+    }
+}
+
+// Requests only match when they hit the same route with the same query and body
+fn fingerprint(request_line: &str, body: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(request_line);
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+fn read_key(request: &Request<'_>) -> Result<Option<String>, (Status, &'static str)> {
+    match request.headers().get_one(IDEMPOTENCY_KEY_HEADER) {
+        Some(key) if key.trim().is_empty() || key.len() > MAX_KEY_LENGTH => Err((Status::BadRequest, INVALID_KEY)),
+        key => Ok(key.map(str::to_string)),
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IdempotencyKey {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match read_key(request) {
+            Ok(key) => request::Outcome::Success(IdempotencyKey { key, request_line: request_line(request) }),
+            Err(error) => request::Outcome::Error(error),
+        }
+    }
+}
+
+impl IdempotencyKey {
+    // Run a handler once per key, where `body` stands in for the request body
+    pub fn respond<F>(self, connection: &mut SqliteConnection, body: &str, handler: F) -> Result<StoredResponse, (Status, &'static str)>
+    where
+        F: FnOnce(&mut SqliteConnection) -> Result<StoredResponse, (Status, &'static str)>,
+    {
+        let fingerprint = fingerprint(&self.request_line, body);
+        run_once(connection, self.key, &fingerprint, handler)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: Deserialize<'r>> FromData<'r> for Idempotent<'r, T> {
+    type Error = &'static str;
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let key = match read_key(request) {
+            Ok(key) => key,
+            Err(error) => return data::Outcome::Error(error),
+        };
+
+        let limit = request.limits().get("json").unwrap_or(Limits::JSON);
+        let body = match data.open(limit).into_string().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => return data::Outcome::Error((Status::PayloadTooLarge, "Request body is too large")),
+            Err(_) => return data::Outcome::Error((Status::BadRequest, "Failed to read request body")),
+        };
+
+        let body: &'r str = local_cache!(request, body);
+        let payload = serde_json::from_str(body)
+            .map(Json)
+            .map_err(|err| json::Error::Parse(body, err));
+
+        data::Outcome::Success(Idempotent { key, fingerprint: fingerprint(&request_line(request), body), payload })
+    }
+}
+
+impl<'r, T> Idempotent<'r, T> {
+    // Run a handler once per key, replaying its saved response on retries
+    pub fn respond<F>(self, connection: &mut SqliteConnection, handler: F) -> Result<StoredResponse, (Status, &'static str)>
+    where
+        F: FnOnce(&mut SqliteConnection, Result<Json<T>, json::Error<'r>>) -> Result<StoredResponse, (Status, &'static str)>,
+    {
+        let payload = self.payload;
+        run_once(connection, self.key, &self.fingerprint, |connection| handler(connection, payload))
+    }
+}
+
+// Run a handler, or replay what it answered last time the key was sent
+fn run_once<F>(connection: &mut SqliteConnection, key: Option<String>, fingerprint: &str, handler: F) -> Result<StoredResponse, (Status, &'static str)>
+where
+    F: FnOnce(&mut SqliteConnection) -> Result<StoredResponse, (Status, &'static str)>,
+{
+    let key = match key {
+        Some(key) => key,
+        None => return handler(connection),
+    };
+
+    let storage_error = |_| (Status::InternalServerError, "Failed to store idempotency key");
+
+    let expired = Utc::now().naive_utc() - Duration::hours(RETENTION_HOURS);
+    diesel::delete(idempotency_keys::table.filter(idempotency_keys::dsl::created_at.lt(expired)))
+        .execute(connection)
+        .map_err(storage_error)?;
+
+    let existing: Option<IdempotencyRecord> = idempotency_keys::table
+        .find(&key)
+        .first(connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch idempotency key"))?;
+
+    if let Some(record) = existing {
+        return replay(record, fingerprint);
+    }
+
+    // Claim the key before running, so a concurrent retry can't run the handler too
+    let claimed = diesel::insert_into(idempotency_keys::table)
+        .values((
+            idempotency_keys::dsl::idempotency_key.eq(&key),
+            idempotency_keys::dsl::fingerprint.eq(fingerprint),
+        ))
+        .execute(connection);
+    if claimed.is_err() {
+        return Err((Status::Conflict, KEY_IN_PROGRESS));
+    }
+
+    let response = handler(connection)
+        .unwrap_or_else(|(status, body)| StoredResponse::text(status, body));
+
+    let target = idempotency_keys::table.find(&key);
+    if response.status.class().is_server_error() {
+        diesel::delete(target).execute(connection).map_err(storage_error)?;
+    } else {
+        diesel::update(target)
+            .set((
+                idempotency_keys::dsl::status.eq(i32::from(response.status.code)),
+                idempotency_keys::dsl::content_type.eq(response.content_type.to_string()),
+                idempotency_keys::dsl::body.eq(&response.body),
+            ))
+            .execute(connection)
+            .map_err(storage_error)?;
+    }
+
+    Ok(response)
+}
+
+// The request body, or the error Rocket would have sent for a malformed one
+pub fn require_payload<'r, T>(payload: Result<Json<T>, json::Error<'r>>) -> Result<Json<T>, (Status, &'static str)> {
+    payload.map_err(|err| {
+        error!("Rejected request body: {:?}", err);
+        match err {
+            json::Error::Io(_) => (Status::BadRequest, "Failed to read request body"),
+            json::Error::Parse(..) => (Status::UnprocessableEntity, "Invalid request body"),
+        }
+    })
+}
+
+// The saved response for a retried request
+fn replay(record: IdempotencyRecord, fingerprint: &str) -> Result<StoredResponse, (Status, &'static str)> {
+    if record.fingerprint != fingerprint {
+        return Err((Status::UnprocessableEntity, KEY_REUSED));
+    }
+
+    match (record.status, record.content_type, record.body) {
+        (Some(status), Some(content_type), Some(body)) => Ok(StoredResponse {
+            status: Status::from_code(status as u16).unwrap_or(Status::Ok),
+            content_type: ContentType::parse_flexible(&content_type).unwrap_or(ContentType::Plain),
+            body,
+            replayed: true,
+        }),
+        _ => Err((Status::Conflict, KEY_IN_PROGRESS)),
+    }
+}
@@ -5,4 +5,28 @@ pub mod db;
 pub mod schema;
 pub mod todos;
 pub mod helpers;
-pub mod user;
\ No newline at end of file
+pub mod user;
+pub mod views;
+pub mod stats;
+pub mod rfc3339;
+pub mod priority;
+pub mod statuses;
+pub mod ordering;
+pub mod bulk;
+pub mod etag;
+pub mod idempotency;
+pub mod sync;
+pub mod events;
+pub mod collab;
+pub mod webhooks;
+pub mod sharing;
+pub mod assignees;
+pub mod comments;
+pub mod notifications;
+pub mod attachments;
+pub mod time_tracking;
+pub mod templates;
+pub mod duplicate;
+pub mod snooze;
+pub mod rules;
+pub mod habits;
\ No newline at end of file
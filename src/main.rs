@@ -2,20 +2,16 @@
 
 use rocket::fairing::AdHoc;
 use log::info;
-use env_logger;
 use std::io::Write;
 
-mod db;
-mod schema;
-mod todos;
-mod user;
+use dooly::{assignees, attachments, bulk, collab, comments, db, duplicate, events, habits, notifications, rules, sharing, snooze, stats, statuses, sync, templates, time_tracking, todos, user, views, webhooks};
 
 #[launch]
 fn rocket() -> _ {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
         .format(|buf, record| {
             let timestamp = buf.timestamp();  // Get the timestamp
-            write!(buf, "[{}] - {} - {}\n", timestamp, record.level(), record.args())  // Correct use of write!
+            writeln!(buf, "[{}] - {} - {}", timestamp, record.level(), record.args())
         })
         .init();
 
@@ -29,6 +25,10 @@ fn rocket() -> _ {
         .attach(AdHoc::on_liftoff("Logger", |_| Box::pin(async move {
             info!("Rocket has launched successfully!");
         })))
+        .attach(webhooks::WebhookWorker::fairing())
+        .attach(attachments::Storage::fairing())
+        .manage(events::EventBus::new(&pool))
+        .manage(collab::Presence::default())
         .manage(pool)
-        .mount("/", routes![todos::get_todos, todos::add_todo, todos::delete_todo, todos::update_todo, todos::complete_todo, user::create_user, user::get_user_by_id, todos::search_todos])
+        .mount("/", routes![todos::get_todos, todos::get_todo, todos::add_todo, todos::delete_todo, todos::update_todo, todos::complete_todo, user::create_user, user::get_user_by_id, user::get_user_settings, user::update_user_settings, todos::search_todos, todos::move_todo, bulk::bulk_todos, sync::get_changes, sync::push_changes, events::get_events, collab::connect, views::get_today, views::get_overdue, views::get_upcoming, views::get_someday, stats::get_stats, statuses::get_statuses, statuses::add_status, statuses::delete_status, statuses::transition_todo, statuses::get_board, webhooks::add_webhook, webhooks::get_webhooks, webhooks::delete_webhook, webhooks::enable_webhook, webhooks::get_deliveries, sharing::add_share, sharing::get_shares, sharing::delete_share, assignees::assign_todo, assignees::unassign_todo, assignees::get_assigned, comments::get_comments, comments::add_comment, comments::update_comment, comments::delete_comment, notifications::get_notifications, notifications::mark_read, notifications::mark_all_read, notifications::get_notification_preferences, notifications::update_notification_preferences, attachments::upload_attachment, attachments::get_attachments, attachments::download_attachment, attachments::delete_attachment, attachments::get_storage_usage, time_tracking::start_timer, time_tracking::get_timer, time_tracking::stop_timer, time_tracking::add_time_entry, time_tracking::get_time_entries, time_tracking::delete_time_entry, time_tracking::get_timesheet, templates::add_template, templates::save_as_template, templates::get_templates, templates::get_template, templates::delete_template, templates::instantiate_template, duplicate::duplicate_todo, snooze::snooze_todo, snooze::unsnooze_todo, rules::add_rule, rules::get_rules, rules::get_rule, rules::update_rule, rules::delete_rule, rules::dry_run_rule, habits::add_habit, habits::get_habits, habits::get_habit, habits::delete_habit, habits::check_in, habits::delete_checkin, habits::get_habit_history, habits::get_habit_heatmap])
 }
@@ -0,0 +1,337 @@
+//! In-app notifications.
+//!
+//! Handlers call `notify` when something happens that another user should
+//! hear about: being assigned a todo, mentioned in a comment or given access
+//! by a share. Due-soon notifications have no request to hang off, so they
+//! are raised when the user next fetches their notifications, once per todo.
+//! Users can turn each kind off in their notification preferences.
+
+use rocket::http::Status;
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::schema::{notification_preferences, notifications, todos, users};
+use crate::user::load_user_settings;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use log::info;
+use chrono::{Duration, NaiveDateTime, Utc};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+// How far ahead a todo's deadline counts as due soon
+const DUE_SOON_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[diesel(sql_type = Text)]
+pub enum NotificationKind {
+    Assigned,
+    Mentioned,
+    Shared,
+    DueSoon,
+}
+
+impl NotificationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::Assigned => "assigned",
+            NotificationKind::Mentioned => "mentioned",
+            NotificationKind::Shared => "shared",
+            NotificationKind::DueSoon => "due_soon",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for NotificationKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.as_str());
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for NotificationKind {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "assigned" => Ok(NotificationKind::Assigned),
+            "mentioned" => Ok(NotificationKind::Mentioned),
+            "shared" => Ok(NotificationKind::Shared),
+            "due_soon" => Ok(NotificationKind::DueSoon),
+            other => Err(format!("Unknown notification kind: {}", other).into()),
+        }
+    }
+}
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct Notification {
+    pub id: i32,
+    pub user_id: i32,
+    pub kind: NotificationKind,
+    pub actor_id: Option<i32>,
+    pub todo_id: Option<i32>,
+    pub comment_id: Option<i32>,
+    pub message: String,
+    pub read_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = notifications)]
+pub struct NewNotification {
+    pub user_id: i32,
+    pub kind: NotificationKind,
+    pub actor_id: Option<i32>,
+    pub todo_id: Option<i32>,
+    pub comment_id: Option<i32>,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NotificationList {
+    pub unread: i64,
+    pub notifications: Vec<Notification>,
+}
+
+#[derive(Queryable, Insertable, AsChangeset, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = notification_preferences, primary_key(user_id))]
+pub struct NotificationPreferences {
+    pub user_id: i32,
+    pub assigned: bool,
+    pub mentioned: bool,
+    pub shared: bool,
+    pub due_soon: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateNotificationPreferences {
+    pub assigned: bool,
+    pub mentioned: bool,
+    pub shared: bool,
+    pub due_soon: bool,
+}
+
+impl NotificationPreferences {
+    // Everything is on until a user says otherwise
+    pub fn defaults(user_id: i32) -> Self {
+        NotificationPreferences { user_id, assigned: true, mentioned: true, shared: true, due_soon: true }
+    }
+
+    pub fn wants(&self, kind: NotificationKind) -> bool {
+        match kind {
+            NotificationKind::Assigned => self.assigned,
+            NotificationKind::Mentioned => self.mentioned,
+            NotificationKind::Shared => self.shared,
+            NotificationKind::DueSoon => self.due_soon,
+        }
+    }
+}
+
+pub fn load_notification_preferences(connection: &mut SqliteConnection, user_id: i32) -> QueryResult<NotificationPreferences> {
+    let preferences = notification_preferences::table
+        .find(user_id)
+        .first::<NotificationPreferences>(connection)
+        .optional()?;
+
+    Ok(preferences.unwrap_or_else(|| NotificationPreferences::defaults(user_id)))
+}
+
+// Store a notification unless its recipient has turned that kind off
+pub fn notify(connection: &mut SqliteConnection, notification: NewNotification) -> QueryResult<bool> {
+    if !load_notification_preferences(connection, notification.user_id)?.wants(notification.kind) {
+        return Ok(false);
+    }
+
+    info!("Notifying user {} ({})", notification.user_id, notification.kind.as_str());
+    diesel::insert_into(notifications::table)
+        .values(&notification)
+        .execute(connection)?;
+    Ok(true)
+}
+
+// The name used in messages about something a user did
+pub fn username(connection: &mut SqliteConnection, user_id: i32) -> QueryResult<String> {
+    users::table.find(user_id).select(users::dsl::username).first(connection)
+}
+
+// The distinct `@username`s in a piece of text. An `@` inside a word, as in an
+// email address, is not a mention.
+pub fn mentions(text: &str) -> Vec<&str> {
+    let mut names: Vec<&str> = Vec::new();
+    let mut previous: Option<char> = None;
+
+    for (index, character) in text.char_indices() {
+        if character == '@' && previous.is_none_or(|previous| !previous.is_alphanumeric()) {
+            let rest = &text[index + 1..];
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.'))
+                .unwrap_or(rest.len());
+            // A mention at the end of a sentence keeps its full stop out of the name
+            let name = rest[..end].trim_end_matches('.');
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        previous = Some(character);
+    }
+
+    names
+}
+
+// Raise a notification for each open todo the user is responsible for that is
+// coming due and hasn't been notified about yet. A todo is the assignee's
+// responsibility, or its owner's when nobody is assigned.
+fn notify_due_soon(connection: &mut SqliteConnection, user_id: i32) -> QueryResult<()> {
+    let settings = load_user_settings(connection, user_id)?;
+    let today = settings.today();
+    let now = Utc::now().naive_utc();
+
+    let notified = notifications::table
+        .filter(notifications::dsl::user_id.eq(user_id))
+        .filter(notifications::dsl::kind.eq(NotificationKind::DueSoon))
+        .select(notifications::dsl::todo_id);
+
+    let due: Vec<(i32, String)> = todos::table
+        .filter(todos::dsl::completed.eq(false))
+        .filter(
+            todos::dsl::assignee_id.eq(user_id)
+                .or(todos::dsl::assignee_id.is_null().and(todos::dsl::user_id.eq(user_id)))
+        )
+        .filter(
+            todos::dsl::due_at.between(now, now + Duration::hours(DUE_SOON_HOURS))
+                .or(todos::dsl::due_at.is_null().and(todos::dsl::due_date.between(today, today + Duration::days(1))))
+        )
+        .filter(diesel::dsl::not(todos::dsl::id.nullable().eq_any(notified)))
+        .select((todos::dsl::id, todos::dsl::title))
+        .load(connection)?;
+
+    for (todo_id, title) in due {
+        notify(connection, NewNotification {
+            user_id,
+            kind: NotificationKind::DueSoon,
+            actor_id: None,
+            todo_id: Some(todo_id),
+            comment_id: None,
+            message: format!("\"{}\" is due soon", title),
+        })?;
+    }
+
+    Ok(())
+}
+
+fn unread_count(connection: &mut SqliteConnection, user_id: i32) -> QueryResult<i64> {
+    notifications::table
+        .filter(notifications::dsl::user_id.eq(user_id))
+        .filter(notifications::dsl::read_at.is_null())
+        .count()
+        .get_result(connection)
+}
+
+// A user's notifications, newest first, with how many are unread
+#[get("/notifications?<user_id>&<unread>&<limit>")]
+pub fn get_notifications(pool: &State<DbPool>, user_id: i32, unread: Option<bool>, limit: Option<i64>) -> Result<Json<NotificationList>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    notify_due_soon(&mut connection, user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to check for todos due soon"))?;
+
+    let mut query = notifications::table
+        .filter(notifications::dsl::user_id.eq(user_id))
+        .order(notifications::dsl::id.desc())
+        .limit(limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE))
+        .into_boxed();
+    if unread == Some(true) {
+        query = query.filter(notifications::dsl::read_at.is_null());
+    }
+
+    let notifications = query
+        .load::<Notification>(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch notifications"))?;
+    let unread = unread_count(&mut connection, user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch notifications"))?;
+
+    Ok(Json(NotificationList { unread, notifications }))
+}
+
+// Mark one of the user's notifications as read
+#[put("/notifications/<id>/read?<user_id>")]
+pub fn mark_read(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<Json<Notification>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let target = notifications::table.find(id).filter(notifications::dsl::user_id.eq(user_id));
+    let notification: Notification = target
+        .first(&mut connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch notification"))?
+        .ok_or((Status::NotFound, "Notification not found"))?;
+
+    // Reading it again keeps the time it was first read
+    if notification.read_at.is_some() {
+        return Ok(Json(notification));
+    }
+
+    diesel::update(target)
+        .set(notifications::dsl::read_at.eq(Utc::now().naive_utc()))
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to mark notification as read"))?;
+
+    target.first(&mut connection)
+        .map(Json)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch notification"))
+}
+
+// Mark all of the user's notifications as read
+#[put("/notifications/read?<user_id>")]
+pub fn mark_all_read(pool: &State<DbPool>, user_id: i32) -> Result<&'static str, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    diesel::update(notifications::table
+            .filter(notifications::dsl::user_id.eq(user_id))
+            .filter(notifications::dsl::read_at.is_null()))
+        .set(notifications::dsl::read_at.eq(Utc::now().naive_utc()))
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to mark notifications as read"))?;
+
+    Ok("Notifications marked as read")
+}
+
+#[get("/notifications/preferences?<user_id>")]
+pub fn get_notification_preferences(pool: &State<DbPool>, user_id: i32) -> Result<Json<NotificationPreferences>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    username(&mut connection, user_id).map_err(|_| (Status::NotFound, "User not found"))?;
+    let preferences = load_notification_preferences(&mut connection, user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch notification preferences"))?;
+
+    Ok(Json(preferences))
+}
+
+#[put("/notifications/preferences?<user_id>", format = "json", data = "<updated_preferences>")]
+pub fn update_notification_preferences(pool: &State<DbPool>, user_id: i32, updated_preferences: Json<UpdateNotificationPreferences>) -> Result<Json<NotificationPreferences>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    username(&mut connection, user_id).map_err(|_| (Status::NotFound, "User not found"))?;
+
+    let preferences = NotificationPreferences {
+        user_id,
+        assigned: updated_preferences.assigned,
+        mentioned: updated_preferences.mentioned,
+        shared: updated_preferences.shared,
+        due_soon: updated_preferences.due_soon,
+    };
+
+    diesel::insert_into(notification_preferences::table)
+        .values(&preferences)
+        .on_conflict(notification_preferences::user_id)
+        .do_update()
+        .set(&preferences)
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to save notification preferences"))?;
+
+    Ok(Json(preferences))
+}
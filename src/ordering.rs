@@ -0,0 +1,136 @@
+//! Lexicographic position keys for manually ordered todo lists.
+//!
+//! Each key is a base-62 fraction (the digits after an implied "0."), so any
+//! two keys always have room for another key between them and moving a todo
+//! only rewrites that one row. Keys grow by a digit when squeezed into a tight
+//! spot; once a list has a key longer than `MAX_KEY_LENGTH` the whole list is
+//! respaced evenly.
+//!
+//! Respacing keeps every todo in the same relative order, so versions are left
+//! alone and offline edits to the list don't conflict with it. Each respaced
+//! todo is still logged as updated, so clients pick up the new keys before they
+//! compute their next move against them.
+
+use crate::schema::todos;
+use crate::sync::{record_change, ChangeKind};
+use diesel::prelude::*;
+
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const BASE: usize = 62;
+// Longest key handed out. A key needing more digits than this means its
+// neighbours are too close, and `key_between` refuses so the caller rebalances.
+const MAX_KEY_LENGTH: usize = 10;
+
+fn digit_value(digit: u8) -> usize {
+    DIGITS.iter().position(|candidate| *candidate == digit).unwrap_or(0)
+}
+
+// A key strictly between `low` and `high`, where an empty `low` means the start of
+// the list and a missing `high` the end. Keys never end in "0".
+fn midpoint(low: &[u8], high: Option<&[u8]>) -> Vec<u8> {
+    if let Some(high) = high {
+        // Keep any shared prefix and find the midpoint of what follows it
+        let shared = high.iter()
+            .enumerate()
+            .take_while(|(index, digit)| low.get(*index).copied().unwrap_or(b'0') == **digit)
+            .count();
+        if shared > 0 {
+            let mut key = high[..shared].to_vec();
+            key.extend(midpoint(low.get(shared..).unwrap_or(&[]), Some(&high[shared..])));
+            return key;
+        }
+    }
+
+    let low_digit = low.first().map(|digit| digit_value(*digit)).unwrap_or(0);
+    let high_digit = high.and_then(|high| high.first()).map(|digit| digit_value(*digit)).unwrap_or(BASE);
+
+    if high_digit - low_digit > 1 {
+        return vec![DIGITS[(low_digit + high_digit) / 2]];
+    }
+
+    match high {
+        // The high key's first digit alone already sorts before the rest of it
+        Some(high) if high.len() > 1 => high[..1].to_vec(),
+        _ => {
+            let mut key = vec![DIGITS[low_digit]];
+            key.extend(midpoint(low.get(1..).unwrap_or(&[]), None));
+            key
+        }
+    }
+}
+
+// A position key between two neighbours, or `None` if the neighbours leave no room
+pub fn key_between(before: Option<&str>, after: Option<&str>) -> Option<String> {
+    let low = before.unwrap_or("");
+    if let Some(high) = after {
+        if low >= high {
+            return None;
+        }
+    }
+
+    let key = midpoint(low.as_bytes(), after.map(str::as_bytes));
+    let key = String::from_utf8(key).ok()?;
+    if key.len() > MAX_KEY_LENGTH {
+        return None;
+    }
+
+    Some(key)
+}
+
+// `count` evenly spaced keys, in order
+fn spaced_keys(count: usize) -> Vec<String> {
+    let mut width = 1;
+    while BASE.pow(width) <= count {
+        width += 1;
+    }
+    let step = BASE.pow(width) / (count + 1);
+
+    (1..=count)
+        .map(|index| {
+            let mut value = index * step;
+            let mut key = vec![b'0'; width as usize];
+            for slot in key.iter_mut().rev() {
+                *slot = DIGITS[value % BASE];
+                value /= BASE;
+            }
+            let key = String::from_utf8(key).expect("position keys are ASCII");
+            key.trim_end_matches('0').to_string()
+        })
+        .collect()
+}
+
+// Respace every position in a user's list, keeping the current order
+pub fn rebalance(connection: &mut SqliteConnection, user_id: i32) -> QueryResult<()> {
+    let ids: Vec<i32> = todos::table
+        .filter(todos::dsl::user_id.eq(user_id))
+        .order((todos::dsl::position, todos::dsl::id))
+        .select(todos::dsl::id)
+        .load(connection)?;
+
+    for (id, key) in ids.iter().zip(spaced_keys(ids.len())) {
+        diesel::update(todos::table.find(id))
+            .set(todos::dsl::position.eq(key))
+            .execute(connection)?;
+        record_change(connection, *id, ChangeKind::Updated)?;
+    }
+
+    Ok(())
+}
+
+// The position for a todo appended to the end of a user's list
+pub fn next_position(connection: &mut SqliteConnection, user_id: i32) -> QueryResult<String> {
+    let last: Option<String> = todos::table
+        .filter(todos::dsl::user_id.eq(user_id))
+        .select(todos::dsl::position)
+        .order(todos::dsl::position.desc())
+        .first(connection)
+        .optional()?;
+
+    match key_between(last.as_deref(), None) {
+        Some(key) => Ok(key),
+        None => {
+            rebalance(connection, user_id)?;
+            next_position(connection, user_id)
+        }
+    }
+}
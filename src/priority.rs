@@ -0,0 +1,118 @@
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Integer;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+// Message used whenever a client sends a priority outside the known levels
+pub const INVALID_PRIORITY: &str = "Priority must be one of none, low, medium, high, urgent (or 0-4)";
+
+// Stored as 0 (none) through 4 (urgent), so ordering by the column orders by importance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Integer)]
+pub enum Priority {
+    #[default]
+    None = 0,
+    Low = 1,
+    Medium = 2,
+    High = 3,
+    Urgent = 4,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::None => "none",
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "none" => Some(Priority::None),
+            "low" => Some(Priority::Low),
+            "medium" => Some(Priority::Medium),
+            "high" => Some(Priority::High),
+            "urgent" => Some(Priority::Urgent),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<i64> for Priority {
+    type Error = &'static str;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Priority::None),
+            1 => Ok(Priority::Low),
+            2 => Ok(Priority::Medium),
+            3 => Ok(Priority::High),
+            4 => Ok(Priority::Urgent),
+            _ => Err(INVALID_PRIORITY),
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ToSql<Integer, Sqlite> for Priority {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(*self as i32);
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Integer, Sqlite> for Priority {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let value = <i32 as FromSql<Integer, Sqlite>>::from_sql(bytes)?;
+        Ok(Priority::try_from(i64::from(value))?)
+    }
+}
+
+impl Serialize for Priority {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+// Accepts either the level name ("high") or its number (3)
+impl<'de> Deserialize<'de> for Priority {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PriorityVisitor;
+
+        impl Visitor<'_> for PriorityVisitor {
+            type Value = Priority;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a priority name or a number from 0 to 4")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Priority, E> {
+                Priority::from_name(value).ok_or_else(|| E::custom(INVALID_PRIORITY))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Priority, E> {
+                Priority::try_from(value).map_err(E::custom)
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Priority, E> {
+                i64::try_from(value)
+                    .map_err(|_| E::custom(INVALID_PRIORITY))
+                    .and_then(|value| self.visit_i64(value))
+            }
+        }
+
+        deserializer.deserialize_any(PriorityVisitor)
+    }
+}
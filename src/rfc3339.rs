@@ -0,0 +1,33 @@
+//! Serde helpers for optional UTC timestamps exchanged as RFC 3339 strings.
+//!
+//! Timestamps are stored as naive UTC values; any offset given by the client
+//! is converted to UTC on the way in, and values are always written back out
+//! with a `Z` suffix.
+
+use chrono::{DateTime, NaiveDateTime, SecondsFormat};
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S>(value: &Option<NaiveDateTime>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match value {
+        Some(timestamp) => serializer.serialize_str(&timestamp.and_utc().to_rfc3339_opts(SecondsFormat::Secs, true)),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+
+    value
+        .map(|value| {
+            DateTime::parse_from_rfc3339(&value)
+                .map(|timestamp| timestamp.naive_utc())
+                .map_err(serde::de::Error::custom)
+        })
+        .transpose()
+}
@@ -0,0 +1,495 @@
+//! Automation rules.
+//!
+//! A rule belongs to a user and watches the todos in their list. When a todo
+//! is created, updated or completed through `todos.rs` or a bulk operation,
+//! every enabled rule with that trigger whose conditions all hold runs its
+//! actions, and the result is saved as one more version of the todo before
+//! the request returns. Rules run in the order they were created, each seeing what the
+//! ones before it changed.
+//!
+//! Changes made by rules count as updates, so they can set off other rules.
+//! Each rule fires at most once per original change and a chain stops after
+//! `MAX_RULE_PASSES`, which keeps rules from undoing each other forever. A
+//! rule that fails is logged and rolled back without failing the change that
+//! set it off.
+//!
+//! Todos have no tags, so conditions look at a todo's own fields; matching on
+//! a word in the title covers most of what a tag would.
+
+use rocket::http::Status;
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::db::DbPool;
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::notifications::{notify, NewNotification, NotificationKind};
+use crate::priority::Priority;
+use crate::schema::{rules, todos};
+use crate::sharing::permission;
+use crate::snooze::{preset_until, SnoozePreset};
+use crate::sync::{record_change, ChangeKind};
+use crate::todos::TodoItem;
+use crate::user::load_user_settings;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use log::{error, info};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use std::cmp::Ordering;
+
+// How many rounds of rule changes one todo change can set off
+const MAX_RULE_PASSES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Title,
+    Description,
+    Priority,
+    DueDate,
+    Completed,
+    AssigneeId,
+    StatusId,
+    EstimateMinutes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    Eq,
+    Ne,
+    Contains,  // Case-insensitive, for text fields
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    IsSet,
+    IsNotSet,
+}
+
+// A test on one field, e.g. `{"field": "title", "op": "contains", "value": "urgent"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Condition {
+    pub field: Field,
+    pub op: Operator,
+    #[serde(default)]
+    pub value: Value,
+}
+
+// A field's value on a todo, in the form conditions compare against
+fn field_value(todo: &TodoItem, field: Field) -> Value {
+    match field {
+        Field::Title => Value::from(todo.title.as_str()),
+        Field::Description => todo.description.as_deref().map_or(Value::Null, Value::from),
+        Field::Priority => Value::from(todo.priority as i64),
+        Field::DueDate => todo.due_date.map_or(Value::Null, |date| Value::from(date.to_string())),
+        Field::Completed => Value::from(todo.completed),
+        Field::AssigneeId => todo.assignee_id.map_or(Value::Null, Value::from),
+        Field::StatusId => todo.status_id.map_or(Value::Null, Value::from),
+        Field::EstimateMinutes => todo.estimate_minutes.map_or(Value::Null, Value::from),
+    }
+}
+
+// The value a condition was written with, in the same form as `field_value`;
+// priorities can be given by name or number
+fn expected_value(field: Field, value: &Value) -> Option<Value> {
+    match field {
+        Field::Priority => serde_json::from_value::<Priority>(value.clone()).ok().map(|priority| Value::from(priority as i64)),
+        Field::DueDate => value.as_str()?.parse::<NaiveDate>().ok().map(|date| Value::from(date.to_string())),
+        Field::Title | Field::Description => value.as_str().map(Value::from),
+        Field::Completed => value.as_bool().map(Value::from),
+        Field::AssigneeId | Field::StatusId | Field::EstimateMinutes => value.as_i64().map(Value::from),
+    }
+}
+
+fn compare(actual: &Value, expected: &Value) -> Option<Ordering> {
+    match (actual, expected) {
+        (Value::Number(actual), Value::Number(expected)) => Some(actual.as_i64()?.cmp(&expected.as_i64()?)),
+        (Value::String(actual), Value::String(expected)) => Some(actual.cmp(expected)),
+        _ => None,
+    }
+}
+
+impl Condition {
+    fn validate(&self) -> Result<(), (Status, &'static str)> {
+        let text = matches!(self.field, Field::Title | Field::Description);
+        let ordered = matches!(self.field, Field::Priority | Field::DueDate | Field::EstimateMinutes);
+        match self.op {
+            Operator::IsSet | Operator::IsNotSet => return Ok(()),
+            Operator::Contains if !text => return Err((Status::BadRequest, "Only text fields support contains")),
+            Operator::Gt | Operator::Gte | Operator::Lt | Operator::Lte if !ordered => {
+                return Err((Status::BadRequest, "Only priority, due date and estimate can be compared"));
+            }
+            _ => {}
+        }
+        if expected_value(self.field, &self.value).is_none() {
+            return Err((Status::BadRequest, "Condition value does not fit its field"));
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, todo: &TodoItem) -> bool {
+        let actual = field_value(todo, self.field);
+        let expected = expected_value(self.field, &self.value);
+
+        match (self.op, expected) {
+            (Operator::IsSet, _) => !actual.is_null(),
+            (Operator::IsNotSet, _) => actual.is_null(),
+            (_, None) => false,
+            (Operator::Eq, Some(expected)) => actual == expected,
+            (Operator::Ne, Some(expected)) => actual != expected,
+            (Operator::Contains, Some(expected)) => match (actual.as_str(), expected.as_str()) {
+                (Some(actual), Some(expected)) => actual.to_lowercase().contains(&expected.to_lowercase()),
+                _ => false,
+            },
+            (op, Some(expected)) => compare(&actual, &expected).is_some_and(|ordering| match op {
+                Operator::Gt => ordering.is_gt(),
+                Operator::Gte => ordering.is_ge(),
+                Operator::Lt => ordering.is_lt(),
+                _ => ordering.is_le(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    SetPriority { priority: Priority },
+    Assign { user_id: i32 },  // Skipped when the user can't see the todo
+    Unassign,
+    Complete,
+    Snooze { preset: SnoozePreset },
+}
+
+// All of a rule's conditions, stored as a JSON array
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(transparent)]
+#[diesel(sql_type = Text)]
+pub struct Conditions(pub Vec<Condition>);
+
+impl Conditions {
+    pub fn matches(&self, todo: &TodoItem) -> bool {
+        self.0.iter().all(|condition| condition.matches(todo))
+    }
+}
+
+impl ToSql<Text, Sqlite> for Conditions {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(serde_json::to_string(&self.0)?);
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for Conditions {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let conditions = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(Conditions(serde_json::from_str(&conditions)?))
+    }
+}
+
+// A rule's actions in the order they run, stored as a JSON array
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(transparent)]
+#[diesel(sql_type = Text)]
+pub struct Actions(pub Vec<Action>);
+
+impl ToSql<Text, Sqlite> for Actions {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(serde_json::to_string(&self.0)?);
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for Actions {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let actions = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(Actions(serde_json::from_str(&actions)?))
+    }
+}
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct Rule {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub trigger: ChangeKind,
+    pub conditions: Conditions,
+    pub actions: Actions,
+    pub enabled: bool,
+    pub created_at: NaiveDateTime,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Insertable, Deserialize, Debug)]
+#[diesel(table_name = rules)]
+pub struct NewRule {
+    pub user_id: i32,
+    pub name: String,
+    pub trigger: ChangeKind,
+    #[serde(default)]
+    pub conditions: Conditions,
+    pub actions: Actions,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+// A todo as it is now and as the rule would leave it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RulePreview {
+    pub before: TodoItem,
+    pub after: TodoItem,
+}
+
+fn validate(rule: &NewRule) -> Result<(), (Status, &'static str)> {
+    if rule.name.trim().is_empty() {
+        return Err((Status::BadRequest, "Rule name cannot be empty"));
+    }
+    if rule.trigger == ChangeKind::Deleted {
+        return Err((Status::BadRequest, "Rules cannot run on deleted todos"));
+    }
+    if rule.actions.0.is_empty() {
+        return Err((Status::BadRequest, "A rule needs at least one action"));
+    }
+    rule.conditions.0.iter().try_for_each(Condition::validate)
+}
+
+// Apply one action to an in-memory todo
+fn apply(connection: &mut SqliteConnection, action: &Action, todo: &mut TodoItem, now: NaiveDateTime) -> QueryResult<()> {
+    match action {
+        Action::SetPriority { priority } => todo.priority = *priority,
+        Action::Assign { user_id } => {
+            if permission(connection, *user_id, todo)?.is_some() {
+                todo.assignee_id = Some(*user_id);
+            } else {
+                info!("Rule skipped assigning to-do item {} to user {} without access", todo.id, user_id);
+            }
+        }
+        Action::Unassign => todo.assignee_id = None,
+        Action::Complete => {
+            if !todo.completed {
+                todo.completed = true;
+                todo.completed_at = Some(now);
+                todo.status_id = None;
+            }
+        }
+        Action::Snooze { preset } => {
+            let settings = load_user_settings(connection, todo.user_id)?;
+            todo.defer_until = Some(preset_until(*preset, &settings, now.and_utc()));
+        }
+    }
+    Ok(())
+}
+
+// Whether actions left anything to save
+fn changed(before: &TodoItem, after: &TodoItem) -> bool {
+    before.priority != after.priority
+        || before.assignee_id != after.assignee_id
+        || before.completed != after.completed
+        || before.defer_until != after.defer_until
+}
+
+// Save the fields rules can change as the todo's next version
+fn save(connection: &mut SqliteConnection, before: &TodoItem, after: &TodoItem) -> QueryResult<bool> {
+    let updated = diesel::update(todos::table.find(before.id).filter(todos::dsl::version.eq(before.version)))
+        .set((
+            todos::dsl::priority.eq(after.priority),
+            todos::dsl::assignee_id.eq(after.assignee_id),
+            todos::dsl::completed.eq(after.completed),
+            todos::dsl::completed_at.eq(after.completed_at),
+            todos::dsl::status_id.eq(after.status_id),
+            todos::dsl::defer_until.eq(after.defer_until),
+            todos::dsl::version.eq(before.version + 1),
+        ))
+        .execute(connection)?;
+    if updated == 0 {
+        return Ok(false);
+    }
+
+    record_change(connection, before.id, ChangeKind::of_update(before.completed, after.completed))?;
+    if let Some(assignee_id) = after.assignee_id.filter(|id| before.assignee_id != Some(*id) && *id != after.user_id) {
+        notify(connection, NewNotification {
+            user_id: assignee_id,
+            kind: NotificationKind::Assigned,
+            actor_id: None,
+            todo_id: Some(after.id),
+            comment_id: None,
+            message: format!("A rule assigned you \"{}\"", after.title),
+        })?;
+    }
+    Ok(true)
+}
+
+fn apply_rules(connection: &mut SqliteConnection, todo: &TodoItem, kind: ChangeKind) -> QueryResult<Option<TodoItem>> {
+    let rules: Vec<Rule> = rules::table
+        .filter(rules::dsl::user_id.eq(todo.user_id))
+        .filter(rules::dsl::enabled.eq(true))
+        .order(rules::dsl::id)
+        .load(connection)?;
+    if rules.is_empty() {
+        return Ok(None);
+    }
+
+    let now = Utc::now().naive_utc();
+    let mut fired: Vec<i32> = Vec::new();
+    let mut current = todo.clone();
+    let mut trigger = kind;
+    let mut saved = false;
+
+    for _ in 0..MAX_RULE_PASSES {
+        let mut next = current.clone();
+        for rule in &rules {
+            if rule.trigger != trigger || fired.contains(&rule.id) || !rule.conditions.matches(&next) {
+                continue;
+            }
+            info!("Running rule {} on to-do item {}", rule.id, todo.id);
+            fired.push(rule.id);
+            // Each rule gets its own savepoint, so a failing one leaves the rest in place
+            let mut attempt = next.clone();
+            let outcome = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+                rule.actions.0.iter().try_for_each(|action| apply(connection, action, &mut attempt, now))
+            });
+            match outcome {
+                Ok(()) => next = attempt,
+                Err(err) => error!("Rule {} failed on to-do item {} and was rolled back: {:?}", rule.id, todo.id, err),
+            }
+        }
+
+        if !changed(&current, &next) || !save(connection, &current, &next)? {
+            break;
+        }
+        trigger = ChangeKind::of_update(current.completed, next.completed);
+        next.version = current.version + 1;
+        current = next;
+        saved = true;
+    }
+
+    Ok(saved.then_some(current))
+}
+
+// Run the owner's rules after a change to a todo, returning the todo as the
+// rules left it
+pub fn run_rules(connection: &mut SqliteConnection, todo: TodoItem, kind: ChangeKind) -> TodoItem {
+    match connection.transaction(|connection| apply_rules(connection, &todo, kind)) {
+        Ok(Some(todo)) => todo,
+        Ok(None) => todo,
+        Err(err) => {
+            error!("Failed to run rules on to-do item {}: {:?}", todo.id, err);
+            todo
+        }
+    }
+}
+
+fn owned_rule(connection: &mut SqliteConnection, id: i32, user_id: i32) -> Result<Rule, (Status, &'static str)> {
+    rules::table
+        .find(id)
+        .filter(rules::dsl::user_id.eq(user_id))
+        .first(connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch rule"))?
+        .ok_or((Status::NotFound, "Rule not found"))
+}
+
+#[post("/rules", format = "json", data = "<new_rule>")]
+pub fn add_rule(pool: &State<DbPool>, new_rule: Idempotent<'_, NewRule>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    new_rule.respond(&mut connection, |connection, new_rule| {
+        let new_rule = require_payload(new_rule)?;
+        validate(&new_rule)?;
+
+        info!("Adding rule {:?} for user {}", new_rule.name, new_rule.user_id);
+        let rule: Rule = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+            diesel::insert_into(rules::table).values(&*new_rule).execute(connection)?;
+            rules::table.order(rules::dsl::id.desc()).first(connection)
+        }).map_err(|_| (Status::InternalServerError, "Failed to add rule"))?;
+
+        StoredResponse::json(Status::Ok, &rule)
+    })
+}
+
+#[get("/rules?<user_id>")]
+pub fn get_rules(pool: &State<DbPool>, user_id: i32) -> Result<Json<Vec<Rule>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let results = rules::table
+        .filter(rules::dsl::user_id.eq(user_id))
+        .order(rules::dsl::id)
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch rules"))?;
+
+    Ok(Json(results))
+}
+
+#[get("/rules/<id>?<user_id>")]
+pub fn get_rule(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<Json<Rule>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    owned_rule(&mut connection, id, user_id).map(Json)
+}
+
+// Replace a rule's definition, or switch it on and off
+#[put("/rules/<id>", format = "json", data = "<updated_rule>")]
+pub fn update_rule(pool: &State<DbPool>, id: i32, updated_rule: Json<NewRule>) -> Result<Json<Rule>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    owned_rule(&mut connection, id, updated_rule.user_id)?;
+    validate(&updated_rule)?;
+
+    diesel::update(rules::table.find(id))
+        .set((
+            rules::dsl::name.eq(&updated_rule.name),
+            rules::dsl::trigger.eq(updated_rule.trigger),
+            rules::dsl::conditions.eq(&updated_rule.conditions),
+            rules::dsl::actions.eq(&updated_rule.actions),
+            rules::dsl::enabled.eq(updated_rule.enabled),
+        ))
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to update rule"))?;
+
+    owned_rule(&mut connection, id, updated_rule.user_id).map(Json)
+}
+
+#[delete("/rules/<id>?<user_id>")]
+pub fn delete_rule(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<&'static str, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    owned_rule(&mut connection, id, user_id)?;
+
+    diesel::delete(rules::table.find(id))
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to delete rule"))?;
+
+    Ok("Rule deleted successfully!")
+}
+
+// Show what a rule would do to the user's existing todos, without saving
+// the rule or changing anything. The trigger is ignored and rules don't chain.
+#[post("/rules/dry-run", format = "json", data = "<rule>")]
+pub fn dry_run_rule(pool: &State<DbPool>, rule: Json<NewRule>) -> Result<Json<Vec<RulePreview>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    validate(&rule)?;
+
+    let candidates: Vec<TodoItem> = todos::table
+        .filter(todos::dsl::user_id.eq(rule.user_id))
+        .order((todos::dsl::position, todos::dsl::id))
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+
+    let now = Utc::now().naive_utc();
+    let mut previews = Vec::new();
+    for todo in candidates.into_iter().filter(|todo| rule.conditions.matches(todo)) {
+        let mut after = todo.clone();
+        for action in &rule.actions.0 {
+            apply(&mut connection, action, &mut after, now)
+                .map_err(|_| (Status::InternalServerError, "Failed to run rule"))?;
+        }
+        previews.push(RulePreview { before: todo, after });
+    }
+
+    Ok(Json(previews))
+}
@@ -1,12 +1,189 @@
 diesel::table! {
-    todos (id) {
+    attachments (id) {
+        id -> Integer,
+        todo_id -> Integer,
+        user_id -> Integer,
+        filename -> Text,
+        content_type -> Text,
+        size -> BigInt,
+        digest -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    comments (id) {
+        id -> Integer,
+        todo_id -> Integer,
+        user_id -> Integer,
+        body -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    habit_checkins (id) {
+        id -> Integer,
+        habit_id -> Integer,
+        date -> Date,
+        count -> Integer,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    habits (id) {
+        id -> Integer,
+        user_id -> Integer,
+        name -> Text,
+        frequency -> Text,
+        target -> Integer,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    idempotency_keys (idempotency_key) {
+        idempotency_key -> Text,
+        fingerprint -> Text,
+        status -> Nullable<Integer>,
+        content_type -> Nullable<Text>,
+        body -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    notification_preferences (user_id) {
+        user_id -> Integer,
+        assigned -> Bool,
+        mentioned -> Bool,
+        shared -> Bool,
+        due_soon -> Bool,
+    }
+}
+
+diesel::table! {
+    notifications (id) {
+        id -> Integer,
+        user_id -> Integer,
+        kind -> Text,
+        actor_id -> Nullable<Integer>,
+        todo_id -> Nullable<Integer>,
+        comment_id -> Nullable<Integer>,
+        message -> Text,
+        read_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    rules (id) {
+        id -> Integer,
+        user_id -> Integer,
+        name -> Text,
+        trigger -> Text,
+        conditions -> Text,
+        actions -> Text,
+        enabled -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    shares (id) {
+        id -> Integer,
+        owner_id -> Integer,
+        grantee_id -> Integer,
+        todo_id -> Nullable<Integer>,
+        role -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    statuses (id) {
+        id -> Integer,
+        user_id -> Integer,
+        name -> Text,
+        category -> Text,
+        position -> Integer,
+    }
+}
+
+diesel::table! {
+    template_items (id) {
         id -> Integer,
+        template_id -> Integer,
+        position -> Integer,
         title -> Text,
         description -> Nullable<Text>,
         priority -> Nullable<Integer>,
+        due_offset_days -> Nullable<Integer>,
+        estimate_minutes -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    templates (id) {
+        id -> Integer,
+        user_id -> Integer,
+        name -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    time_entries (id) {
+        id -> Integer,
+        todo_id -> Integer,
+        user_id -> Integer,
+        started_at -> Timestamp,
+        ended_at -> Nullable<Timestamp>,
+        note -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    todo_changes (id) {
+        id -> Integer,
+        todo_id -> Integer,
+        user_id -> Integer,
+        kind -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    todos (id) {
+        id -> Integer,
+        title -> Text,
+        description -> Nullable<Text>,
+        priority -> Integer,
         due_date -> Nullable<Date>,
         completed -> Bool,
         user_id -> Integer,
+        created_at -> Timestamp,
+        completed_at -> Nullable<Timestamp>,
+        due_at -> Nullable<Timestamp>,
+        status_id -> Nullable<Integer>,
+        position -> Text,
+        version -> Integer,
+        assignee_id -> Nullable<Integer>,
+        estimate_minutes -> Nullable<Integer>,
+        defer_until -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    user_settings (user_id) {
+        user_id -> Integer,
+        timezone -> Text,
+        week_start -> Text,
+        date_format -> Text,
+        default_priority -> Nullable<Integer>,
     }
 }
 
@@ -18,9 +195,76 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Integer,
+        webhook_id -> Integer,
+        event -> Text,
+        payload -> Text,
+        status -> Text,
+        attempts -> Integer,
+        next_attempt_at -> Timestamp,
+        response_status -> Nullable<Integer>,
+        error -> Nullable<Text>,
+        created_at -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    webhooks (id) {
+        id -> Integer,
+        user_id -> Integer,
+        url -> Text,
+        secret -> Text,
+        events -> Text,
+        active -> Bool,
+        consecutive_failures -> Integer,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(attachments -> todos (todo_id));
+diesel::joinable!(attachments -> users (user_id));
+diesel::joinable!(comments -> todos (todo_id));
+diesel::joinable!(comments -> users (user_id));
+diesel::joinable!(habit_checkins -> habits (habit_id));
+diesel::joinable!(habits -> users (user_id));
+diesel::joinable!(notification_preferences -> users (user_id));
+diesel::joinable!(notifications -> comments (comment_id));
+diesel::joinable!(notifications -> todos (todo_id));
+diesel::joinable!(rules -> users (user_id));
+diesel::joinable!(shares -> todos (todo_id));
+diesel::joinable!(statuses -> users (user_id));
+diesel::joinable!(template_items -> templates (template_id));
+diesel::joinable!(templates -> users (user_id));
+diesel::joinable!(time_entries -> todos (todo_id));
+diesel::joinable!(time_entries -> users (user_id));
+diesel::joinable!(todo_changes -> users (user_id));
+diesel::joinable!(todos -> statuses (status_id));
 diesel::joinable!(todos -> users (user_id));
+diesel::joinable!(user_settings -> users (user_id));
+diesel::joinable!(webhook_deliveries -> webhooks (webhook_id));
+diesel::joinable!(webhooks -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    attachments,
+    comments,
+    habit_checkins,
+    habits,
+    idempotency_keys,
+    notification_preferences,
+    notifications,
+    rules,
+    shares,
+    statuses,
+    template_items,
+    templates,
+    time_entries,
+    todo_changes,
     todos,
+    user_settings,
     users,
+    webhook_deliveries,
+    webhooks,
 );
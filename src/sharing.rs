@@ -0,0 +1,319 @@
+//! Sharing todos with other users.
+//!
+//! An owner can grant another user access to a single todo or to their whole
+//! list, as a viewer or an editor. Shared todos show up in the grantee's
+//! listings, searches, views, sync and event stream. Handlers that take an
+//! acting `user_id` check it with `authorize`: viewers can read, editors can
+//! also change todos, and only the owner can delete a todo or give it to
+//! someone else.
+
+use rocket::http::Status;
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::events::EventBus;
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::notifications::{notify, username, NewNotification, NotificationKind};
+use crate::schema::{shares, todos, users};
+use crate::sync::{record_change, record_removal, ChangeKind};
+use crate::todos::TodoItem;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::{Bool, Text};
+use diesel::sqlite::{Sqlite, SqliteValue};
+use log::{error, info};
+use chrono::NaiveDateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[diesel(sql_type = Text)]
+pub enum ShareRole {
+    Viewer,
+    Editor,
+}
+
+impl ShareRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareRole::Viewer => "viewer",
+            ShareRole::Editor => "editor",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for ShareRole {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.as_str());
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for ShareRole {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "viewer" => Ok(ShareRole::Viewer),
+            "editor" => Ok(ShareRole::Editor),
+            other => Err(format!("Unknown share role: {}", other).into()),
+        }
+    }
+}
+
+// What a user may do with a todo, from least to most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    View,
+    Edit,
+    Own,
+}
+
+impl From<ShareRole> for Permission {
+    fn from(role: ShareRole) -> Self {
+        match role {
+            ShareRole::Viewer => Permission::View,
+            ShareRole::Editor => Permission::Edit,
+        }
+    }
+}
+
+// `todo_id` is `None` when the owner's whole list is shared
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct Share {
+    pub id: i32,
+    pub owner_id: i32,
+    pub grantee_id: i32,
+    pub todo_id: Option<i32>,
+    pub role: ShareRole,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewShare {
+    pub user_id: i32,
+    pub username: String,
+    pub todo_id: Option<i32>,
+    pub role: ShareRole,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShareList {
+    pub granted: Vec<Share>,
+    pub received: Vec<Share>,
+}
+
+// Filter for the todos a user owns or has been given access to
+pub fn visible_to(user_id: i32) -> Box<dyn BoxableExpression<todos::table, Sqlite, SqlType = Bool>> {
+    let shared_lists = shares::table
+        .filter(shares::dsl::grantee_id.eq(user_id))
+        .filter(shares::dsl::todo_id.is_null())
+        .select(shares::dsl::owner_id);
+    let shared_todos = shares::table
+        .filter(shares::dsl::grantee_id.eq(user_id))
+        .select(shares::dsl::todo_id);
+
+    Box::new(
+        todos::dsl::user_id.eq(user_id)
+            .or(todos::dsl::user_id.eq_any(shared_lists))
+            .or(todos::dsl::id.nullable().eq_any(shared_todos))
+    )
+}
+
+// Everyone a todo is shared with, directly or through its owner's list
+pub fn grantees(connection: &mut SqliteConnection, todo_id: i32, owner_id: i32) -> QueryResult<Vec<i32>> {
+    shares::table
+        .filter(shares::dsl::todo_id.eq(todo_id)
+            .or(shares::dsl::todo_id.is_null().and(shares::dsl::owner_id.eq(owner_id))))
+        .select(shares::dsl::grantee_id)
+        .distinct()
+        .load(connection)
+}
+
+// The todos a grant covers
+fn shared_todos(connection: &mut SqliteConnection, share: &Share) -> QueryResult<Vec<TodoItem>> {
+    match share.todo_id {
+        Some(todo_id) => todos::table.find(todo_id).load(connection),
+        None => todos::table.filter(todos::dsl::user_id.eq(share.owner_id)).load(connection),
+    }
+}
+
+// What a user may do with everything in another user's list
+pub fn list_permission(connection: &mut SqliteConnection, user_id: i32, owner_id: i32) -> QueryResult<Option<Permission>> {
+    if user_id == owner_id {
+        return Ok(Some(Permission::Own));
+    }
+
+    let role: Option<ShareRole> = shares::table
+        .filter(shares::dsl::owner_id.eq(owner_id))
+        .filter(shares::dsl::grantee_id.eq(user_id))
+        .filter(shares::dsl::todo_id.is_null())
+        .select(shares::dsl::role)
+        .first(connection)
+        .optional()?;
+    Ok(role.map(Permission::from))
+}
+
+// What a user may do with a todo, through its list or a share of the todo itself
+pub fn permission(connection: &mut SqliteConnection, user_id: i32, todo: &TodoItem) -> QueryResult<Option<Permission>> {
+    let through_list = list_permission(connection, user_id, todo.user_id)?;
+    let role: Option<ShareRole> = shares::table
+        .filter(shares::dsl::grantee_id.eq(user_id))
+        .filter(shares::dsl::todo_id.eq(todo.id))
+        .select(shares::dsl::role)
+        .first(connection)
+        .optional()?;
+    Ok(through_list.max(role.map(Permission::from)))
+}
+
+// Fetch a todo the acting user needs `needed` on. Todos the user can't see at
+// all are reported as missing rather than forbidden.
+pub fn authorize(connection: &mut SqliteConnection, user_id: i32, id: i32, needed: Permission) -> Result<TodoItem, (Status, &'static str)> {
+    let todo: TodoItem = todos::table
+        .find(id)
+        .first(connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todo"))?
+        .ok_or((Status::NotFound, "Todo item not found"))?;
+
+    let granted = permission(connection, user_id, &todo)
+        .map_err(|_| (Status::InternalServerError, "Failed to check todo access"))?;
+    match granted {
+        None => Err((Status::NotFound, "Todo item not found")),
+        Some(granted) if granted < needed => Err((Status::Forbidden, "You do not have permission to do that")),
+        Some(_) => Ok(todo),
+    }
+}
+
+// Grant a user access to a todo or a whole list, replacing any earlier grant of the same thing
+#[post("/shares", format = "json", data = "<new_share>")]
+pub fn add_share(pool: &State<DbPool>, events: &State<EventBus>, new_share: Idempotent<'_, NewShare>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let response = new_share.respond(&mut connection, |connection, new_share| {
+        let new_share = require_payload(new_share)?;
+
+        let grantee_id: i32 = users::table
+            .filter(users::dsl::username.eq(&new_share.username))
+            .select(users::dsl::id)
+            .first(connection)
+            .optional()
+            .map_err(|_| (Status::InternalServerError, "Failed to fetch user"))?
+            .ok_or((Status::NotFound, "User not found"))?;
+
+        if grantee_id == new_share.user_id {
+            return Err((Status::BadRequest, "Cannot share with yourself"));
+        }
+
+        let todo = match new_share.todo_id {
+            Some(todo_id) => Some(authorize(connection, new_share.user_id, todo_id, Permission::Own)?),
+            None => None,
+        };
+
+        info!("User {} sharing {:?} with user {} as {:?}", new_share.user_id, new_share.todo_id, grantee_id, new_share.role);
+        let share = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+            let existing = shares::table
+                .filter(shares::dsl::owner_id.eq(new_share.user_id))
+                .filter(shares::dsl::grantee_id.eq(grantee_id))
+                .into_boxed();
+            let existing = match new_share.todo_id {
+                Some(todo_id) => existing.filter(shares::dsl::todo_id.eq(todo_id)),
+                None => existing.filter(shares::dsl::todo_id.is_null()),
+            };
+            let existing: Option<Share> = existing.first(connection).optional()?;
+
+            let share = match existing {
+                Some(share) => {
+                    diesel::update(shares::table.find(share.id))
+                        .set(shares::dsl::role.eq(new_share.role))
+                        .execute(connection)?;
+                    shares::table.find(share.id).first::<Share>(connection)
+                }
+                None => {
+                    diesel::insert_into(shares::table)
+                        .values((
+                            shares::dsl::owner_id.eq(new_share.user_id),
+                            shares::dsl::grantee_id.eq(grantee_id),
+                            shares::dsl::todo_id.eq(new_share.todo_id),
+                            shares::dsl::role.eq(new_share.role),
+                        ))
+                        .execute(connection)?;
+                    shares::table.order(shares::dsl::id.desc()).first::<Share>(connection)
+                }
+            }?;
+
+            // Log the shared todos again so they reach the grantee's next sync
+            for todo in shared_todos(connection, &share)? {
+                record_change(connection, todo.id, ChangeKind::Updated)?;
+            }
+            Ok(share)
+        }).map_err(|_| (Status::InternalServerError, "Failed to share"))?;
+
+        let notified = username(connection, new_share.user_id).and_then(|owner| notify(connection, NewNotification {
+            user_id: grantee_id,
+            kind: NotificationKind::Shared,
+            actor_id: Some(new_share.user_id),
+            todo_id: new_share.todo_id,
+            comment_id: None,
+            message: match &todo {
+                Some(todo) => format!("{} shared \"{}\" with you as {}", owner, todo.title, share.role.as_str()),
+                None => format!("{} shared their list with you as {}", owner, share.role.as_str()),
+            },
+        }));
+        if let Err(err) = notified {
+            error!("Failed to notify grantee: {:?}", err);
+        }
+
+        StoredResponse::json(Status::Ok, &share)
+    });
+
+    events.publish(&mut connection);
+    response
+}
+
+// The grants a user has made and the ones they have been given
+#[get("/shares?<user_id>")]
+pub fn get_shares(pool: &State<DbPool>, user_id: i32) -> Result<Json<ShareList>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let granted = shares::table
+        .filter(shares::dsl::owner_id.eq(user_id))
+        .order(shares::dsl::id)
+        .load::<Share>(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch shares"))?;
+    let received = shares::table
+        .filter(shares::dsl::grantee_id.eq(user_id))
+        .order(shares::dsl::id)
+        .load::<Share>(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch shares"))?;
+
+    Ok(Json(ShareList { granted, received }))
+}
+
+// Revoke a grant; only the owner who made it can
+#[delete("/shares/<id>?<user_id>")]
+pub fn delete_share(pool: &State<DbPool>, events: &State<EventBus>, id: i32, user_id: i32) -> Result<&'static str, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let share: Share = shares::table
+        .find(id)
+        .filter(shares::dsl::owner_id.eq(user_id))
+        .first(&mut connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch share"))?
+        .ok_or((Status::NotFound, "Share not found"))?;
+
+    // The grantee's clients drop whatever they can no longer see through another grant
+    connection.transaction::<_, diesel::result::Error, _>(|connection| {
+        diesel::delete(shares::table.find(id)).execute(connection)?;
+        for todo in shared_todos(connection, &share)? {
+            if permission(connection, share.grantee_id, &todo)?.is_none() {
+                record_removal(connection, todo.id, share.grantee_id)?;
+            }
+        }
+        Ok(())
+    }).map_err(|_| (Status::InternalServerError, "Failed to revoke share"))?;
+    events.publish(&mut connection);
+
+    Ok("Share revoked successfully!")
+}
@@ -0,0 +1,141 @@
+//! Deferring todos. A todo with `defer_until` in the future is left out of
+//! the default listing and the smart views until that moment passes; it can
+//! still be fetched directly, searched for, and synced.
+//!
+//! Snooze presets are worked out on the user's local clock, so "tomorrow"
+//! means tomorrow morning wherever the user is.
+
+use rocket::http::Status;
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::etag::{check_version, IfMatch, Tagged, PRECONDITION_FAILED};
+use crate::events::EventBus;
+use crate::schema::todos;
+use crate::sharing::{authorize, Permission};
+use crate::sync::{record_change, ChangeKind};
+use crate::todos::{load_details, TodoItem};
+use crate::user::{load_user_settings, local_to_utc, UserSettings};
+use diesel::prelude::*;
+use log::info;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+
+// "Later today" is at least this many hours away, rounded up to the next local hour
+const LATER_TODAY_HOURS: i64 = 3;
+// Snoozing to another day wakes the todo at the start of the working day
+const MORNING_HOUR: i64 = 9;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnoozePreset {
+    LaterToday,
+    Tomorrow,
+    NextWeek,
+}
+
+// Either a preset or an explicit moment to defer the todo until
+#[derive(Deserialize, Debug)]
+pub struct Snooze {
+    pub user_id: i32,
+    pub preset: Option<SnoozePreset>,
+    #[serde(default, with = "crate::rfc3339")]
+    pub until: Option<NaiveDateTime>,
+}
+
+fn morning(date: NaiveDate) -> NaiveDateTime {
+    date.and_time(NaiveTime::MIN) + Duration::hours(MORNING_HOUR)
+}
+
+// When a preset snoozed at `now` should wake up
+pub fn preset_until(preset: SnoozePreset, settings: &UserSettings, now: DateTime<Utc>) -> NaiveDateTime {
+    let local = now.with_timezone(&settings.tz());
+    let today = local.date_naive();
+
+    let wake = match preset {
+        SnoozePreset::LaterToday => {
+            let later = (local + Duration::hours(LATER_TODAY_HOURS)).naive_local();
+            let hour = later.date().and_time(NaiveTime::MIN) + Duration::hours(later.hour() as i64);
+            if hour < later { hour + Duration::hours(1) } else { hour }
+        }
+        SnoozePreset::Tomorrow => morning(today + Duration::days(1)),
+        SnoozePreset::NextWeek => {
+            let days_into_week = today.weekday().days_since(settings.week_start()) as i64;
+            morning(today + Duration::days(7 - days_into_week))
+        }
+    };
+
+    local_to_utc(settings.tz(), wake)
+}
+
+// Set or clear a todo's deferral, returning its new version
+fn set_defer_until(connection: &mut SqliteConnection, todo: &TodoItem, if_match: IfMatch, defer_until: Option<NaiveDateTime>) -> Result<i32, (Status, &'static str)> {
+    check_version(if_match, todo.version)?;
+
+    let updated = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+        let updated = diesel::update(todos::table.find(todo.id).filter(todos::dsl::version.eq(todo.version)))
+            .set((
+                todos::dsl::defer_until.eq(defer_until),
+                todos::dsl::version.eq(todo.version + 1),
+            ))
+            .execute(connection)?;
+        if updated > 0 {
+            record_change(connection, todo.id, ChangeKind::Updated)?;
+        }
+        Ok(updated)
+    }).map_err(|_| (Status::InternalServerError, "Failed to snooze todo"))?;
+
+    if updated == 0 {
+        return Err((Status::PreconditionFailed, PRECONDITION_FAILED));
+    }
+
+    Ok(todo.version + 1)
+}
+
+// Hide a todo until a preset or explicit moment
+#[post("/todos/<id>/snooze", format = "json", data = "<snooze>")]
+pub fn snooze_todo(pool: &State<DbPool>, events: &State<EventBus>, id: i32, if_match: IfMatch, snooze: Json<Snooze>) -> Result<Tagged<Json<TodoItem>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let todo = authorize(&mut connection, snooze.user_id, id, Permission::Edit)?;
+
+    let now = Utc::now();
+    let until = match (snooze.preset, snooze.until) {
+        (Some(preset), None) => {
+            let settings = load_user_settings(&mut connection, snooze.user_id)
+                .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))?;
+            preset_until(preset, &settings, now)
+        }
+        (None, Some(until)) => until,
+        _ => return Err((Status::BadRequest, "Give either a preset or a time to snooze until")),
+    };
+    if until <= now.naive_utc() {
+        return Err((Status::BadRequest, "Snooze time must be in the future"));
+    }
+
+    info!("Snoozing to-do item {} until {}", id, until);
+    let version = set_defer_until(&mut connection, &todo, if_match, Some(until))?;
+    events.publish(&mut connection);
+
+    let mut todo: TodoItem = todos::table
+        .find(id)
+        .first(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todo"))?;
+    load_details(&mut connection, std::slice::from_mut(&mut todo))
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todo"))?;
+
+    Ok(Tagged { version, inner: Json(todo) })
+}
+
+// Bring a deferred todo back right away
+#[delete("/todos/<id>/snooze?<user_id>")]
+pub fn unsnooze_todo(pool: &State<DbPool>, events: &State<EventBus>, id: i32, user_id: i32, if_match: IfMatch) -> Result<Tagged<&'static str>, (Status, &'static str)> {
+    info!("Unsnoozing to-do item {}", id);
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let todo = authorize(&mut connection, user_id, id, Permission::Edit)?;
+    let version = set_defer_until(&mut connection, &todo, if_match, None)?;
+    events.publish(&mut connection);
+
+    Ok(Tagged { version, inner: "Todo unsnoozed successfully!" })
+}
@@ -0,0 +1,267 @@
+use rocket::http::Status;
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::priority::Priority;
+use crate::schema::{todos, users};
+use crate::todos::overdue;
+use crate::user::{load_user_settings, load_users_settings, local_to_utc};
+use diesel::dsl::{count_star, sql};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Bool, Date, Nullable, Double};
+use diesel::sqlite::Sqlite;
+use log::info;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use std::collections::{BTreeMap, HashMap};
+
+// Reports cover the last four weeks unless a range is given
+const DEFAULT_RANGE_DAYS: i64 = 28;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UserStats {
+    pub user_id: i32,
+    pub username: String,
+    pub open: i64,
+    pub completed: i64,
+    pub overdue: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Queryable)]
+pub struct PeriodCount {
+    pub period_start: NaiveDate,
+    pub completed: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Queryable)]
+pub struct PriorityStats {
+    pub priority: Priority,
+    pub open: i64,
+    pub completed: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StatsReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub users: Vec<UserStats>,
+    pub completions_per_day: Vec<PeriodCount>,
+    pub completions_per_week: Vec<PeriodCount>,
+    pub average_seconds_to_complete: Option<f64>,
+    pub by_priority: Vec<PriorityStats>,
+}
+
+// Productivity report for one user, or for every user when no user_id is given
+#[get("/stats?<user_id>&<from>&<to>")]
+pub fn get_stats(
+    pool: &State<DbPool>,
+    user_id: Option<i32>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Json<StatsReport>, (Status, &'static str)> {
+    info!("Computing stats from {:?} to {:?} for user {:?}", from, to, user_id);
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    // A single user's report follows their own calendar, the team-wide report uses UTC weeks starting Monday
+    let settings = user_id
+        .map(|user_id| load_user_settings(&mut connection, user_id))
+        .transpose()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))?;
+    let (tz, week_start) = match &settings {
+        Some(settings) => (settings.tz(), settings.week_start()),
+        None => (Tz::UTC, Weekday::Mon),
+    };
+    let now = Utc::now().naive_utc();
+    let today = now.and_utc().with_timezone(&tz).date_naive();
+
+    let to = parse_date(to)?.unwrap_or(today);
+    let from = parse_date(from)?.unwrap_or(to - Duration::days(DEFAULT_RANGE_DAYS - 1));
+    if from > to {
+        return Err((Status::BadRequest, "From date must not be after to date"));
+    }
+
+    // Completion timestamps are compared against the half-open local range [from, to + 1 day),
+    // each end converted with the UTC offset in force on that day
+    let range_start = local_to_utc(tz, from.and_time(NaiveTime::MIN));
+    let range_end = local_to_utc(tz, (to + Duration::days(1)).and_time(NaiveTime::MIN));
+
+    let mut users_query = todos::table
+        .inner_join(users::table)
+        .group_by((users::dsl::id, users::dsl::username))
+        .select((
+            users::dsl::id,
+            users::dsl::username,
+            sql::<BigInt>("COALESCE(SUM(CASE WHEN todos.completed THEN 0 ELSE 1 END), 0)"),
+            sql::<BigInt>("COALESCE(SUM(CASE WHEN todos.completed THEN 1 ELSE 0 END), 0)"),
+        ))
+        .order(users::dsl::id)
+        .into_boxed();
+    if let Some(user_id) = user_id {
+        users_query = users_query.filter(todos::dsl::user_id.eq(user_id));
+    }
+    let user_counts: Vec<(i32, String, i64, i64)> = users_query
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to compute user stats"))?;
+
+    // "Overdue" depends on each user's own today, so users whose today is the same
+    // date are matched together and the whole lot is counted in one query
+    let user_ids: Vec<i32> = user_counts.iter().map(|(user_id, ..)| *user_id).collect();
+    let mut by_today: HashMap<NaiveDate, Vec<i32>> = HashMap::new();
+    for settings in load_users_settings(&mut connection, &user_ids)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))? {
+        by_today.entry(settings.today()).or_default().push(settings.user_id);
+    }
+    let mut overdue_filter: Box<dyn BoxableExpression<todos::table, Sqlite, SqlType = Bool>> = Box::new(false.into_sql::<Bool>());
+    for (user_today, ids) in by_today {
+        overdue_filter = Box::new(overdue_filter.or(todos::dsl::user_id.eq_any(ids).and(overdue(user_today, now))));
+    }
+    let overdue_counts: HashMap<i32, i64> = todos::table
+        .filter(overdue_filter)
+        .group_by(todos::dsl::user_id)
+        .select((todos::dsl::user_id, count_star()))
+        .load::<(i32, i64)>(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to compute user stats"))?
+        .into_iter()
+        .collect();
+
+    let users = user_counts.into_iter()
+        .map(|(user_id, username, open, completed)| UserStats {
+            user_id,
+            username,
+            open,
+            completed,
+            overdue: overdue_counts.get(&user_id).copied().unwrap_or(0),
+        })
+        .collect();
+
+    // Each completion falls on the local date it happened, so SQLite buckets each
+    // stretch of the range that shares one UTC offset with that offset
+    let mut per_day: BTreeMap<NaiveDate, i64> = BTreeMap::new();
+    for (start, end, offset) in offset_segments(tz, range_start, range_end) {
+        for (day, completed) in completions_per_day(&mut connection, user_id, start, end, offset)? {
+            *per_day.entry(day).or_insert(0) += completed;
+        }
+    }
+    let completions_per_week = count_by_period(&per_day, |date| {
+        date - Duration::days(date.weekday().days_since(week_start) as i64)
+    });
+    let completions_per_day = count_by_period(&per_day, |date| date);
+
+    let mut average_query = todos::table
+        .filter(todos::dsl::completed_at.ge(range_start))
+        .filter(todos::dsl::completed_at.lt(range_end))
+        .select(sql::<Nullable<Double>>("AVG((julianday(todos.completed_at) - julianday(todos.created_at)) * 86400.0)"))
+        .into_boxed();
+    if let Some(user_id) = user_id {
+        average_query = average_query.filter(todos::dsl::user_id.eq(user_id));
+    }
+    let average_seconds_to_complete: Option<f64> = average_query
+        .first(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to compute completion time"))?;
+
+    let mut priority_query = todos::table
+        .group_by(todos::dsl::priority)
+        .select((
+            todos::dsl::priority,
+            sql::<BigInt>("COALESCE(SUM(CASE WHEN todos.completed THEN 0 ELSE 1 END), 0)"),
+            sql::<BigInt>("COALESCE(SUM(CASE WHEN todos.completed THEN 1 ELSE 0 END), 0)"),
+        ))
+        .order(todos::dsl::priority)
+        .into_boxed();
+    if let Some(user_id) = user_id {
+        priority_query = priority_query.filter(todos::dsl::user_id.eq(user_id));
+    }
+    let by_priority: Vec<PriorityStats> = priority_query
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to compute priority stats"))?;
+
+    Ok(Json(StatsReport {
+        from,
+        to,
+        users,
+        completions_per_day,
+        completions_per_week,
+        average_seconds_to_complete,
+        by_priority,
+    }))
+}
+
+// Split [start, end) into stretches over which the time zone keeps one UTC
+// offset, as (start, end, offset in seconds)
+fn offset_segments(tz: Tz, start: NaiveDateTime, end: NaiveDateTime) -> Vec<(NaiveDateTime, NaiveDateTime, i32)> {
+    let offset_at = |moment: NaiveDateTime| tz.offset_from_utc_datetime(&moment).fix().local_minus_utc();
+
+    let mut segments = Vec::new();
+    let mut segment_start = start;
+    let mut offset = offset_at(start);
+    let mut probe = start;
+    while probe < end {
+        let next = (probe + Duration::days(1)).min(end);
+        let last = next - Duration::seconds(1);
+        if offset_at(last) == offset {
+            probe = next;
+            continue;
+        }
+
+        // The offset changes within this day, so find the second it does
+        let (mut low, mut high) = (probe, last);
+        while high - low > Duration::seconds(1) {
+            let middle = low + (high - low) / 2;
+            if offset_at(middle) == offset {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        segments.push((segment_start, high, offset));
+        segment_start = high;
+        offset = offset_at(high);
+        probe = high;
+    }
+    segments.push((segment_start, end, offset));
+    segments
+}
+
+// Completions per local date within [start, end), where the UTC offset is fixed
+fn completions_per_day(
+    connection: &mut SqliteConnection,
+    user_id: Option<i32>,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    offset: i32,
+) -> Result<Vec<(NaiveDate, i64)>, (Status, &'static str)> {
+    let day = format!("date(todos.completed_at, '{:+} seconds')", offset);
+    let mut query = todos::table
+        .filter(todos::dsl::completed_at.ge(start))
+        .filter(todos::dsl::completed_at.lt(end))
+        .group_by(sql::<Date>(&day))
+        .select((sql::<Date>(&day), count_star()))
+        .into_boxed();
+    if let Some(user_id) = user_id {
+        query = query.filter(todos::dsl::user_id.eq(user_id));
+    }
+
+    query
+        .load(connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to compute completions"))
+}
+
+// Sum daily counts per period, keyed by the date each period starts on
+fn count_by_period(per_day: &BTreeMap<NaiveDate, i64>, period_start: impl Fn(NaiveDate) -> NaiveDate) -> Vec<PeriodCount> {
+    let mut counts: BTreeMap<NaiveDate, i64> = BTreeMap::new();
+    for (date, completed) in per_day {
+        *counts.entry(period_start(*date)).or_insert(0) += completed;
+    }
+
+    counts.into_iter()
+        .map(|(period_start, completed)| PeriodCount { period_start, completed })
+        .collect()
+}
+
+pub(crate) fn parse_date(value: Option<String>) -> Result<Option<NaiveDate>, (Status, &'static str)> {
+    value
+        .map(|value| NaiveDate::parse_from_str(&value, "%Y-%m-%d"))
+        .transpose()
+        .map_err(|_| (Status::BadRequest, "Dates must be formatted as YYYY-MM-DD"))
+}
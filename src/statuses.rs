@@ -0,0 +1,279 @@
+use rocket::http::Status;
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::etag::{check_version, IfMatch, PRECONDITION_FAILED};
+use crate::events::EventBus;
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::schema::{statuses, todos};
+use crate::sharing::{authorize, Permission};
+use crate::sync::{record_change, ChangeKind};
+use crate::todos::{load_details, TodoItem};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use log::info;
+use chrono::Utc;
+
+// Every status belongs to one of these categories; only "done" counts as completed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[diesel(sql_type = Text)]
+pub enum StatusCategory {
+    Todo,
+    InProgress,
+    Done,
+}
+
+impl StatusCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatusCategory::Todo => "todo",
+            StatusCategory::InProgress => "in_progress",
+            StatusCategory::Done => "done",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for StatusCategory {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.as_str());
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for StatusCategory {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "todo" => Ok(StatusCategory::Todo),
+            "in_progress" => Ok(StatusCategory::InProgress),
+            "done" => Ok(StatusCategory::Done),
+            other => Err(format!("Unknown status category: {}", other).into()),
+        }
+    }
+}
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct TodoStatus {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub category: StatusCategory,
+    pub position: i32,
+}
+
+#[derive(Insertable, Deserialize, Debug)]
+#[diesel(table_name = statuses)]
+pub struct NewTodoStatus<'a> {
+    pub user_id: i32,
+    pub name: &'a str,
+    pub category: StatusCategory,
+    pub position: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatusTransition {
+    pub status_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BoardColumn {
+    pub status: TodoStatus,
+    pub todos: Vec<TodoItem>,
+}
+
+// Columns every user starts with
+const DEFAULT_STATUSES: [(&str, StatusCategory); 3] = [
+    ("To Do", StatusCategory::Todo),
+    ("In Progress", StatusCategory::InProgress),
+    ("Done", StatusCategory::Done),
+];
+
+// Give a new user the default columns
+pub fn create_default_statuses(connection: &mut SqliteConnection, user_id: i32) -> QueryResult<()> {
+    for (position, (name, category)) in DEFAULT_STATUSES.iter().enumerate() {
+        diesel::insert_into(statuses::table)
+            .values(NewTodoStatus { user_id, name, category: *category, position: Some(position as i32) })
+            .execute(connection)?;
+    }
+    Ok(())
+}
+
+// Load a user's statuses in board order
+pub fn load_statuses(connection: &mut SqliteConnection, user_id: i32) -> QueryResult<Vec<TodoStatus>> {
+    statuses::table
+        .filter(statuses::dsl::user_id.eq(user_id))
+        .order((statuses::dsl::position, statuses::dsl::id))
+        .load(connection)
+}
+
+#[get("/statuses?<user_id>")]
+pub fn get_statuses(pool: &State<DbPool>, user_id: i32) -> Result<Json<Vec<TodoStatus>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let results = load_statuses(&mut connection, user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch statuses"))?;
+
+    Ok(Json(results))
+}
+
+// Add a status column, at the end of the board unless a position is given
+#[post("/statuses", format = "json", data = "<new_status>")]
+pub fn add_status(pool: &State<DbPool>, new_status: Idempotent<'_, NewTodoStatus<'_>>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    new_status.respond(&mut connection, |connection, new_status| {
+        let new_status = require_payload(new_status)?;
+        if new_status.name.trim().is_empty() {
+            return Err((Status::BadRequest, "Status name cannot be empty"));
+        }
+
+        info!("Adding status {:?}", new_status);
+        let current = load_statuses(connection, new_status.user_id)
+            .map_err(|_| (Status::InternalServerError, "Failed to fetch statuses"))?;
+        let position = new_status.position
+            .unwrap_or_else(|| current.iter().map(|status| status.position + 1).max().unwrap_or(0));
+
+        let status = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+            // Make room for the new column by shifting the ones after it
+            diesel::update(statuses::table
+                .filter(statuses::dsl::user_id.eq(new_status.user_id))
+                .filter(statuses::dsl::position.ge(position)))
+                .set(statuses::dsl::position.eq(statuses::dsl::position + 1))
+                .execute(connection)?;
+
+            diesel::insert_into(statuses::table)
+                .values(NewTodoStatus { user_id: new_status.user_id, name: new_status.name, category: new_status.category, position: Some(position) })
+                .execute(connection)?;
+
+            statuses::table.order(statuses::dsl::id.desc()).first::<TodoStatus>(connection)
+        }).map_err(|_| (Status::InternalServerError, "Failed to add status"))?;
+
+        StoredResponse::json(Status::Ok, &status)
+    })
+}
+
+// Remove one of the user's status columns; todos still in it must be moved first
+#[delete("/statuses/<id>?<user_id>")]
+pub fn delete_status(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<&'static str, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let owned = statuses::table
+        .find(id)
+        .filter(statuses::dsl::user_id.eq(user_id))
+        .select(statuses::dsl::id)
+        .first::<i32>(&mut connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch status"))?;
+    if owned.is_none() {
+        return Err((Status::NotFound, "Status not found"));
+    }
+
+    let in_use: i64 = todos::table
+        .filter(todos::dsl::status_id.eq(id))
+        .count()
+        .get_result(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to delete status"))?;
+
+    if in_use > 0 {
+        return Err((Status::Conflict, "Status still has todos"));
+    }
+
+    diesel::delete(statuses::table.find(id))
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to delete status"))?;
+
+    Ok("Status deleted successfully!")
+}
+
+// Move a todo to another column; the column's category decides whether it is completed
+#[put("/todos/<id>/status?<user_id>", format = "json", data = "<transition>")]
+pub fn transition_todo(pool: &State<DbPool>, events: &State<EventBus>, id: i32, user_id: i32, if_match: IfMatch, transition: Json<StatusTransition>) -> Result<&'static str, (Status, &'static str)> {
+    info!("Moving to-do item {} to status {}", id, transition.status_id);
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let todo = authorize(&mut connection, user_id, id, Permission::Edit)?;
+    check_version(if_match, todo.version)?;
+
+    let status: TodoStatus = statuses::table
+        .find(transition.status_id)
+        .first(&mut connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch status"))?
+        .ok_or((Status::NotFound, "Status not found"))?;
+
+    if status.user_id != todo.user_id {
+        return Err((Status::BadRequest, "Status does not belong to the todo's owner"));
+    }
+
+    let completed = status.category == StatusCategory::Done;
+    let completed_at = if completed {
+        todo.completed_at.or_else(|| Some(Utc::now().naive_utc()))
+    } else {
+        None
+    };
+
+    // A concurrent write since the todo was fetched leaves nothing to update
+    let updated = diesel::update(todos::table.find(id).filter(todos::dsl::version.eq(todo.version)))
+        .set((
+            todos::dsl::status_id.eq(status.id),
+            todos::dsl::completed.eq(completed),
+            todos::dsl::completed_at.eq(completed_at),
+            todos::dsl::version.eq(todos::dsl::version + 1),
+        ))
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to update todo status"))?;
+    if updated == 0 {
+        return Err((Status::PreconditionFailed, PRECONDITION_FAILED));
+    }
+    record_change(&mut connection, id, ChangeKind::of_update(todo.completed, completed))
+        .map_err(|_| (Status::InternalServerError, "Failed to update todo status"))?;
+    events.publish(&mut connection);
+
+    Ok("Todo status updated successfully!")
+}
+
+// The user's todos grouped into their status columns, in board order
+#[get("/board?<user_id>")]
+pub fn get_board(pool: &State<DbPool>, user_id: i32) -> Result<Json<Vec<BoardColumn>>, (Status, &'static str)> {
+    info!("Fetching board for user {}", user_id);
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let columns = load_statuses(&mut connection, user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch statuses"))?;
+
+    let mut results: Vec<TodoItem> = todos::table
+        .filter(todos::dsl::user_id.eq(user_id))
+        .order((todos::dsl::position, todos::dsl::id))
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+    load_details(&mut connection, &mut results)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+
+    // Todos without a status land in the first column matching their completed flag
+    let first_open = columns.iter().position(|status| status.category == StatusCategory::Todo)
+        .or_else(|| columns.iter().position(|status| status.category != StatusCategory::Done));
+    let first_done = columns.iter().position(|status| status.category == StatusCategory::Done);
+
+    let mut board: Vec<BoardColumn> = columns.into_iter()
+        .map(|status| BoardColumn { status, todos: Vec::new() })
+        .collect();
+
+    for todo in results {
+        let column = match todo.status_id {
+            Some(status_id) => board.iter().position(|column| column.status.id == status_id),
+            None if todo.completed => first_done,
+            None => first_open,
+        };
+
+        if let Some(column) = column {
+            board[column].todos.push(todo);
+        }
+    }
+
+    Ok(Json(board))
+}
@@ -0,0 +1,387 @@
+//! Incremental sync for offline-first clients.
+//!
+//! Every write to a todo appends a row to `todo_changes`. The row ids only ever
+//! grow, so the latest id doubles as a sync token: a client that remembers the
+//! token from its last pull asks for the changes after it and gets back the
+//! todos created or updated since then, plus tombstones for deleted ones.
+
+use rocket::http::Status;
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use crate::attachments::{remove_todo_and_files, AttachmentStore, Storage};
+use crate::db::DbPool;
+use crate::etag::IfMatch;
+use crate::events::EventBus;
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::priority::Priority;
+use crate::schema::{shares, todo_changes, todos};
+use crate::sharing::{authorize, visible_to, Permission};
+use crate::todos::{insert_todo, load_details, modify_todo, NewTodoItem, TodoItem};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::{Bool, Text};
+use diesel::sqlite::{Sqlite, SqliteValue};
+use diesel::IntoSql;
+use log::info;
+use chrono::{NaiveDate, NaiveDateTime};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[diesel(sql_type = Text)]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Completed,
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Completed => "completed",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+
+    // Completing a todo is reported separately from other edits
+    pub fn of_update(was_completed: bool, completed: bool) -> Self {
+        if completed && !was_completed {
+            ChangeKind::Completed
+        } else {
+            ChangeKind::Updated
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for ChangeKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.as_str());
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for ChangeKind {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "created" => Ok(ChangeKind::Created),
+            "updated" => Ok(ChangeKind::Updated),
+            "completed" => Ok(ChangeKind::Completed),
+            "deleted" => Ok(ChangeKind::Deleted),
+            other => Err(format!("Unknown change kind: {}", other).into()),
+        }
+    }
+}
+
+#[derive(Queryable, Debug)]
+pub struct TodoChange {
+    pub id: i32,
+    pub todo_id: i32,
+    pub user_id: i32,
+    pub kind: ChangeKind,
+    pub created_at: NaiveDateTime,
+}
+
+// Log a write to a todo that still exists, under its current owner
+pub fn record_change(connection: &mut SqliteConnection, todo_id: i32, kind: ChangeKind) -> QueryResult<usize> {
+    diesel::insert_into(todo_changes::table)
+        .values(todos::table.find(todo_id).select((todos::dsl::id, todos::dsl::user_id, kind.into_sql::<Text>())))
+        .into_columns((todo_changes::dsl::todo_id, todo_changes::dsl::user_id, todo_changes::dsl::kind))
+        .execute(connection)
+}
+
+// Log that a todo has left a user's list, either deleted or handed to someone else
+pub fn record_removal(connection: &mut SqliteConnection, todo_id: i32, user_id: i32) -> QueryResult<usize> {
+    diesel::insert_into(todo_changes::table)
+        .values((
+            todo_changes::dsl::todo_id.eq(todo_id),
+            todo_changes::dsl::user_id.eq(user_id),
+            todo_changes::dsl::kind.eq(ChangeKind::Deleted),
+        ))
+        .execute(connection)
+}
+
+// Filter for the changes a user's clients should hear about: removals logged
+// for that user, and every other change to a todo they can currently see
+pub fn visible_changes(user_id: i32) -> Box<dyn BoxableExpression<todo_changes::table, Sqlite, SqlType = Bool>> {
+    // The same grants `visible_to` looks at
+    let shared_lists = shares::table
+        .filter(shares::dsl::grantee_id.eq(user_id))
+        .filter(shares::dsl::todo_id.is_null())
+        .select(shares::dsl::owner_id);
+    let listed = todos::table
+        .filter(todos::dsl::user_id.eq(user_id).or(todos::dsl::user_id.eq_any(shared_lists)))
+        .select(todos::dsl::id);
+    let shared_todos = shares::table
+        .filter(shares::dsl::grantee_id.eq(user_id))
+        .select(shares::dsl::todo_id);
+
+    Box::new(
+        todo_changes::dsl::kind.eq(ChangeKind::Deleted).and(todo_changes::dsl::user_id.eq(user_id))
+            .or(todo_changes::dsl::kind.ne(ChangeKind::Deleted).and(
+                todo_changes::dsl::todo_id.eq_any(listed)
+                    .or(todo_changes::dsl::todo_id.nullable().eq_any(shared_todos))
+            ))
+    )
+}
+
+// The newest change id, or 0 before anything has changed
+pub fn current_token(connection: &mut SqliteConnection) -> QueryResult<i32> {
+    todo_changes::table
+        .select(diesel::dsl::max(todo_changes::dsl::id))
+        .first::<Option<i32>>(connection)
+        .map(|token| token.unwrap_or(0))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Tombstone {
+    pub id: i32,
+    pub deleted_at: NaiveDateTime,
+}
+
+// Todos in `updated` may still be new to the client (e.g. handed over by another user),
+// so clients should treat both lists as upserts
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SyncResponse {
+    pub token: i32,
+    pub created: Vec<TodoItem>,
+    pub updated: Vec<TodoItem>,
+    pub deleted: Vec<Tombstone>,
+}
+
+// Everything that changed in a user's list after `since`; without a token the whole list is sent
+#[get("/sync?<user_id>&<since>")]
+pub fn get_changes(pool: &State<DbPool>, user_id: i32, since: Option<i32>) -> Result<Json<SyncResponse>, (Status, &'static str)> {
+    info!("Syncing todos for user {} since {:?}", user_id, since);
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    // Take the token first, so a write racing with this pull is sent again next time rather than missed
+    let token = current_token(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch changes"))?;
+    let since = since.unwrap_or(0);
+    if since < 0 || since > token {
+        return Err((Status::BadRequest, "Unknown sync token"));
+    }
+
+    let user_todos = todos::table
+        .filter(visible_to(user_id))
+        .order((todos::dsl::position, todos::dsl::id));
+
+    if since == 0 {
+        let mut created: Vec<TodoItem> = user_todos
+            .load(&mut connection)
+            .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+        load_details(&mut connection, &mut created)
+            .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+        return Ok(Json(SyncResponse { token, created, updated: Vec::new(), deleted: Vec::new() }));
+    }
+
+    let changes: Vec<TodoChange> = todo_changes::table
+        .filter(visible_changes(user_id))
+        .filter(todo_changes::dsl::id.gt(since))
+        .filter(todo_changes::dsl::id.le(token))
+        .order(todo_changes::dsl::id)
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch changes"))?;
+
+    // The first change to a todo says whether it is new, the last whether it still exists
+    let mut first: HashMap<i32, ChangeKind> = HashMap::new();
+    let mut last: HashMap<i32, &TodoChange> = HashMap::new();
+    for change in &changes {
+        first.entry(change.todo_id).or_insert(change.kind);
+        last.insert(change.todo_id, change);
+    }
+
+    let mut deleted: Vec<Tombstone> = last.values()
+        .filter(|change| change.kind == ChangeKind::Deleted)
+        .map(|change| Tombstone { id: change.todo_id, deleted_at: change.created_at })
+        .collect();
+    deleted.sort_by_key(|tombstone| tombstone.id);
+
+    let live: Vec<i32> = last.values()
+        .filter(|change| change.kind != ChangeKind::Deleted)
+        .map(|change| change.todo_id)
+        .collect();
+    let mut changed: Vec<TodoItem> = user_todos
+        .filter(todos::dsl::id.eq_any(&live))
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+    load_details(&mut connection, &mut changed)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+
+    let (created, updated) = changed.into_iter()
+        .partition(|todo| first.get(&todo.id) == Some(&ChangeKind::Created));
+
+    Ok(Json(SyncResponse { token, created, updated, deleted }))
+}
+
+// Todo fields as sent by a client in a sync batch
+#[derive(Deserialize, Debug)]
+pub struct SyncTodo {
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: Option<Priority>,
+    pub due_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub completed: bool,
+    #[serde(default, with = "crate::rfc3339")]
+    pub due_at: Option<NaiveDateTime>,
+    pub estimate_minutes: Option<i32>,
+    #[serde(default, with = "crate::rfc3339")]
+    pub defer_until: Option<NaiveDateTime>,
+}
+
+impl SyncTodo {
+    pub fn for_user(&self, user_id: i32) -> NewTodoItem<'_> {
+        NewTodoItem {
+            title: &self.title,
+            description: self.description.as_deref(),
+            priority: self.priority,
+            due_date: self.due_date,
+            completed: self.completed,
+            user_id,
+            due_at: self.due_at,
+            position: None,
+            estimate_minutes: self.estimate_minutes,
+            defer_until: self.defer_until,
+        }
+    }
+}
+
+// A change made on the client while offline; `version` is the one the client last saw
+#[derive(Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ClientChange {
+    Create { client_id: Option<String>, todo: SyncTodo },
+    Update { id: i32, version: Option<i32>, todo: SyncTodo },
+    Delete { id: i32, version: Option<i32> },
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SyncPush {
+    pub user_id: i32,
+    pub changes: Vec<ClientChange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncOutcome {
+    Applied,
+    Conflict,
+    NotFound,
+    Rejected,
+}
+
+// On a conflict `current` holds the server's copy, for the client to merge with
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SyncResult {
+    pub index: usize,
+    pub client_id: Option<String>,
+    pub id: Option<i32>,
+    pub outcome: SyncOutcome,
+    pub version: Option<i32>,
+    pub error: Option<String>,
+    pub current: Option<TodoItem>,
+}
+
+impl SyncResult {
+    fn new(index: usize, id: Option<i32>, outcome: SyncOutcome) -> Self {
+        SyncResult { index, client_id: None, id, outcome, version: None, error: None, current: None }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SyncPushResponse {
+    pub results: Vec<SyncResult>,
+}
+
+// Turn a failed write into the per-item report the client sees
+fn failed(connection: &mut SqliteConnection, mut result: SyncResult, (status, message): (Status, &'static str)) -> Result<SyncResult, (Status, &'static str)> {
+    result.outcome = if status == Status::PreconditionFailed {
+        SyncOutcome::Conflict
+    } else if status == Status::NotFound {
+        SyncOutcome::NotFound
+    } else {
+        SyncOutcome::Rejected
+    };
+    result.error = Some(message.to_string());
+
+    if result.outcome == SyncOutcome::Conflict {
+        result.current = match result.id {
+            Some(id) => todos::table.find(id).first(connection).optional()
+                .map_err(|_| (Status::InternalServerError, "Failed to fetch todo"))?,
+            None => None,
+        };
+        result.version = result.current.as_ref().map(|todo| todo.version);
+    }
+
+    Ok(result)
+}
+
+fn push_change(connection: &mut SqliteConnection, store: &dyn AttachmentStore, user_id: i32, index: usize, change: &ClientChange) -> Result<SyncResult, (Status, &'static str)> {
+    match change {
+        ClientChange::Create { client_id, todo } => {
+            let mut result = SyncResult::new(index, None, SyncOutcome::Applied);
+            result.client_id = client_id.clone();
+            match insert_todo(connection, &todo.for_user(user_id)) {
+                Ok(todo) => {
+                    result.id = Some(todo.id);
+                    result.version = Some(todo.version);
+                    Ok(result)
+                }
+                Err(err) => failed(connection, result, err),
+            }
+        }
+        ClientChange::Update { id, version, todo } => {
+            let result = SyncResult::new(index, Some(*id), SyncOutcome::Applied);
+            // Editors of a shared list may change its todos, which stay with their owner
+            let owner = match authorize(connection, user_id, *id, Permission::Edit) {
+                Ok(current) => current.user_id,
+                Err(err) => return failed(connection, result, err),
+            };
+
+            match modify_todo(connection, *id, IfMatch(*version), &todo.for_user(owner)) {
+                Ok(version) => Ok(SyncResult { version: Some(version), ..result }),
+                Err(err) => failed(connection, result, err),
+            }
+        }
+        ClientChange::Delete { id, version } => {
+            let result = SyncResult::new(index, Some(*id), SyncOutcome::Applied);
+            if let Err(err) = authorize(connection, user_id, *id, Permission::Own) {
+                return failed(connection, result, err);
+            }
+
+            match remove_todo_and_files(connection, store, *id, IfMatch(*version)) {
+                Ok(_) => Ok(result),
+                Err(err) => failed(connection, result, err),
+            }
+        }
+    }
+}
+
+// Apply a batch of offline changes, reporting the outcome of each; clients pull with
+// GET /sync afterwards to pick up the server's copies
+#[post("/sync", format = "json", data = "<push>")]
+pub fn push_changes(pool: &State<DbPool>, events: &State<EventBus>, storage: &State<Storage>, push: Idempotent<'_, SyncPush>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let response = push.respond(&mut connection, |connection, push| {
+        let push = require_payload(push)?;
+        info!("Applying {} synced changes for user {}", push.changes.len(), push.user_id);
+
+        let results = push.changes.iter()
+            .enumerate()
+            .map(|(index, change)| push_change(connection, storage.store.as_ref(), push.user_id, index, change))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        StoredResponse::json(Status::Ok, &SyncPushResponse { results })
+    });
+
+    events.publish(&mut connection);
+    response
+}
@@ -0,0 +1,365 @@
+//! Todo templates.
+//!
+//! A template is a named checklist of items that each become a todo when the
+//! template is instantiated. Titles and descriptions may contain `{{name}}`
+//! placeholders, filled in from the variables given at instantiation, and an
+//! item's due date is an offset in days from an anchor date, today by
+//! default. Todos here have no tags or subtasks, so a template carries the
+//! fields a todo does have: title, description, priority and estimate.
+
+use rocket::http::Status;
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::events::EventBus;
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::priority::Priority;
+use crate::schema::{template_items, templates};
+use crate::sharing::{authorize, Permission};
+use crate::todos::{insert_todo, load_details, NewTodoItem, TodoItem};
+use crate::user::load_user_settings;
+use diesel::prelude::*;
+use diesel::result::Error;
+use log::info;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use std::collections::HashMap;
+
+const MISSING_VARIABLE: &str = "Missing a value for a template variable";
+// Items can be due up to ten years either side of the day a template is used
+const MAX_DUE_OFFSET_DAYS: i32 = 3650;
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct Template {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct TemplateItem {
+    pub id: i32,
+    pub template_id: i32,
+    pub position: i32,
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: Option<Priority>,
+    pub due_offset_days: Option<i32>,
+    pub estimate_minutes: Option<i32>,
+}
+
+// A template with its items and the variables they use
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TemplateDetails {
+    #[serde(flatten)]
+    pub template: Template,
+    pub items: Vec<TemplateItem>,
+    pub variables: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NewTemplateItem {
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: Option<Priority>,
+    pub due_offset_days: Option<i32>,
+    pub estimate_minutes: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewTemplate {
+    pub user_id: i32,
+    pub name: String,
+    pub items: Vec<NewTemplateItem>,
+}
+
+// Save an existing todo as a single-item template
+#[derive(Deserialize, Debug)]
+pub struct SaveAsTemplate {
+    pub user_id: i32,
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Instantiate {
+    pub user_id: i32,
+    pub anchor: Option<NaiveDate>,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+}
+
+// A placeholder's name, if `inner` (what is between the braces) is one
+fn placeholder(inner: &str) -> Option<&str> {
+    let name = inner.trim();
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    valid.then_some(name)
+}
+
+// The `{{name}}` placeholders in a piece of text, in order of first use
+pub fn variables(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let inner = &rest[start + 2..start + 2 + end];
+        match placeholder(inner) {
+            Some(name) => {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+                rest = &rest[start + 2 + end + 2..];
+            }
+            None => rest = &rest[start + 2..],
+        }
+    }
+    names
+}
+
+// Fill in every placeholder, failing on the first one without a value. Text
+// in braces that isn't a placeholder is left as it is.
+pub fn substitute(text: &str, values: &HashMap<String, String>) -> Result<String, String> {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let inner = &rest[start + 2..start + 2 + end];
+        match placeholder(inner) {
+            Some(name) => {
+                let value = values.get(name).ok_or_else(|| name.to_string())?;
+                output.push_str(&rest[..start]);
+                output.push_str(value);
+                rest = &rest[start + 2 + end + 2..];
+            }
+            None => {
+                output.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+            }
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+fn validate_items(items: &[NewTemplateItem]) -> Result<(), (Status, &'static str)> {
+    if items.is_empty() {
+        return Err((Status::BadRequest, "A template needs at least one item"));
+    }
+    if items.iter().any(|item| item.title.trim().is_empty()) {
+        return Err((Status::BadRequest, "Title cannot be empty"));
+    }
+    if items.iter().any(|item| item.estimate_minutes.is_some_and(|estimate| estimate < 0)) {
+        return Err((Status::BadRequest, "Estimate cannot be negative"));
+    }
+    if items.iter().any(|item| item.due_offset_days.is_some_and(|offset| offset.abs() > MAX_DUE_OFFSET_DAYS)) {
+        return Err((Status::BadRequest, "Due offset must be within ten years"));
+    }
+    Ok(())
+}
+
+fn insert_template(connection: &mut SqliteConnection, user_id: i32, name: &str, items: &[NewTemplateItem]) -> Result<i32, (Status, &'static str)> {
+    if name.trim().is_empty() {
+        return Err((Status::BadRequest, "Template name cannot be empty"));
+    }
+    validate_items(items)?;
+
+    info!("Saving template {:?} with {} items for user {}", name, items.len(), user_id);
+    connection.transaction::<_, Error, _>(|connection| {
+        diesel::insert_into(templates::table)
+            .values((templates::dsl::user_id.eq(user_id), templates::dsl::name.eq(name)))
+            .execute(connection)?;
+        let template_id: i32 = templates::table.select(templates::dsl::id).order(templates::dsl::id.desc()).first(connection)?;
+
+        for (position, item) in items.iter().enumerate() {
+            diesel::insert_into(template_items::table)
+                .values((
+                    template_items::dsl::template_id.eq(template_id),
+                    template_items::dsl::position.eq(position as i32),
+                    template_items::dsl::title.eq(&item.title),
+                    template_items::dsl::description.eq(&item.description),
+                    template_items::dsl::priority.eq(item.priority),
+                    template_items::dsl::due_offset_days.eq(item.due_offset_days),
+                    template_items::dsl::estimate_minutes.eq(item.estimate_minutes),
+                ))
+                .execute(connection)?;
+        }
+        Ok(template_id)
+    }).map_err(|_| (Status::InternalServerError, "Failed to save template"))
+}
+
+fn with_items(connection: &mut SqliteConnection, template: Template) -> QueryResult<TemplateDetails> {
+    let items: Vec<TemplateItem> = template_items::table
+        .filter(template_items::dsl::template_id.eq(template.id))
+        .order(template_items::dsl::position)
+        .load(connection)?;
+
+    let mut names: Vec<String> = Vec::new();
+    for item in &items {
+        for name in variables(&item.title).into_iter().chain(item.description.as_deref().map(variables).unwrap_or_default()) {
+            if !names.iter().any(|known| known == name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+
+    Ok(TemplateDetails { template, items, variables: names })
+}
+
+// Fetch one of the user's templates with its items
+fn owned_template(connection: &mut SqliteConnection, id: i32, user_id: i32) -> Result<TemplateDetails, (Status, &'static str)> {
+    let template: Template = templates::table
+        .find(id)
+        .filter(templates::dsl::user_id.eq(user_id))
+        .first(connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch template"))?
+        .ok_or((Status::NotFound, "Template not found"))?;
+
+    with_items(connection, template).map_err(|_| (Status::InternalServerError, "Failed to fetch template"))
+}
+
+#[post("/templates", format = "json", data = "<new_template>")]
+pub fn add_template(pool: &State<DbPool>, new_template: Idempotent<'_, NewTemplate>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    new_template.respond(&mut connection, |connection, new_template| {
+        let new_template = require_payload(new_template)?;
+        let id = insert_template(connection, new_template.user_id, &new_template.name, &new_template.items)?;
+        StoredResponse::json(Status::Ok, &owned_template(connection, id, new_template.user_id)?)
+    })
+}
+
+// Save a todo the user can see as a template of its own. A due date becomes
+// an offset from the day the todo was created.
+#[post("/todos/<id>/template", format = "json", data = "<request>")]
+pub fn save_as_template(pool: &State<DbPool>, id: i32, request: Idempotent<'_, SaveAsTemplate>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    request.respond(&mut connection, |connection, request| {
+        let request = require_payload(request)?;
+        let todo = authorize(connection, request.user_id, id, Permission::View)?;
+
+        let settings = load_user_settings(connection, todo.user_id)
+            .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))?;
+        let item = NewTemplateItem {
+            title: todo.title,
+            description: todo.description,
+            priority: Some(todo.priority),
+            due_offset_days: todo.due_date.map(|due_date| (due_date - settings.local_date(todo.created_at)).num_days() as i32),
+            estimate_minutes: todo.estimate_minutes,
+        };
+
+        let template_id = insert_template(connection, request.user_id, &request.name, &[item])?;
+        StoredResponse::json(Status::Ok, &owned_template(connection, template_id, request.user_id)?)
+    })
+}
+
+#[get("/templates?<user_id>")]
+pub fn get_templates(pool: &State<DbPool>, user_id: i32) -> Result<Json<Vec<TemplateDetails>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let results = templates::table
+        .filter(templates::dsl::user_id.eq(user_id))
+        .order(templates::dsl::name)
+        .load::<Template>(&mut connection)
+        .and_then(|results| results.into_iter().map(|template| with_items(&mut connection, template)).collect())
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch templates"))?;
+
+    Ok(Json(results))
+}
+
+#[get("/templates/<id>?<user_id>")]
+pub fn get_template(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<Json<TemplateDetails>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    owned_template(&mut connection, id, user_id).map(Json)
+}
+
+#[delete("/templates/<id>?<user_id>")]
+pub fn delete_template(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<&'static str, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    owned_template(&mut connection, id, user_id)?;
+
+    connection.transaction::<_, Error, _>(|connection| {
+        diesel::delete(template_items::table.filter(template_items::dsl::template_id.eq(id))).execute(connection)?;
+        diesel::delete(templates::table.find(id)).execute(connection)
+    }).map_err(|_| (Status::InternalServerError, "Failed to delete template"))?;
+
+    Ok("Template deleted successfully!")
+}
+
+// Create a todo from each of a template's items, all or none
+#[post("/templates/<id>/instantiate", format = "json", data = "<request>")]
+pub fn instantiate_template(pool: &State<DbPool>, events: &State<EventBus>, id: i32, request: Idempotent<'_, Instantiate>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let response = request.respond(&mut connection, |connection, request| {
+        let request = require_payload(request)?;
+        let template = owned_template(connection, id, request.user_id)?;
+
+        let anchor = match request.anchor {
+            Some(anchor) => anchor,
+            None => load_user_settings(connection, request.user_id)
+                .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))?
+                .today(),
+        };
+
+        // Fill in every item before creating anything
+        let mut filled = Vec::with_capacity(template.items.len());
+        for item in &template.items {
+            let title = substitute(&item.title, &request.variables).map_err(|_| (Status::BadRequest, MISSING_VARIABLE))?;
+            let description = item.description.as_deref()
+                .map(|description| substitute(description, &request.variables))
+                .transpose()
+                .map_err(|_| (Status::BadRequest, MISSING_VARIABLE))?;
+            let due_date = item.due_offset_days
+                .map(|offset| anchor.checked_add_signed(Duration::days(offset.into())).ok_or((Status::BadRequest, "Due date is out of range")))
+                .transpose()?;
+            filled.push((title, description, item, due_date));
+        }
+
+        info!("Instantiating template {} for user {}", id, request.user_id);
+        let mut failure = None;
+        let created = connection.transaction::<_, Error, _>(|connection| {
+            let mut created = Vec::with_capacity(filled.len());
+            for (title, description, item, due_date) in &filled {
+                let new_todo = NewTodoItem {
+                    title,
+                    description: description.as_deref(),
+                    priority: item.priority,
+                    due_date: *due_date,
+                    completed: false,
+                    user_id: request.user_id,
+                    due_at: None,
+                    position: None,
+                    estimate_minutes: item.estimate_minutes,
+                    defer_until: None,
+                };
+                match insert_todo(connection, &new_todo) {
+                    Ok(todo) => created.push(todo),
+                    Err(err) => {
+                        failure = Some(err);
+                        return Err(Error::RollbackTransaction);
+                    }
+                }
+            }
+            Ok(created)
+        });
+
+        let mut created: Vec<TodoItem> = match (created, failure) {
+            (Ok(created), _) => created,
+            (Err(_), Some(err)) => return Err(err),
+            (Err(_), None) => return Err((Status::InternalServerError, "Failed to instantiate template")),
+        };
+        load_details(connection, &mut created)
+            .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+
+        StoredResponse::json(Status::Ok, &created)
+    });
+
+    events.publish(&mut connection);
+    response
+}
@@ -0,0 +1,339 @@
+//! Time tracking on todos.
+//!
+//! Time is logged as entries against a todo, either by starting and stopping
+//! a timer or by adding an entry after the fact. Each user can have one
+//! timer running at a time. Durations count in whole minutes, rounded to the
+//! nearest, and an entry belongs to the day it started on in the user's
+//! timezone.
+//!
+//! The timesheet totals a user's finished entries by day and by list. There
+//! are no projects, so a list (the todos of one owner) stands in for one:
+//! work done for someone else happens in the list they shared.
+
+use rocket::http::{ContentType, Status};
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::schema::{time_entries, todos, users};
+use crate::sharing::{authorize, Permission};
+use crate::stats::parse_date;
+use crate::user::load_user_settings;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use log::info;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+
+const DEFAULT_RANGE_DAYS: i64 = 7;
+const TIMER_RUNNING: &str = "A timer is already running";
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct TimeEntry {
+    pub id: i32,
+    pub todo_id: i32,
+    pub user_id: i32,
+    pub started_at: NaiveDateTime,
+    pub ended_at: Option<NaiveDateTime>,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+// Time logged after the fact; both times are RFC 3339
+#[derive(Deserialize, Debug)]
+pub struct NewTimeEntry {
+    pub user_id: i32,
+    #[serde(default, with = "crate::rfc3339")]
+    pub started_at: Option<NaiveDateTime>,
+    #[serde(default, with = "crate::rfc3339")]
+    pub ended_at: Option<NaiveDateTime>,
+    pub note: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TimesheetRow {
+    pub date: NaiveDate,
+    pub list_id: i32,
+    pub todo_id: i32,
+    pub title: String,
+    pub minutes: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TimesheetDay {
+    pub date: NaiveDate,
+    pub minutes: i64,
+}
+
+// `list_id` is the id of the list's owner
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TimesheetList {
+    pub list_id: i32,
+    pub owner: String,
+    pub minutes: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Timesheet {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub total_minutes: i64,
+    pub days: Vec<TimesheetDay>,
+    pub lists: Vec<TimesheetList>,
+    pub rows: Vec<TimesheetRow>,
+}
+
+#[derive(Responder)]
+pub enum TimesheetResponse {
+    Json(Json<Timesheet>),
+    Csv((ContentType, String)),
+}
+
+pub fn duration_minutes(started_at: NaiveDateTime, ended_at: NaiveDateTime) -> i64 {
+    ((ended_at - started_at).num_seconds() + 30).div_euclid(60)
+}
+
+// Minutes logged on each of the given todos in finished entries
+pub fn tracked_minutes(connection: &mut SqliteConnection, todo_ids: &[i32]) -> QueryResult<HashMap<i32, i64>> {
+    let entries: Vec<(i32, NaiveDateTime, Option<NaiveDateTime>)> = time_entries::table
+        .filter(time_entries::dsl::todo_id.eq_any(todo_ids))
+        .filter(time_entries::dsl::ended_at.is_not_null())
+        .select((time_entries::dsl::todo_id, time_entries::dsl::started_at, time_entries::dsl::ended_at))
+        .load(connection)?;
+
+    let mut totals = HashMap::new();
+    for (todo_id, started_at, ended_at) in entries {
+        if let Some(ended_at) = ended_at {
+            *totals.entry(todo_id).or_insert(0) += duration_minutes(started_at, ended_at);
+        }
+    }
+    Ok(totals)
+}
+
+fn running_timer(connection: &mut SqliteConnection, user_id: i32) -> QueryResult<Option<TimeEntry>> {
+    time_entries::table
+        .filter(time_entries::dsl::user_id.eq(user_id))
+        .filter(time_entries::dsl::ended_at.is_null())
+        .first(connection)
+        .optional()
+}
+
+fn insert_entry(connection: &mut SqliteConnection, todo_id: i32, user_id: i32, started_at: NaiveDateTime, ended_at: Option<NaiveDateTime>, note: Option<&str>) -> Result<TimeEntry, (Status, &'static str)> {
+    connection.transaction::<_, DieselError, _>(|connection| {
+        diesel::insert_into(time_entries::table)
+            .values((
+                time_entries::dsl::todo_id.eq(todo_id),
+                time_entries::dsl::user_id.eq(user_id),
+                time_entries::dsl::started_at.eq(started_at),
+                time_entries::dsl::ended_at.eq(ended_at),
+                time_entries::dsl::note.eq(note),
+            ))
+            .execute(connection)?;
+
+        time_entries::table.order(time_entries::dsl::id.desc()).first::<TimeEntry>(connection)
+    }).map_err(|err| match err {
+        // Another request started a timer first
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => (Status::Conflict, TIMER_RUNNING),
+        _ => (Status::InternalServerError, "Failed to add time entry"),
+    })
+}
+
+// Start a timer on a todo the user can edit. The request has no body, so
+// only the idempotency key is looked at.
+#[post("/todos/<id>/timer?<user_id>", data = "<request>")]
+pub fn start_timer(pool: &State<DbPool>, id: i32, user_id: i32, request: Idempotent<'_, ()>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    request.respond(&mut connection, |connection, _| {
+        authorize(connection, user_id, id, Permission::Edit)?;
+
+        let running = running_timer(connection, user_id)
+            .map_err(|_| (Status::InternalServerError, "Failed to fetch timer"))?;
+        if running.is_some() {
+            return Err((Status::Conflict, TIMER_RUNNING));
+        }
+
+        info!("User {} starting a timer on to-do item {}", user_id, id);
+        let entry = insert_entry(connection, id, user_id, Utc::now().naive_utc(), None, None)?;
+        StoredResponse::json(Status::Ok, &entry)
+    })
+}
+
+// The user's running timer, if any
+#[get("/timer?<user_id>")]
+pub fn get_timer(pool: &State<DbPool>, user_id: i32) -> Result<Json<Option<TimeEntry>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    running_timer(&mut connection, user_id)
+        .map(Json)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch timer"))
+}
+
+// Stop the user's running timer, returning the finished entry
+#[delete("/timer?<user_id>")]
+pub fn stop_timer(pool: &State<DbPool>, user_id: i32) -> Result<Json<TimeEntry>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let running = running_timer(&mut connection, user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch timer"))?
+        .ok_or((Status::NotFound, "No timer is running"))?;
+
+    info!("User {} stopping the timer on to-do item {}", user_id, running.todo_id);
+    let target = time_entries::table.find(running.id);
+    diesel::update(target)
+        .set(time_entries::dsl::ended_at.eq(Utc::now().naive_utc()))
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to stop timer"))?;
+
+    target.first(&mut connection)
+        .map(Json)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch timer"))
+}
+
+// Log time spent on a todo without a timer
+#[post("/todos/<id>/time", format = "json", data = "<entry>")]
+pub fn add_time_entry(pool: &State<DbPool>, id: i32, entry: Idempotent<'_, NewTimeEntry>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    entry.respond(&mut connection, |connection, entry| {
+        let entry = require_payload(entry)?;
+        let (Some(started_at), Some(ended_at)) = (entry.started_at, entry.ended_at) else {
+            return Err((Status::BadRequest, "Start and end times are required"));
+        };
+        if ended_at <= started_at {
+            return Err((Status::BadRequest, "End time must be after start time"));
+        }
+
+        authorize(connection, entry.user_id, id, Permission::Edit)?;
+
+        info!("User {} logging time on to-do item {}", entry.user_id, id);
+        let entry = insert_entry(connection, id, entry.user_id, started_at, Some(ended_at), entry.note.as_deref())?;
+        StoredResponse::json(Status::Ok, &entry)
+    })
+}
+
+// Everyone's time on a todo, oldest first
+#[get("/todos/<id>/time?<user_id>")]
+pub fn get_time_entries(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<Json<Vec<TimeEntry>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    authorize(&mut connection, user_id, id, Permission::View)?;
+
+    let results = time_entries::table
+        .filter(time_entries::dsl::todo_id.eq(id))
+        .order((time_entries::dsl::started_at, time_entries::dsl::id))
+        .load::<TimeEntry>(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch time entries"))?;
+
+    Ok(Json(results))
+}
+
+// Delete one of the user's own entries
+#[delete("/time/<id>?<user_id>")]
+pub fn delete_time_entry(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<&'static str, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let deleted = diesel::delete(time_entries::table.find(id).filter(time_entries::dsl::user_id.eq(user_id)))
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to delete time entry"))?;
+
+    if deleted == 0 {
+        return Err((Status::NotFound, "Time entry not found"));
+    }
+
+    Ok("Time entry deleted successfully!")
+}
+
+// Quote a CSV field when it needs it
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn to_csv(timesheet: &Timesheet) -> String {
+    let mut csv = String::from("date,list_id,todo_id,title,minutes\n");
+    for row in &timesheet.rows {
+        csv.push_str(&format!("{},{},{},{},{}\n", row.date, row.list_id, row.todo_id, csv_field(&row.title), row.minutes));
+    }
+    csv
+}
+
+// A user's finished time over a date range, by day and by list, as JSON or CSV
+#[get("/timesheet?<user_id>&<from>&<to>&<format>")]
+pub fn get_timesheet(pool: &State<DbPool>, user_id: i32, from: Option<String>, to: Option<String>, format: Option<String>) -> Result<TimesheetResponse, (Status, &'static str)> {
+    let csv = match format.as_deref() {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(_) => return Err((Status::BadRequest, "Format must be json or csv")),
+    };
+
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    let settings = load_user_settings(&mut connection, user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))?;
+
+    let to = parse_date(to)?.unwrap_or(settings.today());
+    let from = parse_date(from)?.unwrap_or(to - Duration::days(DEFAULT_RANGE_DAYS - 1));
+    if from > to {
+        return Err((Status::BadRequest, "From date must not be after to date"));
+    }
+
+    // Fetch a day either side to cover any timezone, then keep what started in range locally
+    let entries: Vec<(NaiveDateTime, Option<NaiveDateTime>, i32, String, i32)> = time_entries::table
+        .inner_join(todos::table)
+        .filter(time_entries::dsl::user_id.eq(user_id))
+        .filter(time_entries::dsl::ended_at.is_not_null())
+        .filter(time_entries::dsl::started_at.ge((from - Duration::days(1)).and_hms_opt(0, 0, 0).unwrap()))
+        .filter(time_entries::dsl::started_at.lt((to + Duration::days(2)).and_hms_opt(0, 0, 0).unwrap()))
+        .select((time_entries::dsl::started_at, time_entries::dsl::ended_at, todos::dsl::id, todos::dsl::title, todos::dsl::user_id))
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch time entries"))?;
+
+    let mut by_row: BTreeMap<(NaiveDate, i32, i32), (String, i64)> = BTreeMap::new();
+    for (started_at, ended_at, todo_id, title, list_id) in entries {
+        let date = settings.local_date(started_at);
+        let Some(ended_at) = ended_at.filter(|_| from <= date && date <= to) else {
+            continue;
+        };
+        by_row.entry((date, list_id, todo_id)).or_insert((title, 0)).1 += duration_minutes(started_at, ended_at);
+    }
+
+    let rows: Vec<TimesheetRow> = by_row.into_iter()
+        .map(|((date, list_id, todo_id), (title, minutes))| TimesheetRow { date, list_id, todo_id, title, minutes })
+        .collect();
+
+    let mut by_day: BTreeMap<NaiveDate, i64> = BTreeMap::new();
+    let mut by_list: BTreeMap<i32, i64> = BTreeMap::new();
+    for row in &rows {
+        *by_day.entry(row.date).or_insert(0) += row.minutes;
+        *by_list.entry(row.list_id).or_insert(0) += row.minutes;
+    }
+
+    let owner_ids: Vec<i32> = by_list.keys().copied().collect();
+    let owners: HashMap<i32, String> = users::table
+        .filter(users::dsl::id.eq_any(&owner_ids))
+        .select((users::dsl::id, users::dsl::username))
+        .load::<(i32, String)>(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch users"))?
+        .into_iter()
+        .collect();
+
+    let timesheet = Timesheet {
+        from,
+        to,
+        total_minutes: rows.iter().map(|row| row.minutes).sum(),
+        days: by_day.into_iter().map(|(date, minutes)| TimesheetDay { date, minutes }).collect(),
+        lists: by_list.into_iter()
+            .map(|(list_id, minutes)| TimesheetList { list_id, owner: owners.get(&list_id).cloned().unwrap_or_default(), minutes })
+            .collect(),
+        rows,
+    };
+
+    if csv {
+        Ok(TimesheetResponse::Csv((ContentType::CSV, to_csv(&timesheet))))
+    } else {
+        Ok(TimesheetResponse::Json(Json(timesheet)))
+    }
+}
@@ -1,22 +1,99 @@
 use rocket::http::Status;
 use rocket::State;
-use rocket::serde::json::Json;
+use rocket::serde::json::{self, Json};
 use serde::{Serialize, Deserialize};
+use crate::attachments::{remove_todo_and_files, Storage};
 use crate::db::DbPool;
-use crate::schema::todos;
+use crate::events::EventBus;
+use crate::etag::{check_version, current_version, IfMatch, Tagged, PRECONDITION_FAILED};
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::ordering::{key_between, next_position, rebalance};
+use crate::priority::{Priority, INVALID_PRIORITY};
+use crate::rules::run_rules;
+use crate::schema::{attachments, comments, notifications, shares, time_entries, todos, users};
+use crate::sharing::{authorize, grantees, permission, visible_to, Permission};
+use crate::sync::{record_change, record_removal, ChangeKind};
+use crate::time_tracking::tracked_minutes;
+use crate::user::{load_user_settings, PublicUser};
+use diesel::deserialize;
 use diesel::prelude::*;
+use diesel::sql_types::Bool;
+use diesel::sqlite::Sqlite;
 use log::info;
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
 
-#[derive(Queryable, Serialize, Deserialize, Debug)]
+const INVALID_ESTIMATE: &str = "Estimate cannot be negative";
+
+// Loaded from a todos row by the `Queryable` impl below. The fields after
+// the columns are left empty until `load_details` fills them in.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TodoItem {
     pub id: i32,
     pub title: String,
     pub description: Option<String>,
-    pub priority: Option<i32>,
+    pub priority: Priority,
     pub due_date: Option<NaiveDate>,
     pub completed: bool,
     pub user_id: i32,
+    pub created_at: NaiveDateTime,
+    pub completed_at: Option<NaiveDateTime>,
+    #[serde(default, with = "crate::rfc3339")]
+    pub due_at: Option<NaiveDateTime>,
+    pub status_id: Option<i32>,
+    pub position: String,
+    pub version: i32,
+    pub assignee_id: Option<i32>,
+    pub estimate_minutes: Option<i32>,
+    #[serde(default, with = "crate::rfc3339")]
+    pub defer_until: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub assignee: Option<PublicUser>,
+    #[serde(default)]
+    pub comment_count: i64,
+    #[serde(default)]
+    pub tracked_minutes: i64,  // From finished time entries only
+}
+
+type TodoRow = (i32, String, Option<String>, Priority, Option<NaiveDate>, bool, i32, NaiveDateTime, Option<NaiveDateTime>, Option<NaiveDateTime>, Option<i32>, String, i32, Option<i32>, Option<i32>, Option<NaiveDateTime>);
+
+impl Queryable<todos::SqlType, Sqlite> for TodoItem {
+    type Row = TodoRow;
+
+    fn build(row: Self::Row) -> deserialize::Result<Self> {
+        let (id, title, description, priority, due_date, completed, user_id, created_at, completed_at, due_at, status_id, position, version, assignee_id, estimate_minutes, defer_until) = row;
+        Ok(TodoItem {
+            id, title, description, priority, due_date, completed, user_id, created_at, completed_at, due_at, status_id, position, version, assignee_id, estimate_minutes, defer_until,
+            assignee: None,
+            comment_count: 0,
+            tracked_minutes: 0,
+        })
+    }
+}
+
+// Fill in what a todo response carries beyond its own columns
+pub fn load_details(connection: &mut SqliteConnection, todos: &mut [TodoItem]) -> QueryResult<()> {
+    let assignee_ids: Vec<i32> = todos.iter().filter_map(|todo| todo.assignee_id).collect();
+    let assignees: Vec<PublicUser> = users::table
+        .filter(users::dsl::id.eq_any(&assignee_ids))
+        .select((users::dsl::id, users::dsl::username))
+        .load(connection)?;
+
+    let ids: Vec<i32> = todos.iter().map(|todo| todo.id).collect();
+    let comment_counts: Vec<(i32, i64)> = comments::table
+        .filter(comments::dsl::todo_id.eq_any(&ids))
+        .group_by(comments::dsl::todo_id)
+        .select((comments::dsl::todo_id, diesel::dsl::count_star()))
+        .load(connection)?;
+
+    let tracked = tracked_minutes(connection, &ids)?;
+
+    for todo in todos.iter_mut() {
+        todo.assignee = assignees.iter().find(|user| Some(user.id) == todo.assignee_id).cloned();
+        todo.comment_count = comment_counts.iter().find(|(id, _)| *id == todo.id).map_or(0, |(_, count)| *count);
+        todo.tracked_minutes = tracked.get(&todo.id).copied().unwrap_or(0);
+    }
+
+    Ok(())
 }
 
 #[derive(Insertable, Deserialize, Debug)]
@@ -24,28 +101,93 @@ pub struct TodoItem {
 pub struct NewTodoItem<'a> {
     pub title: &'a str,
     pub description: Option<&'a str>,
-    pub priority: Option<i32>,
+    pub priority: Option<Priority>,
     pub due_date: Option<NaiveDate>,
     pub completed: bool,
     pub user_id: i32,  // Associate the new todo with a user
+    #[serde(default, with = "crate::rfc3339")]
+    pub due_at: Option<NaiveDateTime>,  // Precise deadline, given in RFC 3339
+    #[serde(skip_deserializing)]
+    pub position: Option<String>,  // Assigned by the server, at the end of the user's list
+    pub estimate_minutes: Option<i32>,
+    #[serde(default, with = "crate::rfc3339")]
+    pub defer_until: Option<NaiveDateTime>,  // Hidden from listings and views until then
+}
+
+// Place a todo directly before and/or after other todos in the same list
+#[derive(Deserialize, Debug)]
+pub struct MoveTodo {
+    pub before: Option<i32>,
+    pub after: Option<i32>,
+}
+
+// Filter for open todos past their deadline: the precise due time when one is set, otherwise the due date
+pub fn overdue(today: NaiveDate, now: NaiveDateTime) -> Box<dyn BoxableExpression<todos::table, Sqlite, SqlType = Bool>> {
+    use crate::schema::todos::dsl::*;
+
+    Box::new(
+        completed.eq(false).and(
+            due_at.is_not_null().and(due_at.assume_not_null().lt(now))
+                .or(due_at.is_null().and(due_date.is_not_null()).and(due_date.assume_not_null().lt(today)))
+        )
+    )
 }
 
-// Fetch all to-do items from the database
-#[get("/todos")]
-pub fn get_todos(pool: &State<DbPool>) -> Result<Json<Vec<TodoItem>>, (Status, &'static str)> {
+// Filter for todos that aren't deferred past the given moment
+pub fn not_deferred(now: NaiveDateTime) -> Box<dyn BoxableExpression<todos::table, Sqlite, SqlType = Bool>> {
+    use crate::schema::todos::dsl::*;
+
+    Box::new(defer_until.is_null().or(defer_until.assume_not_null().le(now)))
+}
+
+// Turn a malformed todo body into a validation error, calling out bad priorities specifically
+fn validate_payload<'r>(payload: Result<Json<NewTodoItem<'r>>, json::Error<'r>>) -> Result<Json<NewTodoItem<'r>>, (Status, &'static str)> {
+    payload.map_err(|err| {
+        error!("Rejected todo payload: {:?}", err);
+        match err {
+            json::Error::Parse(_, err) if err.to_string().contains(INVALID_PRIORITY) => (Status::UnprocessableEntity, INVALID_PRIORITY),
+            _ => (Status::UnprocessableEntity, "Invalid todo payload"),
+        }
+    })
+}
+
+// Fetch the to-do items a user can see; deferred todos are left out unless asked for
+#[get("/todos?<user_id>&<deferred>")]
+pub fn get_todos(pool: &State<DbPool>, user_id: i32, deferred: Option<bool>) -> Result<Json<Vec<TodoItem>>, (Status, &'static str)> {
     info!("Fetching all to-do items");
     let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
     
-    let todos: Vec<TodoItem> = todos::table.load(&mut connection)
+    let mut query = todos::table
+        .filter(visible_to(user_id))
+        .order((todos::dsl::user_id, todos::dsl::position, todos::dsl::id))
+        .into_boxed();
+    if deferred != Some(true) {
+        query = query.filter(not_deferred(Utc::now().naive_utc()));
+    }
+    let mut todos: Vec<TodoItem> = query
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+    load_details(&mut connection, &mut todos)
         .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
 
     info!("Fetched {} to-do items", todos.len());
     Ok(Json(todos))
 }
 
-// Add a new to-do item to the database
-#[post("/todos", format = "json", data = "<new_todo>")]
-pub fn add_todo(pool: &State<DbPool>, new_todo: Json<NewTodoItem>) -> Result<&'static str, (Status, &'static str)> {
+// Fetch a single to-do item, tagged with its current version
+#[get("/todos/<id>?<user_id>")]
+pub fn get_todo(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<Tagged<Json<TodoItem>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let mut todo = authorize(&mut connection, user_id, id, Permission::View)?;
+    load_details(&mut connection, std::slice::from_mut(&mut todo))
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todo"))?;
+
+    Ok(Tagged { version: todo.version, inner: Json(todo) })
+}
+
+// Validate and insert a new todo at the end of its owner's list
+pub fn insert_todo(connection: &mut SqliteConnection, new_todo: &NewTodoItem) -> Result<TodoItem, (Status, &'static str)> {
     if new_todo.title.trim().is_empty() {
         return Err((Status::BadRequest, "Title cannot be empty"));
     }
@@ -54,60 +196,156 @@ pub fn add_todo(pool: &State<DbPool>, new_todo: Json<NewTodoItem>) -> Result<&'s
         return Err((Status::BadRequest, "New todo item cannot be marked as completed"));
     }
 
+    if new_todo.estimate_minutes.is_some_and(|estimate| estimate < 0) {
+        return Err((Status::BadRequest, INVALID_ESTIMATE));
+    }
+
     info!("Adding a new to-do item: {:?}", new_todo);
-    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
-    let new_todo = NewTodoItem { title: &new_todo.title, completed: new_todo.completed, user_id: new_todo.user_id, description: new_todo.description, priority: new_todo.priority, due_date: new_todo.due_date };
-    
-    diesel::insert_into(todos::table)
-        .values(&new_todo)
-        .execute(&mut connection)
+    // Fall back to the user's preferred priority when none is given
+    let settings = load_user_settings(connection, new_todo.user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))?;
+    let priority = new_todo.priority.or(settings.default_priority).unwrap_or_default();
+
+    // A precise deadline determines the due date in the user's timezone
+    let due_date = new_todo.due_at.map(|due_at| settings.local_date(due_at)).or(new_todo.due_date);
+
+    let position = next_position(connection, new_todo.user_id)
         .map_err(|_| (Status::InternalServerError, "Failed to add todo"))?;
-    
-    Ok("Todo added successfully!")
+
+    let new_todo = NewTodoItem { title: new_todo.title, completed: new_todo.completed, user_id: new_todo.user_id, description: new_todo.description, priority: Some(priority), due_date, due_at: new_todo.due_at, position: Some(position), estimate_minutes: new_todo.estimate_minutes, defer_until: new_todo.defer_until };
+
+    let todo = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+        diesel::insert_into(todos::table)
+            .values(&new_todo)
+            .execute(connection)?;
+
+        let todo: TodoItem = todos::table.order(todos::dsl::id.desc()).first(connection)?;
+        record_change(connection, todo.id, ChangeKind::Created)?;
+        Ok(todo)
+    }).map_err(|_| (Status::InternalServerError, "Failed to add todo"))?;
+
+    Ok(run_rules(connection, todo, ChangeKind::Created))
+}
+
+// Add a new to-do item to the database
+#[post("/todos", format = "json", data = "<new_todo>")]
+pub fn add_todo(pool: &State<DbPool>, events: &State<EventBus>, new_todo: Idempotent<'_, NewTodoItem<'_>>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let response = new_todo.respond(&mut connection, |connection, new_todo| {
+        let new_todo = validate_payload(new_todo)?;
+        insert_todo(connection, &new_todo)?;
+
+        Ok(StoredResponse::text(Status::Ok, "Todo added successfully!"))
+    });
+
+    events.publish(&mut connection);
+    response
+}
+
+// Clear out everything attached to a todo that was just deleted and tell the
+// clients of everyone who could see it that it is gone. Stored files are left
+// for the caller to release.
+pub fn remove_dependents(connection: &mut SqliteConnection, id: i32, user_id: i32) -> QueryResult<()> {
+    for grantee_id in grantees(connection, id, user_id)? {
+        record_removal(connection, id, grantee_id)?;
+    }
+    diesel::delete(attachments::table.filter(attachments::dsl::todo_id.eq(id))).execute(connection)?;
+    diesel::delete(comments::table.filter(comments::dsl::todo_id.eq(id))).execute(connection)?;
+    diesel::delete(notifications::table.filter(notifications::dsl::todo_id.eq(id))).execute(connection)?;
+    diesel::delete(time_entries::table.filter(time_entries::dsl::todo_id.eq(id))).execute(connection)?;
+    diesel::delete(shares::table.filter(shares::dsl::todo_id.eq(id))).execute(connection)?;
+    record_removal(connection, id, user_id)?;
+    Ok(())
 }
 
-// Delete a to-do item
-#[delete("/todos/<id>")]
-pub fn delete_todo(pool: &State<DbPool>, id: i32) -> Result<&'static str, (Status, &'static str)> {
+// Delete a todo, returning whether there was one to delete
+pub fn remove_todo(connection: &mut SqliteConnection, id: i32, if_match: IfMatch) -> Result<bool, (Status, &'static str)> {
+    let existing: Option<(i32, i32)> = todos::table
+        .find(id)
+        .select((todos::dsl::user_id, todos::dsl::version))
+        .first(connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todo"))?;
+
+    let (user_id, version) = match existing {
+        Some(existing) => existing,
+        None if if_match.0.is_some() => return Err((Status::NotFound, "Todo item not found")),
+        None => return Ok(false),
+    };
+
+    // Only delete the version the client has seen
+    check_version(if_match, version)?;
+
+    let deleted = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+        let deleted = match if_match {
+            IfMatch(Some(expected)) => diesel::delete(todos::table.find(id).filter(todos::dsl::version.eq(expected))).execute(connection)?,
+            IfMatch(None) => diesel::delete(todos::table.find(id)).execute(connection)?,
+        };
+
+        if deleted > 0 {
+            remove_dependents(connection, id, user_id)?;
+        }
+        Ok(deleted)
+    })
+    .map_err(|_| (Status::InternalServerError, "Failed to delete todo"))?;
+
+    // Someone else changed the todo between the version check and the delete
+    if deleted == 0 {
+        return Err((Status::PreconditionFailed, PRECONDITION_FAILED));
+    }
+
+    Ok(true)
+}
+
+// Delete a to-do item; only its owner can
+#[delete("/todos/<id>?<user_id>")]
+pub fn delete_todo(pool: &State<DbPool>, events: &State<EventBus>, storage: &State<Storage>, id: i32, user_id: i32, if_match: IfMatch) -> Result<&'static str, (Status, &'static str)> {
     info!("Deleting to-do item with id: {}", id);
     let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
-    
-    diesel::delete(todos::table.find(id))
-        .execute(&mut connection)
-        .map_err(|_| (Status::InternalServerError, "Failed to delete todo"))?;
-    
+
+    authorize(&mut connection, user_id, id, Permission::Own)?;
+
+    remove_todo_and_files(&mut connection, storage.store.as_ref(), id, if_match)?;
+    events.publish(&mut connection);
+
     Ok("Todo deleted successfully!")
 }
 
-#[put("/todos/<id>", format = "json", data = "<updated_todo>")]
-pub fn update_todo(
-    pool: &State<DbPool>, 
-    id: i32, 
-    updated_todo: Json<NewTodoItem>
-) -> Result<&'static str, (Status, &'static str)> {
+// Replace a todo's fields, returning its new version
+pub fn modify_todo(connection: &mut SqliteConnection, id: i32, if_match: IfMatch, updated_todo: &NewTodoItem) -> Result<i32, (Status, &'static str)> {
     if updated_todo.title.trim().is_empty() {
         return Err((Status::BadRequest, "Title cannot be empty"));
     }
 
+    if updated_todo.estimate_minutes.is_some_and(|estimate| estimate < 0) {
+        return Err((Status::BadRequest, INVALID_ESTIMATE));
+    }
+
     info!("Updating to-do item with id: {}", id);
     info!("Updated to-do item: {:?}", updated_todo);
-    let mut connection = pool.get().map_err(|err| {
-        error!("Failed to get connection from pool: {:?}", err);
-        (Status::InternalServerError, "Failed to get connection from pool")
-    })?;
 
     // Get the existing todo item from the database
     let target = todos::table.find(id);
 
-    let existing_todo: Option<TodoItem> = target.first(&mut connection).optional()
+    let existing_todo: Option<TodoItem> = target.first(connection).optional()
     .map_err(|err| {
         error!("Failed to fetch todo: {:?}", err);
         (Status::InternalServerError, "Failed to fetch todo")
     })?;
 
-    if existing_todo.is_none() {
-        return Err((Status::NotFound, "Todo item not found"));
-    }
+    let existing_todo = match existing_todo {
+        Some(todo) => todo,
+        None => return Err((Status::NotFound, "Todo item not found")),
+    };
+
+    check_version(if_match, existing_todo.version)?;
+
+    let settings = load_user_settings(connection, existing_todo.user_id)
+        .map_err(|err| {
+            error!("Failed to fetch user settings: {:?}", err);
+            (Status::InternalServerError, "Failed to fetch user settings")
+        })?;
 
     // Create updated data based on existing and new values
     let updated_data = NewTodoItem {
@@ -116,35 +354,150 @@ pub fn update_todo(
         user_id: updated_todo.user_id,
         description: updated_todo.description,
         priority: updated_todo.priority,
-        due_date: updated_todo.due_date,
+        due_date: updated_todo.due_at.map(|due_at| settings.local_date(due_at)).or(updated_todo.due_date),
+        due_at: updated_todo.due_at,
+        position: None,
+        estimate_minutes: updated_todo.estimate_minutes,
+        defer_until: updated_todo.defer_until,
     };
 
-    // Update the todo in the database and log any potential errors
-    diesel::update(target)
-        .set((
-            todos::dsl::title.eq(updated_data.title),
-            todos::dsl::completed.eq(updated_data.completed),
-        ))
-        .execute(&mut connection)
-        .map_err(|err| {
-            error!("Failed to update todo in the database: {:?}", err);
-            (Status::InternalServerError, "Failed to update todo")
-        })?;
+    // Keep the original completion time unless the item is being reopened
+    let completed_at = if updated_data.completed {
+        existing_todo.completed_at.or_else(|| Some(Utc::now().naive_utc()))
+    } else {
+        None
+    };
+
+    // Completing or reopening directly takes the todo out of its status column, and
+    // handing it to someone else takes it off the old owner's board
+    let status_id = if updated_data.completed == existing_todo.completed && updated_data.user_id == existing_todo.user_id {
+        existing_todo.status_id
+    } else {
+        None
+    };
+
+    // Update the todo in the database and log any potential errors; a concurrent write
+    // since the todo was fetched leaves nothing to update
+    let updated = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+        let handed_over = existing_todo.user_id != updated_data.user_id;
+        let mut old_viewers = if handed_over { grantees(connection, id, existing_todo.user_id)? } else { Vec::new() };
+        old_viewers.push(existing_todo.user_id);
+
+        let updated = diesel::update(target.filter(todos::dsl::version.eq(existing_todo.version)))
+            .set((
+                todos::dsl::title.eq(updated_data.title),
+                todos::dsl::user_id.eq(updated_data.user_id),
+                todos::dsl::description.eq(updated_data.description),
+                todos::dsl::priority.eq(updated_data.priority.unwrap_or_default()),
+                todos::dsl::due_date.eq(updated_data.due_date),
+                todos::dsl::due_at.eq(updated_data.due_at),
+                todos::dsl::estimate_minutes.eq(updated_data.estimate_minutes),
+                todos::dsl::defer_until.eq(updated_data.defer_until),
+                todos::dsl::completed.eq(updated_data.completed),
+                todos::dsl::completed_at.eq(completed_at),
+                todos::dsl::status_id.eq(status_id),
+                todos::dsl::version.eq(existing_todo.version + 1),
+            ))
+            .execute(connection)?;
 
-    Ok("Todo updated successfully!")
+        if updated > 0 {
+            record_change(connection, id, ChangeKind::of_update(existing_todo.completed, updated_data.completed))?;
+            // The previous owner's clients should drop the todo, as should those
+            // of anyone who could only see it through the old owner's list
+            if handed_over {
+                let todo: TodoItem = target.first(connection)?;
+                for viewer_id in old_viewers {
+                    if permission(connection, viewer_id, &todo)?.is_none() {
+                        record_removal(connection, id, viewer_id)?;
+                    }
+                }
+            }
+        }
+        Ok(updated)
+    })
+    .map_err(|err| {
+        error!("Failed to update todo in the database: {:?}", err);
+        (Status::InternalServerError, "Failed to update todo")
+    })?;
+
+    if updated == 0 {
+        return Err((Status::PreconditionFailed, PRECONDITION_FAILED));
+    }
+
+    let todo: TodoItem = target.first(connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todo"))?;
+    Ok(run_rules(connection, todo, ChangeKind::of_update(existing_todo.completed, updated_data.completed)).version)
+}
+
+// Replace a todo's fields; editors can change anything but the owner
+#[put("/todos/<id>?<user_id>", format = "json", data = "<updated_todo>")]
+pub fn update_todo(
+    pool: &State<DbPool>, 
+    events: &State<EventBus>,
+    id: i32, 
+    user_id: i32,
+    if_match: IfMatch,
+    updated_todo: Result<Json<NewTodoItem<'_>>, json::Error<'_>>
+) -> Result<Tagged<&'static str>, (Status, &'static str)> {
+    let updated_todo = validate_payload(updated_todo)?;
+    let mut connection = pool.get().map_err(|err| {
+        error!("Failed to get connection from pool: {:?}", err);
+        (Status::InternalServerError, "Failed to get connection from pool")
+    })?;
+
+    let todo = authorize(&mut connection, user_id, id, Permission::Edit)?;
+    if todo.user_id != updated_todo.user_id {
+        authorize(&mut connection, user_id, id, Permission::Own)?;
+    }
+
+    let version = modify_todo(&mut connection, id, if_match, &updated_todo)?;
+    events.publish(&mut connection);
+
+    Ok(Tagged { version, inner: "Todo updated successfully!" })
+}
+
+// Complete a todo if it is still open, returning whether anything changed
+pub fn mark_completed(connection: &mut SqliteConnection, id: i32, if_match: IfMatch) -> Result<bool, (Status, &'static str)> {
+    if if_match.0.is_some() {
+        check_version(if_match, current_version(connection, id)?)?;
+    }
+
+    // Update the completed status of the todo, keeping the first completion time
+    let target = todos::table.find(id).filter(todos::dsl::completed.eq(false));
+    let update = (
+        todos::dsl::completed.eq(true),
+        todos::dsl::completed_at.eq(Utc::now().naive_utc()),
+        todos::dsl::status_id.eq(None::<i32>),
+        todos::dsl::version.eq(todos::dsl::version + 1),
+    );
+    let updated = match if_match {
+        IfMatch(Some(expected)) => diesel::update(target.filter(todos::dsl::version.eq(expected))).set(update).execute(connection),
+        IfMatch(None) => diesel::update(target).set(update).execute(connection),
+    }
+    .map_err(|_| (Status::InternalServerError, "Failed to complete todo"))?;
+
+    if updated > 0 {
+        record_change(connection, id, ChangeKind::Completed)
+            .map_err(|_| (Status::InternalServerError, "Failed to complete todo"))?;
+        let todo: TodoItem = todos::table.find(id).first(connection)
+            .map_err(|_| (Status::InternalServerError, "Failed to fetch todo"))?;
+        run_rules(connection, todo, ChangeKind::Completed);
+    }
+
+    Ok(updated > 0)
 }
 
 // Mark a to-do item as completed
-#[put("/todos/<id>/complete")]
-pub fn complete_todo(pool: &State<DbPool>, id: i32) -> Result<&'static str, (Status, &'static str)> {
+#[put("/todos/<id>/complete?<user_id>")]
+pub fn complete_todo(pool: &State<DbPool>, events: &State<EventBus>, id: i32, user_id: i32, if_match: IfMatch) -> Result<&'static str, (Status, &'static str)> {
     info!("Marking to-do item with id: {} as completed", id);
     let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
 
-    // Update the completed status of the todo
-    diesel::update(todos::table.find(id))
-        .set(todos::dsl::completed.eq(true))
-        .execute(&mut connection)
-        .map_err(|_| (Status::InternalServerError, "Failed to complete todo"))?;
+    authorize(&mut connection, user_id, id, Permission::Edit)?;
+
+    if mark_completed(&mut connection, id, if_match)? {
+        events.publish(&mut connection);
+    }
 
     Ok("Todo marked as completed!")
 }
@@ -153,18 +506,134 @@ pub fn complete_todo(pool: &State<DbPool>, id: i32) -> Result<&'static str, (Sta
 pub fn search_todos(pool: &State<DbPool>, query: Option<String>, user_id: i32) -> Result<Json<Vec<TodoItem>>, (Status, &'static str)> {
     let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
 
-    let results: Vec<TodoItem> = if let Some(query) = query {
+    let mut results: Vec<TodoItem> = if let Some(query) = query {
         todos::table
             .filter(todos::dsl::title.like(format!("%{}%", query)))  // Search by title
-            .filter(todos::dsl::user_id.eq(user_id))  // Ensure user_id matches
+            .filter(visible_to(user_id))  // Only todos the user owns or was shared
+            .order((todos::dsl::position, todos::dsl::id))
             .load(&mut connection)
             .map_err(|_| (Status::InternalServerError, "Failed to search todos"))?
     } else {
         todos::table
-            .filter(todos::dsl::user_id.eq(user_id))  // Fetch todos only for the user
+            .filter(visible_to(user_id))  // Fetch todos only for the user
+            .order((todos::dsl::position, todos::dsl::id))
             .load(&mut connection)
             .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?
     };
 
+    load_details(&mut connection, &mut results)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+
     Ok(Json(results))
+}
+
+// Fetch a todo that must be in the given user's list
+fn neighbour(connection: &mut SqliteConnection, id: i32, user_id: i32) -> Result<TodoItem, (Status, &'static str)> {
+    let todo: TodoItem = todos::table
+        .find(id)
+        .first(connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todo"))?
+        .ok_or((Status::NotFound, "Neighbouring todo not found"))?;
+
+    if todo.user_id != user_id {
+        return Err((Status::BadRequest, "Neighbouring todo is in a different list"));
+    }
+
+    Ok(todo)
+}
+
+// Position keys on either side of a spot in a list; `None` is the start or end of the list
+type Neighbours = (Option<String>, Option<String>);
+
+// Work out the keys on either side of the requested spot, skipping the todo being moved
+fn surrounding_keys(connection: &mut SqliteConnection, todo: &TodoItem, request: &MoveTodo) -> Result<Neighbours, (Status, &'static str)> {
+    let fetch_error = |_| (Status::InternalServerError, "Failed to fetch todos");
+    let list = todos::table
+        .filter(todos::dsl::user_id.eq(todo.user_id))
+        .filter(todos::dsl::id.ne(todo.id));
+
+    match (request.after, request.before) {
+        (Some(after), Some(before)) => {
+            let after = neighbour(connection, after, todo.user_id)?;
+            let before = neighbour(connection, before, todo.user_id)?;
+            if after.position >= before.position {
+                return Err((Status::BadRequest, "The after todo must come before the before todo"));
+            }
+            Ok((Some(after.position), Some(before.position)))
+        }
+        (Some(after), None) => {
+            let after = neighbour(connection, after, todo.user_id)?;
+            let next: Option<String> = list
+                .filter(todos::dsl::position.gt(&after.position))
+                .select(todos::dsl::position)
+                .order(todos::dsl::position)
+                .first(connection)
+                .optional()
+                .map_err(fetch_error)?;
+            Ok((Some(after.position), next))
+        }
+        (None, Some(before)) => {
+            let before = neighbour(connection, before, todo.user_id)?;
+            let previous: Option<String> = list
+                .filter(todos::dsl::position.lt(&before.position))
+                .select(todos::dsl::position)
+                .order(todos::dsl::position.desc())
+                .first(connection)
+                .optional()
+                .map_err(fetch_error)?;
+            Ok((previous, Some(before.position)))
+        }
+        (None, None) => Err((Status::BadRequest, "Either before or after must be given")),
+    }
+}
+
+// Move a to-do item to a new spot in its owner's list
+#[post("/todos/<id>/move?<user_id>", format = "json", data = "<request>")]
+pub fn move_todo(pool: &State<DbPool>, events: &State<EventBus>, id: i32, user_id: i32, if_match: IfMatch, request: Idempotent<'_, MoveTodo>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let response = request.respond(&mut connection, |connection, request| {
+        let request = require_payload(request)?;
+        info!("Moving to-do item {}: {:?}", id, request);
+
+        if request.before == Some(id) || request.after == Some(id) {
+            return Err((Status::BadRequest, "A todo cannot be moved next to itself"));
+        }
+
+        let todo = authorize(connection, user_id, id, Permission::Edit)?;
+        check_version(if_match, todo.version)?;
+
+        let (low, high) = surrounding_keys(connection, &todo, &request)?;
+        let position = match key_between(low.as_deref(), high.as_deref()) {
+            Some(position) => position,
+            None => {
+                // The neighbours are too close together, so respace the list and try again
+                rebalance(connection, todo.user_id)
+                    .map_err(|_| (Status::InternalServerError, "Failed to rebalance todos"))?;
+                let (low, high) = surrounding_keys(connection, &todo, &request)?;
+                key_between(low.as_deref(), high.as_deref())
+                    .ok_or((Status::InternalServerError, "Failed to find a position for the todo"))?
+            }
+        };
+
+        // Respacing leaves versions alone, so this only misses after a concurrent write
+        let moved = diesel::update(todos::table.find(id).filter(todos::dsl::version.eq(todo.version)))
+            .set((
+                todos::dsl::position.eq(position),
+                todos::dsl::version.eq(todos::dsl::version + 1),
+            ))
+            .execute(connection)
+            .map_err(|_| (Status::InternalServerError, "Failed to move todo"))?;
+        if moved == 0 {
+            return Err((Status::PreconditionFailed, PRECONDITION_FAILED));
+        }
+        record_change(connection, id, ChangeKind::Updated)
+            .map_err(|_| (Status::InternalServerError, "Failed to move todo"))?;
+
+        Ok(StoredResponse::text(Status::Ok, "Todo moved successfully!"))
+    });
+
+    events.publish(&mut connection);
+    response
 }
\ No newline at end of file
@@ -4,7 +4,15 @@ use rocket::State;
 use rocket::serde::json::Json;
 use serde::{Deserialize, Serialize};
 use crate::db::DbPool;
-use crate::schema::users;
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::priority::Priority;
+use crate::schema::{user_settings, users};
+use crate::statuses::create_default_statuses;
+use chrono::{Duration, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use std::fmt::Write;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
 
 #[derive(Queryable, Serialize, Deserialize, Debug)]
 pub struct User {
@@ -20,30 +28,38 @@ pub struct NewUser<'a> {
     pub password_hash: &'a str,
 }
 
-#[derive(Serialize, Deserialize, Queryable)]
+#[derive(Serialize, Deserialize, Queryable, Debug, Clone)]
 pub struct PublicUser {
     pub id: i32,
     pub username: String,
 }
 
 #[post("/users", format = "json", data = "<new_user>")]
-pub fn create_user(pool: &State<DbPool>, new_user: Json<NewUser>) -> Result<&'static str, (Status, &'static str)> {
-    if new_user.username.trim().is_empty() {
-        return Err((Status::BadRequest, "Username cannot be empty"));
-    }
+pub fn create_user(pool: &State<DbPool>, new_user: Idempotent<'_, NewUser<'_>>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
 
-    if new_user.password_hash.trim().is_empty() {
-        return Err((Status::BadRequest, "Password cannot be empty"));
-    }
+    new_user.respond(&mut connection, |connection, new_user| {
+        let new_user = require_payload(new_user)?;
+        if new_user.username.trim().is_empty() {
+            return Err((Status::BadRequest, "Username cannot be empty"));
+        }
 
-    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+        if new_user.password_hash.trim().is_empty() {
+            return Err((Status::BadRequest, "Password cannot be empty"));
+        }
 
-    diesel::insert_into(users::table)
-        .values(&new_user.into_inner())
-        .execute(&mut connection)
-        .map_err(|_| (Status::InternalServerError, "Failed to create user"))?;
+        // New users start with the default board columns
+        connection.transaction::<_, diesel::result::Error, _>(|connection| {
+            diesel::insert_into(users::table)
+                .values(&new_user.into_inner())
+                .execute(connection)?;
 
-    Ok("User created successfully!")
+            let user_id = users::table.order(users::dsl::id.desc()).select(users::dsl::id).first::<i32>(connection)?;
+            create_default_statuses(connection, user_id)
+        }).map_err(|_| (Status::InternalServerError, "Failed to create user"))?;
+
+        Ok(StoredResponse::text(Status::Ok, "User created successfully!"))
+    })
 }
 
 #[get("/users/<id>")]
@@ -58,4 +74,170 @@ pub fn get_user_by_id(pool: &State<DbPool>, id: i32) -> Result<Json<PublicUser>,
         .map_err(|_| (Status::NotFound, "User not found"))?;
 
     Ok(Json(user))
-}
\ No newline at end of file
+}
+
+#[derive(Queryable, Insertable, AsChangeset, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = user_settings, primary_key(user_id))]
+pub struct UserSettings {
+    pub user_id: i32,
+    pub timezone: String,
+    pub week_start: String,
+    pub date_format: String,
+    pub default_priority: Option<Priority>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateUserSettings<'a> {
+    pub timezone: &'a str,
+    pub week_start: &'a str,
+    pub date_format: &'a str,
+    pub default_priority: Option<Priority>,
+}
+
+impl UserSettings {
+    // Settings used for users who have never saved any
+    pub fn defaults(user_id: i32) -> Self {
+        UserSettings {
+            user_id,
+            timezone: "UTC".to_string(),
+            week_start: "monday".to_string(),
+            date_format: DEFAULT_DATE_FORMAT.to_string(),
+            default_priority: None,
+        }
+    }
+
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+
+    pub fn week_start(&self) -> Weekday {
+        self.week_start.parse().unwrap_or(Weekday::Mon)
+    }
+
+    // The current calendar date in the user's timezone
+    pub fn today(&self) -> NaiveDate {
+        Utc::now().with_timezone(&self.tz()).date_naive()
+    }
+
+    // The calendar date a UTC timestamp falls on in the user's timezone
+    pub fn local_date(&self, timestamp: NaiveDateTime) -> NaiveDate {
+        timestamp.and_utc().with_timezone(&self.tz()).date_naive()
+    }
+
+    // Dates in the user's format, or ISO dates if the stored format can't render one
+    pub fn format_date(&self, date: NaiveDate) -> String {
+        render_date(date, &self.date_format).unwrap_or_else(|| date.format(DEFAULT_DATE_FORMAT).to_string())
+    }
+}
+
+// A date in the given strftime format; None for formats chrono can't render a
+// plain date with, such as ones asking for a time of day
+fn render_date(date: NaiveDate, date_format: &str) -> Option<String> {
+    let mut rendered = String::new();
+    write!(rendered, "{}", date.format(date_format)).ok()?;
+    Some(rendered)
+}
+
+fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "monday",
+        Weekday::Tue => "tuesday",
+        Weekday::Wed => "wednesday",
+        Weekday::Thu => "thursday",
+        Weekday::Fri => "friday",
+        Weekday::Sat => "saturday",
+        Weekday::Sun => "sunday",
+    }
+}
+
+// The UTC moment a local wall-clock time falls on; times skipped by a DST
+// change resolve to the first valid moment after them
+pub fn local_to_utc(tz: Tz, mut local: NaiveDateTime) -> NaiveDateTime {
+    loop {
+        if let Some(moment) = tz.from_local_datetime(&local).earliest() {
+            return moment.naive_utc();
+        }
+        local += Duration::minutes(30);
+    }
+}
+
+// Settings for several users at once, with defaults for any who have none saved
+pub fn load_users_settings(connection: &mut SqliteConnection, user_ids: &[i32]) -> QueryResult<Vec<UserSettings>> {
+    let saved: Vec<UserSettings> = user_settings::table
+        .filter(user_settings::dsl::user_id.eq_any(user_ids))
+        .load(connection)?;
+
+    Ok(user_ids.iter()
+        .map(|user_id| saved.iter()
+            .find(|settings| settings.user_id == *user_id)
+            .cloned()
+            .unwrap_or_else(|| UserSettings::defaults(*user_id)))
+        .collect())
+}
+
+// Load a user's settings, falling back to the defaults when none are saved
+pub fn load_user_settings(connection: &mut SqliteConnection, user_id: i32) -> QueryResult<UserSettings> {
+    let settings = user_settings::table
+        .find(user_id)
+        .first::<UserSettings>(connection)
+        .optional()?;
+
+    Ok(settings.unwrap_or_else(|| UserSettings::defaults(user_id)))
+}
+
+#[get("/users/<id>/settings")]
+pub fn get_user_settings(pool: &State<DbPool>, id: i32) -> Result<Json<UserSettings>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    users::table
+        .find(id)
+        .select(users::id)
+        .first::<i32>(&mut connection)
+        .map_err(|_| (Status::NotFound, "User not found"))?;
+
+    let settings = load_user_settings(&mut connection, id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))?;
+
+    Ok(Json(settings))
+}
+
+#[put("/users/<id>/settings", format = "json", data = "<updated_settings>")]
+pub fn update_user_settings(pool: &State<DbPool>, id: i32, updated_settings: Json<UpdateUserSettings>) -> Result<Json<UserSettings>, (Status, &'static str)> {
+    if updated_settings.timezone.parse::<Tz>().is_err() {
+        return Err((Status::BadRequest, "Unknown timezone"));
+    }
+
+    let week_start = updated_settings.week_start.parse::<Weekday>()
+        .map_err(|_| (Status::BadRequest, "Week start must be a day of the week"))?;
+
+    if updated_settings.date_format.trim().is_empty()
+        || render_date(NaiveDate::MIN, updated_settings.date_format).is_none() {
+        return Err((Status::BadRequest, "Invalid date format"));
+    }
+
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    users::table
+        .find(id)
+        .select(users::id)
+        .first::<i32>(&mut connection)
+        .map_err(|_| (Status::NotFound, "User not found"))?;
+
+    let settings = UserSettings {
+        user_id: id,
+        timezone: updated_settings.timezone.to_string(),
+        week_start: weekday_name(week_start).to_string(),
+        date_format: updated_settings.date_format.to_string(),
+        default_priority: updated_settings.default_priority,
+    };
+
+    diesel::insert_into(user_settings::table)
+        .values(&settings)
+        .on_conflict(user_settings::user_id)
+        .do_update()
+        .set(&settings)
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to save user settings"))?;
+
+    Ok(Json(settings))
+}
@@ -0,0 +1,171 @@
+use rocket::http::Status;
+use rocket::State;
+use rocket::serde::json::Json;
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::schema::todos;
+use crate::sharing::visible_to;
+use crate::todos::{load_details, not_deferred, overdue, TodoItem};
+use crate::user::{load_user_settings, local_to_utc, UserSettings};
+use diesel::prelude::*;
+use diesel::sql_types::Bool;
+use diesel::sqlite::Sqlite;
+use log::info;
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+
+// Upcoming view looks a week ahead unless the client asks otherwise
+const DEFAULT_UPCOMING_DAYS: i64 = 7;
+// and never more than a year
+const MAX_UPCOMING_DAYS: i64 = 365;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DateGroup {
+    pub date: Option<NaiveDate>,
+    pub label: Option<String>,
+    pub todos: Vec<TodoItem>,
+}
+
+// Views are computed relative to the user's own timezone
+fn settings_for(connection: &mut SqliteConnection, user_id: i32) -> Result<UserSettings, (Status, &'static str)> {
+    load_user_settings(connection, user_id)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch user settings"))
+}
+
+// The UTC moment a day starts in the viewer's timezone
+fn start_of(date: NaiveDate, settings: &UserSettings) -> NaiveDateTime {
+    local_to_utc(settings.tz(), date.and_time(NaiveTime::MIN))
+}
+
+// Filter for todos due on a day from `first` to `last` in the viewer's timezone. The
+// stored due date follows the owner's timezone, so precise deadlines are compared by
+// their moment and only date-only todos go by their due date
+fn due_within(first: NaiveDate, last: NaiveDate, settings: &UserSettings) -> Box<dyn BoxableExpression<todos::table, Sqlite, SqlType = Bool>> {
+    use crate::schema::todos::dsl::*;
+
+    let start = start_of(first, settings);
+    let end = start_of(last + Duration::days(1), settings);
+    Box::new(
+        due_at.is_null().and(due_date.is_not_null())
+            .and(due_date.assume_not_null().ge(first)).and(due_date.assume_not_null().le(last))
+            .or(due_at.is_not_null().and(due_at.assume_not_null().ge(start)).and(due_at.assume_not_null().lt(end)))
+    )
+}
+
+// The day a todo is due on for the viewer
+fn due_day(todo: &TodoItem, settings: &UserSettings) -> Option<NaiveDate> {
+    todo.due_at.map(|due_at| settings.local_date(due_at)).or(todo.due_date)
+}
+
+// Group todos into one entry per day they're due on for the viewer
+fn group_by_date(mut todos: Vec<TodoItem>, settings: &UserSettings) -> Vec<DateGroup> {
+    // Stable, so todos due at the same time keep their list order
+    todos.sort_by_key(|todo| (due_day(todo, settings), todo.due_at));
+    let mut groups: Vec<DateGroup> = Vec::new();
+
+    for todo in todos {
+        let date = due_day(&todo, settings);
+        match groups.last_mut() {
+            Some(group) if group.date == date => group.todos.push(todo),
+            _ => groups.push(DateGroup {
+                date,
+                label: date.map(|date| settings.format_date(date)),
+                todos: vec![todo],
+            }),
+        }
+    }
+
+    groups
+}
+
+// Open todos due today
+#[get("/views/today?<user_id>")]
+pub fn get_today(pool: &State<DbPool>, user_id: i32) -> Result<Json<Vec<DateGroup>>, (Status, &'static str)> {
+    info!("Fetching today view for user {}", user_id);
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    let settings = settings_for(&mut connection, user_id)?;
+
+    // Items due earlier today at a precise time are already overdue
+    let mut results: Vec<TodoItem> = todos::table
+        .filter(visible_to(user_id))
+        .filter(not_deferred(Utc::now().naive_utc()))
+        .filter(todos::dsl::completed.eq(false))
+        .filter(due_within(settings.today(), settings.today(), &settings))
+        .filter(todos::dsl::due_at.is_null().or(todos::dsl::due_at.ge(Utc::now().naive_utc())))
+        .order((todos::dsl::position, todos::dsl::id))
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+    load_details(&mut connection, &mut results)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+
+    Ok(Json(group_by_date(results, &settings)))
+}
+
+// Open todos whose deadline has already passed, oldest first
+#[get("/views/overdue?<user_id>")]
+pub fn get_overdue(pool: &State<DbPool>, user_id: i32) -> Result<Json<Vec<DateGroup>>, (Status, &'static str)> {
+    info!("Fetching overdue view for user {}", user_id);
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    let settings = settings_for(&mut connection, user_id)?;
+
+    let mut results: Vec<TodoItem> = todos::table
+        .filter(visible_to(user_id))
+        .filter(not_deferred(Utc::now().naive_utc()))
+        .filter(overdue(settings.today(), Utc::now().naive_utc()))
+        .order((todos::dsl::position, todos::dsl::id))
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+    load_details(&mut connection, &mut results)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+
+    Ok(Json(group_by_date(results, &settings)))
+}
+
+// Open todos due after today and within the next `days` days
+#[get("/views/upcoming?<user_id>&<days>")]
+pub fn get_upcoming(pool: &State<DbPool>, user_id: i32, days: Option<i64>) -> Result<Json<Vec<DateGroup>>, (Status, &'static str)> {
+    let days = days.unwrap_or(DEFAULT_UPCOMING_DAYS);
+    if !(1..=MAX_UPCOMING_DAYS).contains(&days) {
+        return Err((Status::BadRequest, "Days must be between 1 and 365"));
+    }
+
+    info!("Fetching upcoming view for user {} over {} days", user_id, days);
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    let settings = settings_for(&mut connection, user_id)?;
+
+    let today = settings.today();
+    let last_day = today + Duration::days(days);
+
+    let mut results: Vec<TodoItem> = todos::table
+        .filter(visible_to(user_id))
+        .filter(not_deferred(Utc::now().naive_utc()))
+        .filter(todos::dsl::completed.eq(false))
+        .filter(due_within(today + Duration::days(1), last_day, &settings))
+        .order((todos::dsl::position, todos::dsl::id))
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+    load_details(&mut connection, &mut results)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+
+    Ok(Json(group_by_date(results, &settings)))
+}
+
+// Open todos without a due date
+#[get("/views/someday?<user_id>")]
+pub fn get_someday(pool: &State<DbPool>, user_id: i32) -> Result<Json<Vec<DateGroup>>, (Status, &'static str)> {
+    info!("Fetching someday view for user {}", user_id);
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    let settings = settings_for(&mut connection, user_id)?;
+
+    let mut results: Vec<TodoItem> = todos::table
+        .filter(visible_to(user_id))
+        .filter(not_deferred(Utc::now().naive_utc()))
+        .filter(todos::dsl::completed.eq(false))
+        .filter(todos::dsl::due_date.is_null())
+        .order((todos::dsl::position, todos::dsl::id))
+        .load(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+    load_details(&mut connection, &mut results)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch todos"))?;
+
+    Ok(Json(group_by_date(results, &settings)))
+}
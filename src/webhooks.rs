@@ -0,0 +1,517 @@
+//! Outgoing webhooks.
+//!
+//! Users register URLs along with the todo events they care about. A worker
+//! thread started at liftoff reads new entries from the todo change log,
+//! queues a delivery for every matching webhook and POSTs them as JSON. Each
+//! request is signed with the webhook's secret:
+//!
+//! `X-Dooly-Signature: sha256=<hex HMAC-SHA256 of "<X-Dooly-Timestamp>.<body>">`
+//!
+//! Failed deliveries are retried with exponential backoff until
+//! `max_attempts`, and a webhook that keeps failing is disabled until its
+//! owner enables it again. Every delivery is kept as the webhook's log.
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{Orbit, Rocket, State};
+use serde::{Serialize, Deserialize};
+use crate::db::DbPool;
+use crate::events::{load_events, TodoEvent};
+use crate::idempotency::{require_payload, Idempotent, StoredResponse};
+use crate::schema::{webhook_deliveries, webhooks};
+use crate::sync::{current_token, ChangeKind};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
+use log::info;
+use chrono::{Duration, NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::{Arc, Weak};
+use std::thread;
+
+pub const EVENT_HEADER: &str = "X-Dooly-Event";
+pub const DELIVERY_HEADER: &str = "X-Dooly-Delivery";
+pub const TIMESTAMP_HEADER: &str = "X-Dooly-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Dooly-Signature";
+
+pub const EVENTS: [&str; 4] = ["todo.created", "todo.updated", "todo.completed", "todo.deleted"];
+
+const MAX_DELIVERIES: i64 = 100;
+
+// The name webhooks subscribe to for a kind of change
+pub fn event_name(kind: ChangeKind) -> &'static str {
+    match kind {
+        ChangeKind::Created => "todo.created",
+        ChangeKind::Updated => "todo.updated",
+        ChangeKind::Completed => "todo.completed",
+        ChangeKind::Deleted => "todo.deleted",
+    }
+}
+
+// The value of the signature header for a request body sent at `timestamp`
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// The events a webhook is subscribed to, stored comma separated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(transparent)]
+#[diesel(sql_type = Text)]
+pub struct EventFilter(pub Vec<String>);
+
+impl EventFilter {
+    pub fn matches(&self, event: &str) -> bool {
+        self.0.iter().any(|name| name == event)
+    }
+}
+
+impl ToSql<Text, Sqlite> for EventFilter {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.0.join(","));
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for EventFilter {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let events = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(EventFilter(events.split(',').filter(|name| !name.is_empty()).map(str::to_string).collect()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[diesel(sql_type = Text)]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for DeliveryStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.as_str());
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for DeliveryStatus {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "failed" => Ok(DeliveryStatus::Failed),
+            other => Err(format!("Unknown delivery status: {}", other).into()),
+        }
+    }
+}
+
+// The secret is only ever shown once, when the webhook is created
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct Webhook {
+    pub id: i32,
+    pub user_id: i32,
+    pub url: String,
+    #[serde(skip_serializing, default)]
+    pub secret: String,
+    pub events: EventFilter,
+    pub active: bool,
+    pub consecutive_failures: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewWebhook {
+    pub user_id: i32,
+    pub url: String,
+    pub events: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+#[derive(Queryable, Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhook_id: i32,
+    pub event: String,
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+// The JSON body POSTed to a webhook; `id` is the change id, as in `/events`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WebhookPayload {
+    pub event: String,
+    #[serde(flatten)]
+    pub change: TodoEvent,
+}
+
+// Worker settings, read from the `webhooks` table of Rocket.toml
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub poll_interval_ms: u64,
+    pub timeout_secs: u64,
+    pub retry_base_ms: i64,
+    pub max_attempts: i32,
+    pub disable_after: i32,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig { poll_interval_ms: 1000, timeout_secs: 10, retry_base_ms: 30_000, max_attempts: 5, disable_after: 10 }
+    }
+}
+
+impl WebhookConfig {
+    // Wait twice as long after each failed attempt
+    fn backoff(&self, attempts: i32) -> Duration {
+        Duration::milliseconds(self.retry_base_ms.saturating_mul(1 << (attempts - 1).clamp(0, 20)))
+    }
+}
+
+fn validate(new_webhook: &NewWebhook) -> Result<(), (Status, &'static str)> {
+    if !(new_webhook.url.starts_with("http://") || new_webhook.url.starts_with("https://")) {
+        return Err((Status::BadRequest, "Webhook URL must be an http or https URL"));
+    }
+
+    if new_webhook.events.is_empty() {
+        return Err((Status::BadRequest, "Webhook must subscribe to at least one event"));
+    }
+
+    if new_webhook.events.iter().any(|event| !EVENTS.contains(&event.as_str())) {
+        return Err((Status::BadRequest, "Unknown webhook event"));
+    }
+
+    Ok(())
+}
+
+fn owned_webhook(connection: &mut SqliteConnection, id: i32, user_id: i32) -> Result<Webhook, (Status, &'static str)> {
+    webhooks::table
+        .find(id)
+        .filter(webhooks::dsl::user_id.eq(user_id))
+        .first(connection)
+        .optional()
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch webhook"))?
+        .ok_or((Status::NotFound, "Webhook not found"))
+}
+
+// Register a webhook; the response holds the secret used to sign its deliveries
+#[post("/webhooks", format = "json", data = "<new_webhook>")]
+pub fn add_webhook(pool: &State<DbPool>, new_webhook: Idempotent<'_, NewWebhook>) -> Result<StoredResponse, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    new_webhook.respond(&mut connection, |connection, new_webhook| {
+        let new_webhook = require_payload(new_webhook)?;
+        validate(&new_webhook)?;
+
+        info!("Adding webhook for user {} to {}", new_webhook.user_id, new_webhook.url);
+        let secret = hex::encode(rand::random::<[u8; 32]>());
+        let mut events = new_webhook.events.clone();
+        events.sort();
+        events.dedup();
+
+        let webhook = connection.transaction::<_, diesel::result::Error, _>(|connection| {
+            diesel::insert_into(webhooks::table)
+                .values((
+                    webhooks::dsl::user_id.eq(new_webhook.user_id),
+                    webhooks::dsl::url.eq(&new_webhook.url),
+                    webhooks::dsl::secret.eq(&secret),
+                    webhooks::dsl::events.eq(EventFilter(events)),
+                ))
+                .execute(connection)?;
+
+            webhooks::table.order(webhooks::dsl::id.desc()).first::<Webhook>(connection)
+        }).map_err(|_| (Status::InternalServerError, "Failed to add webhook"))?;
+
+        StoredResponse::json(Status::Ok, &CreatedWebhook { webhook, secret })
+    })
+}
+
+#[get("/webhooks?<user_id>")]
+pub fn get_webhooks(pool: &State<DbPool>, user_id: i32) -> Result<Json<Vec<Webhook>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+
+    let results = webhooks::table
+        .filter(webhooks::dsl::user_id.eq(user_id))
+        .order(webhooks::dsl::id)
+        .load::<Webhook>(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch webhooks"))?;
+
+    Ok(Json(results))
+}
+
+// Remove a webhook along with its delivery log
+#[delete("/webhooks/<id>?<user_id>")]
+pub fn delete_webhook(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<&'static str, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    owned_webhook(&mut connection, id, user_id)?;
+
+    connection.transaction::<_, diesel::result::Error, _>(|connection| {
+        diesel::delete(webhook_deliveries::table.filter(webhook_deliveries::dsl::webhook_id.eq(id))).execute(connection)?;
+        diesel::delete(webhooks::table.find(id)).execute(connection)
+    }).map_err(|_| (Status::InternalServerError, "Failed to delete webhook"))?;
+
+    Ok("Webhook deleted successfully!")
+}
+
+// Turn a disabled webhook back on; deliveries that already failed are not retried
+#[put("/webhooks/<id>/enable?<user_id>")]
+pub fn enable_webhook(pool: &State<DbPool>, id: i32, user_id: i32) -> Result<Json<Webhook>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    owned_webhook(&mut connection, id, user_id)?;
+
+    diesel::update(webhooks::table.find(id))
+        .set((webhooks::dsl::active.eq(true), webhooks::dsl::consecutive_failures.eq(0)))
+        .execute(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to enable webhook"))?;
+
+    owned_webhook(&mut connection, id, user_id).map(Json)
+}
+
+// A webhook's most recent deliveries, newest first
+#[get("/webhooks/<id>/deliveries?<user_id>&<limit>")]
+pub fn get_deliveries(pool: &State<DbPool>, id: i32, user_id: i32, limit: Option<i64>) -> Result<Json<Vec<WebhookDelivery>>, (Status, &'static str)> {
+    let mut connection = pool.get().map_err(|_| (Status::InternalServerError, "Failed to get connection from pool"))?;
+    owned_webhook(&mut connection, id, user_id)?;
+
+    let results = webhook_deliveries::table
+        .filter(webhook_deliveries::dsl::webhook_id.eq(id))
+        .order(webhook_deliveries::dsl::id.desc())
+        .limit(limit.unwrap_or(MAX_DELIVERIES).clamp(1, MAX_DELIVERIES))
+        .load::<WebhookDelivery>(&mut connection)
+        .map_err(|_| (Status::InternalServerError, "Failed to fetch deliveries"))?;
+
+    Ok(Json(results))
+}
+
+// Queue a delivery to every active webhook subscribed to each event
+fn enqueue(connection: &mut SqliteConnection, events: Vec<TodoEvent>) -> QueryResult<()> {
+    let now = Utc::now().naive_utc();
+
+    for change in events {
+        let event = event_name(change.kind);
+        let subscribed: Vec<Webhook> = webhooks::table
+            .filter(webhooks::dsl::user_id.eq(change.user_id))
+            .filter(webhooks::dsl::active.eq(true))
+            .load::<Webhook>(connection)?
+            .into_iter()
+            .filter(|webhook| webhook.events.matches(event))
+            .collect();
+        if subscribed.is_empty() {
+            continue;
+        }
+
+        let payload = serde_json::to_string(&WebhookPayload { event: event.to_string(), change })
+            .expect("webhook payloads always serialize");
+        for webhook in subscribed {
+            diesel::insert_into(webhook_deliveries::table)
+                .values((
+                    webhook_deliveries::dsl::webhook_id.eq(webhook.id),
+                    webhook_deliveries::dsl::event.eq(event),
+                    webhook_deliveries::dsl::payload.eq(&payload),
+                    webhook_deliveries::dsl::next_attempt_at.eq(now),
+                    webhook_deliveries::dsl::created_at.eq(now),
+                ))
+                .execute(connection)?;
+        }
+    }
+
+    Ok(())
+}
+
+// POST a delivery, returning the response status or why there wasn't one
+fn send(agent: &ureq::Agent, webhook: &Webhook, delivery: &WebhookDelivery) -> Result<u16, (Option<u16>, String)> {
+    let timestamp = Utc::now().timestamp();
+    let result = agent.post(&webhook.url)
+        .set("Content-Type", "application/json")
+        .set(EVENT_HEADER, &delivery.event)
+        .set(DELIVERY_HEADER, &delivery.id.to_string())
+        .set(TIMESTAMP_HEADER, &timestamp.to_string())
+        .set(SIGNATURE_HEADER, &sign(&webhook.secret, timestamp, &delivery.payload))
+        .send_string(&delivery.payload);
+
+    match result {
+        Ok(response) => Ok(response.status()),
+        Err(ureq::Error::Status(code, _)) => Err((Some(code), format!("Webhook responded with {}", code))),
+        Err(ureq::Error::Transport(err)) => Err((None, err.to_string())),
+    }
+}
+
+// Attempt every delivery that is due, recording how it went
+fn deliver_due(connection: &mut SqliteConnection, agent: &ureq::Agent, config: &WebhookConfig) -> QueryResult<()> {
+    let due: Vec<(WebhookDelivery, Webhook)> = webhook_deliveries::table
+        .inner_join(webhooks::table)
+        .filter(webhook_deliveries::dsl::status.eq(DeliveryStatus::Pending))
+        .filter(webhook_deliveries::dsl::next_attempt_at.le(Utc::now().naive_utc()))
+        .filter(webhooks::dsl::active.eq(true))
+        .order(webhook_deliveries::dsl::id)
+        .load(connection)?;
+
+    for (delivery, webhook) in due {
+        // An earlier failure in this pass may have disabled the webhook
+        let active: bool = webhooks::table
+            .find(webhook.id)
+            .select(webhooks::dsl::active)
+            .first(connection)?;
+        if !active {
+            continue;
+        }
+
+        let attempts = delivery.attempts + 1;
+        let now = Utc::now().naive_utc();
+        let target = webhook_deliveries::table.find(delivery.id);
+
+        match send(agent, &webhook, &delivery) {
+            Ok(code) => {
+                diesel::update(target)
+                    .set((
+                        webhook_deliveries::dsl::status.eq(DeliveryStatus::Delivered),
+                        webhook_deliveries::dsl::attempts.eq(attempts),
+                        webhook_deliveries::dsl::response_status.eq(Some(i32::from(code))),
+                        webhook_deliveries::dsl::error.eq(None::<String>),
+                        webhook_deliveries::dsl::delivered_at.eq(Some(now)),
+                    ))
+                    .execute(connection)?;
+                diesel::update(webhooks::table.find(webhook.id))
+                    .set(webhooks::dsl::consecutive_failures.eq(0))
+                    .execute(connection)?;
+            }
+            Err((code, reason)) => {
+                error!("Webhook {} delivery {} failed: {}", webhook.id, delivery.id, reason);
+                let status = if attempts >= config.max_attempts { DeliveryStatus::Failed } else { DeliveryStatus::Pending };
+                diesel::update(target)
+                    .set((
+                        webhook_deliveries::dsl::status.eq(status),
+                        webhook_deliveries::dsl::attempts.eq(attempts),
+                        webhook_deliveries::dsl::response_status.eq(code.map(i32::from)),
+                        webhook_deliveries::dsl::error.eq(Some(reason)),
+                        webhook_deliveries::dsl::next_attempt_at.eq(now + config.backoff(attempts)),
+                    ))
+                    .execute(connection)?;
+
+                // Counted in SQL, since the row loaded above is stale once an earlier delivery in this pass failed
+                diesel::update(webhooks::table.find(webhook.id))
+                    .set(webhooks::dsl::consecutive_failures.eq(webhooks::dsl::consecutive_failures + 1))
+                    .execute(connection)?;
+                let failures: i32 = webhooks::table
+                    .find(webhook.id)
+                    .select(webhooks::dsl::consecutive_failures)
+                    .first(connection)?;
+                if failures >= config.disable_after {
+                    info!("Disabling webhook {} after {} failed deliveries", webhook.id, failures);
+                    diesel::update(webhooks::table.find(webhook.id))
+                        .set(webhooks::dsl::active.eq(false))
+                        .execute(connection)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Queue and send deliveries until the Rocket instance that started us is gone
+fn run(pool: DbPool, config: WebhookConfig, alive: Weak<()>, mut published: i32) {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(std::time::Duration::from_secs(config.timeout_secs))
+        .build();
+
+    loop {
+        thread::sleep(std::time::Duration::from_millis(config.poll_interval_ms));
+        if alive.upgrade().is_none() {
+            break;
+        }
+
+        let mut connection = match pool.get() {
+            Ok(connection) => connection,
+            Err(err) => {
+                error!("Webhook worker failed to get connection from pool: {:?}", err);
+                continue;
+            }
+        };
+
+        let queued = load_events(&mut connection, published, None).and_then(|events| {
+            let last = events.last().map(|event| event.id);
+            enqueue(&mut connection, events)?;
+            Ok(last)
+        });
+        match queued {
+            Ok(last) => published = last.unwrap_or(published),
+            Err(err) => error!("Failed to queue webhook deliveries: {:?}", err),
+        }
+
+        if let Err(err) = deliver_due(&mut connection, &agent, &config) {
+            error!("Failed to send webhook deliveries: {:?}", err);
+        }
+    }
+}
+
+// Starts the delivery worker once Rocket has launched. Changes made while the
+// server was down are not sent.
+pub struct WebhookWorker {
+    alive: Arc<()>,
+}
+
+impl WebhookWorker {
+    pub fn fairing() -> Self {
+        WebhookWorker { alive: Arc::new(()) }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for WebhookWorker {
+    fn info(&self) -> Info {
+        Info { name: "Webhook worker", kind: Kind::Liftoff }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let Some(pool) = rocket.state::<DbPool>().cloned() else {
+            error!("Webhook worker needs a database pool");
+            return;
+        };
+        let config: WebhookConfig = rocket.figment().extract_inner("webhooks").unwrap_or_default();
+
+        let published = match pool.get().map(|mut connection| current_token(&mut connection)) {
+            Ok(Ok(token)) => token,
+            _ => {
+                error!("Webhook worker failed to read the change log");
+                return;
+            }
+        };
+
+        info!("Starting webhook worker with {:?}", config);
+        let alive = Arc::downgrade(&self.alive);
+        thread::spawn(move || run(pool, config, alive, published));
+    }
+}
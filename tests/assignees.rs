@@ -0,0 +1,89 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use dooly::helpers::setup_with_friend;
+use dooly::todos::TodoItem;
+use serde_json::json;
+
+// Seeded todos belong to user 1; user 2 is added to assign them to
+fn assign(client: &Client, user_id: i32, assignee_id: i32) -> Status {
+    client.put("/todos/1/assignee")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": user_id, "assignee_id": assignee_id }).to_string())
+        .dispatch()
+        .status()
+}
+
+fn get_todo(client: &Client, id: i32) -> TodoItem {
+    client.get(format!("/todos/{}?user_id=1", id)).dispatch().into_json().unwrap()
+}
+
+#[test]
+fn test_assign_requires_access() {
+    let client = setup_with_friend();
+
+    // User 2 can't see the todo yet, so can neither assign it nor be assigned it
+    assert_eq!(assign(&client, 2, 2), Status::NotFound);
+    assert_eq!(assign(&client, 1, 2), Status::BadRequest);
+    assert_eq!(assign(&client, 1, 42), Status::NotFound);
+
+    client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "username": "friend", "todo_id": 1, "role": "viewer" }).to_string())
+        .dispatch();
+
+    assert_eq!(assign(&client, 2, 2), Status::Forbidden);
+    assert_eq!(assign(&client, 1, 2), Status::Ok);
+
+    let todo = get_todo(&client, 1);
+    assert_eq!(todo.assignee_id, Some(2));
+    let assignee = todo.assignee.unwrap();
+    assert_eq!(assignee.id, 2);
+    assert_eq!(assignee.username, "friend");
+}
+
+#[test]
+fn test_assigned_to_me() {
+    let client = setup_with_friend();
+    client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "username": "friend", "role": "editor" }).to_string())
+        .dispatch();
+
+    // The editor takes the task on themselves
+    assert_eq!(assign(&client, 2, 2), Status::Ok);
+
+    let response = client.get("/todos/assigned?user_id=2").dispatch();
+    let assigned: Vec<TodoItem> = response.into_json().unwrap();
+    assert_eq!(assigned.len(), 1);
+    assert_eq!(assigned[0].id, 1);
+    assert_eq!(assigned[0].assignee.as_ref().unwrap().username, "friend");
+
+    let response = client.get("/todos/assigned?user_id=1").dispatch();
+    assert!(response.into_json::<Vec<TodoItem>>().unwrap().is_empty());
+
+    let response = client.delete("/todos/1/assignee?user_id=2").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(get_todo(&client, 1).assignee_id, None);
+    let response = client.get("/todos/assigned?user_id=2").dispatch();
+    assert!(response.into_json::<Vec<TodoItem>>().unwrap().is_empty());
+}
+
+#[test]
+fn test_assign_checks_version() {
+    let client = setup_with_friend();
+
+    let response = client.put("/todos/1/assignee")
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", "\"5\""))
+        .body(json!({ "user_id": 1, "assignee_id": 1 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::PreconditionFailed);
+
+    let response = client.put("/todos/1/assignee")
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", "\"1\""))
+        .body(json!({ "user_id": 1, "assignee_id": 1 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("ETag"), Some("\"2\""));
+}
@@ -0,0 +1,183 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use dooly::attachments::{Attachment, StorageUsage};
+use dooly::helpers::{build_rocket, cleanup_database, establish_test_connection, run_seed_script};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BOUNDARY: &str = "dooly-test-boundary";
+
+// The app with its attachments in a fresh directory and a small quota
+fn setup_client() -> (Client, PathBuf) {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let dir = std::env::temp_dir().join(format!("dooly-attachments-{}", rand::random::<u64>()));
+    let rocket = build_rocket();
+    let figment = rocket.figment().clone()
+        .merge(("attachments.dir", dir.to_str().unwrap()))
+        .merge(("attachments.quota_bytes", 64));
+    (Client::tracked(rocket.configure(figment)).expect("valid rocket instance"), dir)
+}
+
+// Number of files in the store
+fn stored_files(dir: &Path) -> usize {
+    fs::read_dir(dir).unwrap()
+        .map(|entry| fs::read_dir(entry.unwrap().path()).unwrap().count())
+        .sum()
+}
+
+fn upload(client: &Client, todo_id: i32, user_id: i32, filename: &str, content_type: &str, contents: &str) -> (Status, Option<Attachment>) {
+    upload_with_key(client, None, todo_id, user_id, filename, content_type, contents)
+}
+
+fn upload_with_key(client: &Client, key: Option<&str>, todo_id: i32, user_id: i32, filename: &str, content_type: &str, contents: &str) -> (Status, Option<Attachment>) {
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"user_id\"\r\n\r\n{user_id}\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{filename}\"\r\nContent-Type: {content_type}\r\n\r\n{contents}\r\n\
+         --{b}--\r\n",
+        b = BOUNDARY,
+    );
+    let mut request = client.post(format!("/todos/{}/attachments", todo_id))
+        .header(ContentType::new("multipart", "form-data").with_params(("boundary", BOUNDARY)))
+        .body(body);
+    if let Some(key) = key {
+        request = request.header(Header::new("Idempotency-Key", key.to_string()));
+    }
+    let response = request.dispatch();
+    let status = response.status();
+    (status, if status == Status::Ok { response.into_json() } else { None })
+}
+
+#[test]
+fn test_upload_and_download() {
+    let (client, dir) = setup_client();
+
+    let (status, attachment) = upload(&client, 1, 1, "../receipt.txt", "text/plain", "Paid in full");
+    assert_eq!(status, Status::Ok);
+    let attachment = attachment.unwrap();
+    assert_eq!(attachment.filename, "receipt.txt");
+    assert_eq!(attachment.size, 12);
+    assert_eq!(attachment.digest.len(), 64);
+
+    let response = client.get(format!("/todos/1/attachments/{}?user_id=1", attachment.id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::Plain));
+    assert_eq!(response.headers().get_one("Content-Disposition"), Some("attachment; filename=\"receipt.txt\""));
+    assert_eq!(response.into_string().unwrap(), "Paid in full");
+
+    let listed: Vec<Attachment> = client.get("/todos/1/attachments?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(client.get(format!("/todos/2/attachments/{}?user_id=1", attachment.id)).dispatch().status(), Status::NotFound);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_identical_files_are_stored_once() {
+    let (client, dir) = setup_client();
+
+    let (_, first) = upload(&client, 1, 1, "a.png", "image/png", "same bytes");
+    let (_, second) = upload(&client, 2, 1, "b.png", "image/png", "same bytes");
+    let (first, second) = (first.unwrap(), second.unwrap());
+    assert_eq!(first.digest, second.digest);
+    assert_eq!(stored_files(&dir), 1);
+
+    // The file stays until nothing refers to it
+    let response = client.delete(format!("/todos/1/attachments/{}?user_id=1", first.id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(stored_files(&dir), 1);
+    let response = client.get(format!("/todos/2/attachments/{}?user_id=1", second.id)).dispatch();
+    assert_eq!(response.content_type(), Some(ContentType::PNG));
+
+    let response = client.delete(format!("/todos/2/attachments/{}?user_id=1", second.id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(stored_files(&dir), 0);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_retried_upload_is_attached_once() {
+    let (client, dir) = setup_client();
+
+    let (status, first) = upload_with_key(&client, Some("upload-1"), 1, 1, "notes.txt", "text/plain", "Twelve bytes");
+    assert_eq!(status, Status::Ok);
+    let (status, second) = upload_with_key(&client, Some("upload-1"), 1, 1, "notes.txt", "text/plain", "Twelve bytes");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(first.unwrap().id, second.unwrap().id);
+
+    let listed: Vec<Attachment> = client.get("/todos/1/attachments?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(listed.len(), 1);
+    let usage: StorageUsage = client.get("/attachments/usage?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(usage.used_bytes, 12);
+
+    // The same key with a different file is a different request
+    let (status, _) = upload_with_key(&client, Some("upload-1"), 1, 1, "notes.txt", "text/plain", "Other bytes");
+    assert_eq!(status, Status::UnprocessableEntity);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_quota_and_access() {
+    let (client, dir) = setup_client();
+
+    // User 2 can't see the todo
+    client.post("/users")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "friend", "password_hash": "hashed_password" }).to_string())
+        .dispatch();
+    assert_eq!(upload(&client, 1, 2, "note.txt", "text/plain", "hello").0, Status::NotFound);
+    assert_eq!(upload(&client, 1, 1, "empty.txt", "text/plain", "").0, Status::BadRequest);
+
+    assert_eq!(upload(&client, 1, 1, "big.txt", "text/plain", &"x".repeat(40)).0, Status::Ok);
+    assert_eq!(upload(&client, 1, 1, "bigger.txt", "text/plain", &"y".repeat(40)).0, Status::PayloadTooLarge);
+
+    let usage: StorageUsage = client.get("/attachments/usage?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(usage.used_bytes, 40);
+    assert_eq!(usage.quota_bytes, 64);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_deleting_todo_removes_attachments() {
+    let (client, dir) = setup_client();
+
+    let (_, attachment) = upload(&client, 1, 1, "scan.pdf", "application/pdf", "%PDF-1.4");
+    let attachment = attachment.unwrap();
+    assert_eq!(stored_files(&dir), 1);
+
+    assert_eq!(client.delete("/todos/1?user_id=1").dispatch().status(), Status::Ok);
+    assert_eq!(stored_files(&dir), 0);
+
+    let usage: StorageUsage = client.get("/attachments/usage?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(usage.used_bytes, 0);
+    assert_eq!(client.get(format!("/todos/1/attachments/{}?user_id=1", attachment.id)).dispatch().status(), Status::NotFound);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_bulk_delete_removes_attachments() {
+    let (client, dir) = setup_client();
+
+    upload(&client, 1, 1, "scan.pdf", "application/pdf", "%PDF-1.4");
+    upload(&client, 2, 1, "photo.jpg", "image/jpeg", "JFIF");
+    assert_eq!(stored_files(&dir), 2);
+
+    let response = client.post("/todos/bulk")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "operations": [{ "action": "delete", "ids": [1] }] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(stored_files(&dir), 1);
+
+    let usage: StorageUsage = client.get("/attachments/usage?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(usage.used_bytes, 4);
+
+    fs::remove_dir_all(dir).unwrap();
+}
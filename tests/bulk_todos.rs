@@ -0,0 +1,187 @@
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use dooly::bulk::BulkResponse;
+use dooly::helpers::{cleanup_database, establish_test_connection, run_seed_script, setup_rocket};
+use dooly::priority::Priority;
+use dooly::todos::TodoItem;
+use serde_json::{json, Value};
+use rocket::http::ContentType;
+
+fn add_todos(client: &Client, titles: &[&str]) {
+    for title in titles {
+        let new_todo = json!({
+            "title": title,
+            "completed": false,
+            "user_id": 1
+        });
+
+        let response = client.post("/todos")
+            .header(ContentType::JSON)
+            .body(new_todo.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
+
+fn run_bulk(client: &Client, request: Value) -> (Status, BulkResponse) {
+    let response = client.post("/todos/bulk")
+        .header(ContentType::JSON)
+        .body(request.to_string())
+        .dispatch();
+    let status = response.status();
+    (status, serde_json::from_str(&response.into_string().unwrap()).unwrap())
+}
+
+fn get_todos(client: &Client) -> Vec<TodoItem> {
+    let response = client.get("/todos?user_id=1").dispatch();
+    serde_json::from_str(&response.into_string().unwrap()).unwrap()
+}
+
+#[test]
+fn test_bulk_complete_by_ids() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    add_todos(&client, &["Third", "Fourth"]);
+
+    let (status, response) = run_bulk(&client, json!({
+        "user_id": 1,
+        "operations": [{ "action": "complete", "ids": [1, 3, 4] }]
+    }));
+
+    assert_eq!(status, Status::Ok);
+    assert!(response.applied);
+    assert_eq!(response.results.len(), 3);
+    assert!(response.results.iter().all(|result| result.success));
+    assert!(get_todos(&client).iter().all(|todo| todo.completed));
+}
+
+#[test]
+fn test_bulk_operations_with_filter() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    add_todos(&client, &["Third", "Fourth"]);
+
+    let (status, response) = run_bulk(&client, json!({
+        "user_id": 1,
+        "operations": [
+            { "action": "set_priority", "priority": "high", "filter": { "completed": false } },
+            { "action": "set_due_date", "due_date": "2030-01-15", "filter": { "query": "Fourth" } },
+            { "action": "reopen", "ids": [2] }
+        ]
+    }));
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(response.results.len(), 5);
+
+    let todos = get_todos(&client);
+    assert_eq!(todos[0].priority, Priority::High);
+    assert_eq!(todos[1].priority, Priority::None);
+    assert!(!todos[1].completed);
+    assert_eq!(todos[2].priority, Priority::High);
+    assert_eq!(todos[3].due_date.unwrap().to_string(), "2030-01-15");
+}
+
+#[test]
+fn test_bulk_reopen_skips_open_todos() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let (status, _) = run_bulk(&client, json!({
+        "user_id": 1,
+        "operations": [{ "action": "reopen", "ids": [1, 2] }]
+    }));
+    assert_eq!(status, Status::Ok);
+
+    // Todo 1 was already open, so it is left as it was
+    let todos = get_todos(&client);
+    assert!(todos.iter().all(|todo| !todo.completed));
+    assert_eq!(todos[0].version, 1);
+    assert_eq!(todos[1].version, 2);
+}
+
+#[test]
+fn test_bulk_reports_failed_items() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let (status, response) = run_bulk(&client, json!({
+        "user_id": 1,
+        "operations": [
+            { "action": "delete", "ids": [1] },
+            { "action": "complete", "ids": [1, 2] }
+        ]
+    }));
+
+    // Without all_or_nothing the successful items are kept
+    assert_eq!(status, Status::Ok);
+    assert!(response.applied);
+    assert_eq!(response.results[0].id, 1);
+    assert!(response.results[0].success);
+    assert_eq!(response.results[1].id, 1);
+    assert!(!response.results[1].success);
+    assert_eq!(response.results[1].error.as_deref(), Some("Todo item not found"));
+    assert!(response.results[2].success);
+
+    assert_eq!(get_todos(&client).len(), 1);
+}
+
+#[test]
+fn test_bulk_all_or_nothing_rolls_back() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let (status, response) = run_bulk(&client, json!({
+        "user_id": 1,
+        "all_or_nothing": true,
+        "operations": [
+            { "action": "delete", "ids": [2] },
+            { "action": "complete", "ids": [1, 999] }
+        ]
+    }));
+
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert!(!response.applied);
+    assert!(!response.results[2].success);
+
+    let todos = get_todos(&client);
+    assert_eq!(todos.len(), 2);
+    assert!(!todos[0].completed);
+}
+
+#[test]
+fn test_bulk_invalid_request() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = client.post("/todos/bulk")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "operations": [{ "action": "delete" }] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.into_string().unwrap(), "Each operation needs either ids or a filter");
+
+    let response = client.post("/todos/bulk")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "operations": [] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.into_string().unwrap(), "At least one operation is required");
+}
@@ -1,2 +1,19 @@
-DROP TABLE users;
-DROP TABLE todos;
\ No newline at end of file
+DROP TABLE IF EXISTS habit_checkins;
+DROP TABLE IF EXISTS habits;
+DROP TABLE IF EXISTS rules;
+DROP TABLE IF EXISTS template_items;
+DROP TABLE IF EXISTS templates;
+DROP TABLE IF EXISTS time_entries;
+DROP TABLE IF EXISTS attachments;
+DROP TABLE IF EXISTS notification_preferences;
+DROP TABLE IF EXISTS notifications;
+DROP TABLE IF EXISTS comments;
+DROP TABLE IF EXISTS shares;
+DROP TABLE IF EXISTS webhook_deliveries;
+DROP TABLE IF EXISTS webhooks;
+DROP TABLE IF EXISTS todo_changes;
+DROP TABLE IF EXISTS idempotency_keys;
+DROP TABLE IF EXISTS statuses;
+DROP TABLE IF EXISTS user_settings;
+DROP TABLE IF EXISTS users;
+DROP TABLE IF EXISTS todos;
\ No newline at end of file
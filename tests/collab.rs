@@ -0,0 +1,243 @@
+use rocket::config::{Config, LogLevel};
+use rocket::http::{ContentType, Status};
+use dooly::collab::ServerMessage;
+use dooly::helpers::{build_rocket, cleanup_database, establish_test_connection, run_seed_script, setup_rocket};
+use dooly::sync::ChangeKind;
+use serde_json::{json, Value};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::stream::MaybeTlsStream;
+use tokio_tungstenite::tungstenite::{connect, Message, WebSocket};
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+// Start the app on a free port, since WebSockets need a real connection
+fn launch_server() -> u16 {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let config = Config { port, log_level: LogLevel::Off, ..Config::debug_default() };
+    let rocket = build_rocket().configure(config);
+    thread::spawn(move || {
+        rocket::execute(rocket.launch()).expect("server failed to launch");
+    });
+
+    for _ in 0..100 {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            return port;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server did not start on port {}", port);
+}
+
+fn open(port: u16, user_id: i32) -> Socket {
+    let (socket, _) = connect(format!("ws://127.0.0.1:{}/ws?user_id={}", port, user_id)).unwrap();
+    if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    }
+    socket
+}
+
+fn send(socket: &mut Socket, message: Value) {
+    socket.send(Message::Text(message.to_string())).unwrap();
+}
+
+fn receive(socket: &mut Socket) -> ServerMessage {
+    loop {
+        if let Message::Text(text) = socket.read().unwrap() {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+fn subscribe(socket: &mut Socket, list_id: i32) -> Vec<i32> {
+    send(socket, json!({ "type": "subscribe", "list_id": list_id }));
+    match receive(socket) {
+        ServerMessage::Subscribed { list_id: subscribed, viewers } => {
+            assert_eq!(subscribed, list_id);
+            viewers
+        }
+        other => panic!("expected subscribed, got {:?}", other),
+    }
+}
+
+fn setup() -> u16 {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    launch_server()
+}
+
+#[test]
+fn test_websocket_requires_upgrade() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = client.get("/ws?user_id=1").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_subscribe_to_own_list_only() {
+    let port = setup();
+    let mut socket = open(port, 1);
+
+    assert_eq!(subscribe(&mut socket, 1), vec![1]);
+
+    send(&mut socket, json!({ "type": "subscribe", "list_id": 2 }));
+    match receive(&mut socket) {
+        ServerMessage::Error { status, .. } => assert_eq!(status, 403),
+        other => panic!("expected error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_mutations_are_validated_and_broadcast() {
+    let port = setup();
+    let mut writer = open(port, 1);
+    let mut reader = open(port, 1);
+    subscribe(&mut writer, 1);
+    subscribe(&mut reader, 1);
+    // The reader's own join reaches the writer too
+    assert!(matches!(receive(&mut writer), ServerMessage::Presence { .. }));
+
+    send(&mut writer, json!({ "type": "create", "request_id": "a", "list_id": 1, "todo": { "title": "" } }));
+    match receive(&mut writer) {
+        ServerMessage::Error { request_id, status, .. } => {
+            assert_eq!(request_id.as_deref(), Some("a"));
+            assert_eq!(status, 400);
+        }
+        other => panic!("expected error, got {:?}", other),
+    }
+
+    send(&mut writer, json!({ "type": "create", "request_id": "b", "list_id": 1, "todo": { "title": "Live" } }));
+    let mut acked = None;
+    let mut changed = false;
+    // The ack and the writer's own change event can arrive in either order
+    while acked.is_none() || !changed {
+        match receive(&mut writer) {
+            ServerMessage::Ack { request_id, id } => {
+                assert_eq!(request_id.as_deref(), Some("b"));
+                acked = Some(id);
+            }
+            ServerMessage::Change { .. } => changed = true,
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    match receive(&mut reader) {
+        ServerMessage::Change { list_id, event } => {
+            assert_eq!(list_id, 1);
+            assert_eq!(event.kind, ChangeKind::Created);
+            assert_eq!(Some(event.todo_id), acked);
+            assert_eq!(event.todo.unwrap().title, "Live");
+        }
+        other => panic!("expected change, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_stale_update_is_rejected() {
+    let port = setup();
+    let mut socket = open(port, 1);
+
+    send(&mut socket, json!({ "type": "update", "request_id": "u", "id": 1, "version": 7, "todo": { "title": "Stale" } }));
+    match receive(&mut socket) {
+        ServerMessage::Error { status, .. } => assert_eq!(status, 412),
+        other => panic!("expected error, got {:?}", other),
+    }
+
+    send(&mut socket, json!({ "type": "complete", "id": 42 }));
+    match receive(&mut socket) {
+        ServerMessage::Error { status, .. } => assert_eq!(status, 404),
+        other => panic!("expected error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_rest_changes_reach_subscribers() {
+    let port = setup();
+    let mut socket = open(port, 1);
+    subscribe(&mut socket, 1);
+
+    let response = complete_over_http(port);
+    assert!(response.starts_with("HTTP/1.1 200"));
+
+    match receive(&mut socket) {
+        ServerMessage::Change { event, .. } => {
+            assert_eq!(event.kind, ChangeKind::Completed);
+            assert_eq!(event.todo_id, 1);
+        }
+        other => panic!("expected change, got {:?}", other),
+    }
+}
+
+// Complete todo 1 through the REST API of the running server
+fn complete_over_http(port: u16) -> String {
+    use std::io::{Read, Write};
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(stream, "PUT /todos/1/complete?user_id=1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn test_presence_tracks_viewers() {
+    let port = setup();
+    let mut first = open(port, 1);
+    assert_eq!(subscribe(&mut first, 1), vec![1]);
+
+    let mut second = open(port, 1);
+    subscribe(&mut second, 1);
+    match receive(&mut first) {
+        ServerMessage::Presence { list_id, viewers } => {
+            assert_eq!(list_id, 1);
+            assert_eq!(viewers, vec![1]);
+        }
+        other => panic!("expected presence, got {:?}", other),
+    }
+
+    send(&mut second, json!({ "type": "unsubscribe", "list_id": 1 }));
+    assert!(matches!(receive(&mut second), ServerMessage::Unsubscribed { list_id: 1 }));
+    assert!(matches!(receive(&mut first), ServerMessage::Presence { list_id: 1, .. }));
+}
+
+#[test]
+fn test_shared_list_viewers() {
+    let port = setup();
+    let client = setup_rocket();
+    client.post("/users")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "friend", "password_hash": "hashed_password" }).to_string())
+        .dispatch();
+
+    let mut friend = open(port, 2);
+    send(&mut friend, json!({ "type": "subscribe", "list_id": 1 }));
+    assert!(matches!(receive(&mut friend), ServerMessage::Error { status: 403, .. }));
+
+    let response = client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "username": "friend", "role": "viewer" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let mut owner = open(port, 1);
+    subscribe(&mut owner, 1);
+    assert_eq!(subscribe(&mut friend, 1), vec![1, 2]);
+    match receive(&mut owner) {
+        ServerMessage::Presence { viewers, .. } => assert_eq!(viewers, vec![1, 2]),
+        other => panic!("expected presence, got {:?}", other),
+    }
+
+    // Viewers follow the list but cannot change it
+    send(&mut friend, json!({ "type": "complete", "id": 1 }));
+    assert!(matches!(receive(&mut friend), ServerMessage::Error { status: 403, .. }));
+    send(&mut friend, json!({ "type": "create", "list_id": 1, "todo": { "title": "Sneaky" } }));
+    assert!(matches!(receive(&mut friend), ServerMessage::Error { status: 403, .. }));
+}
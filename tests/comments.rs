@@ -0,0 +1,138 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use dooly::comments::{CommentPage, RenderedComment};
+use dooly::helpers::setup_with_friend;
+use dooly::notifications::{NotificationKind, NotificationList};
+use dooly::todos::TodoItem;
+use serde_json::json;
+
+// Seeded todos belong to user 1; user 2 can see todo 1 through a viewer share
+fn setup() -> Client {
+    let client = setup_with_friend();
+    let status = client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "username": "friend", "todo_id": 1, "role": "viewer" }).to_string())
+        .dispatch()
+        .status();
+    assert_eq!(status, Status::Ok);
+    client
+}
+
+fn comment(client: &Client, todo_id: i32, user_id: i32, body: &str) -> RenderedComment {
+    let response = client.post(format!("/todos/{}/comments", todo_id))
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": user_id, "body": body }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().unwrap()
+}
+
+#[test]
+fn test_add_comment_renders_markdown() {
+    let client = setup();
+
+    let added = comment(&client, 1, 2, "Looks **good**, see [docs](https://example.com)");
+    assert_eq!(added.comment.todo_id, 1);
+    assert_eq!(added.author.username, "friend");
+    assert_eq!(added.html, "<p>Looks <strong>good</strong>, see <a href=\"https://example.com\">docs</a></p>\n");
+
+    // Raw HTML and script links are not passed through
+    let added = comment(&client, 1, 1, "<script>alert(1)</script>\n\n[x](javascript:alert(1))");
+    assert!(!added.html.contains("<script>"));
+    assert!(added.html.contains("&lt;script&gt;"));
+    assert!(added.html.contains("href=\"#\""));
+
+    let response = client.post("/todos/1/comments")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "body": "   " }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // User 2 can't see todo 2
+    let response = client.post("/todos/2/comments")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 2, "body": "Hello" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_comments_are_paginated() {
+    let client = setup();
+    for n in 1..=5 {
+        comment(&client, 1, 1, &format!("Comment {}", n));
+    }
+
+    let page: CommentPage = client.get("/todos/1/comments?user_id=2&limit=2").dispatch().into_json().unwrap();
+    let bodies: Vec<_> = page.comments.iter().map(|comment| comment.comment.body.as_str()).collect();
+    assert_eq!(bodies, vec!["Comment 1", "Comment 2"]);
+
+    let page: CommentPage = client.get(format!("/todos/1/comments?user_id=1&limit=2&after={}", page.next.unwrap())).dispatch().into_json().unwrap();
+    assert_eq!(page.comments[0].comment.body, "Comment 3");
+
+    let page: CommentPage = client.get(format!("/todos/1/comments?user_id=1&limit=2&after={}", page.next.unwrap())).dispatch().into_json().unwrap();
+    assert_eq!(page.comments.len(), 1);
+    assert_eq!(page.next, None);
+
+    assert_eq!(client.get("/todos/2/comments?user_id=2").dispatch().status(), Status::NotFound);
+}
+
+#[test]
+fn test_only_author_can_edit_or_delete() {
+    let client = setup();
+    let added = comment(&client, 1, 2, "First draft");
+    let uri = format!("/todos/1/comments/{}", added.comment.id);
+
+    let response = client.put(uri.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "body": "Owner edit" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(client.delete(format!("{}?user_id=1", uri)).dispatch().status(), Status::Forbidden);
+
+    let response = client.put(uri.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 2, "body": "_Final_ draft" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let edited: RenderedComment = response.into_json().unwrap();
+    assert_eq!(edited.html, "<p><em>Final</em> draft</p>\n");
+    assert!(edited.comment.updated_at >= edited.comment.created_at);
+
+    assert_eq!(client.delete(format!("{}?user_id=2", uri)).dispatch().status(), Status::Ok);
+    assert_eq!(client.delete(format!("{}?user_id=2", uri)).dispatch().status(), Status::NotFound);
+}
+
+#[test]
+fn test_comment_counts_in_listings() {
+    let client = setup();
+    comment(&client, 1, 1, "One");
+    comment(&client, 1, 2, "Two");
+
+    let todos: Vec<TodoItem> = client.get("/todos?user_id=1").dispatch().into_json().unwrap();
+    let counts: Vec<_> = todos.iter().map(|todo| (todo.id, todo.comment_count)).collect();
+    assert_eq!(counts, vec![(1, 2), (2, 0)]);
+
+    let todo: TodoItem = client.get("/todos/1?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(todo.comment_count, 2);
+
+    // Deleting the todo takes its thread with it
+    assert_eq!(client.delete("/todos/1?user_id=1").dispatch().status(), Status::Ok);
+    assert_eq!(client.get("/todos/1/comments?user_id=1").dispatch().status(), Status::NotFound);
+}
+
+#[test]
+fn test_deleting_a_comment_removes_its_mentions() {
+    let client = setup();
+    let added = comment(&client, 1, 1, "@friend can you take a look?");
+
+    let mentions = |client: &Client| {
+        let list: NotificationList = client.get("/notifications?user_id=2").dispatch().into_json().unwrap();
+        list.notifications.into_iter().filter(|notification| notification.kind == NotificationKind::Mentioned).count()
+    };
+    assert_eq!(mentions(&client), 1);
+
+    let response = client.delete(format!("/todos/1/comments/{}?user_id=1", added.comment.id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(mentions(&client), 0);
+}
@@ -27,7 +27,7 @@ fn test_complete_todo() {
     assert_eq!(response.into_string().unwrap(), "Todo added successfully!");
 
     // Fetch the todo items to get the ID of the newly added todo
-    let response = client.get("/todos")
+    let response = client.get("/todos?user_id=1")
         .dispatch();
 
     assert_eq!(response.status(), Status::Ok);
@@ -35,14 +35,14 @@ fn test_complete_todo() {
     let todo_id = todos[0].id;  // Assuming this is the only todo item
 
     // Mark the todo item as completed
-    let response = client.put(format!("/todos/{}/complete", todo_id))
+    let response = client.put(format!("/todos/{}/complete?user_id=1", todo_id))
         .dispatch();
 
     assert_eq!(response.status(), Status::Ok);
     assert_eq!(response.into_string().unwrap(), "Todo marked as completed!");
 
     // Fetch the updated todo to verify the changes
-    let response = client.get("/todos")
+    let response = client.get("/todos?user_id=1")
         .dispatch();
 
     assert_eq!(response.status(), Status::Ok);
@@ -0,0 +1,210 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use dooly::{todos::TodoItem, helpers::{cleanup_database, establish_test_connection, run_seed_script, setup_rocket}};
+use serde_json::json;
+
+fn get_todo(client: &Client, id: i32) -> (String, TodoItem) {
+    let response = client.get(format!("/todos/{}?user_id=1", id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let etag = response.headers().get_one("ETag").unwrap().to_string();
+    (etag, serde_json::from_str(&response.into_string().unwrap()).unwrap())
+}
+
+fn update_title(client: &Client, etag: Option<&str>, title: &str) -> (Status, Option<String>) {
+    let mut request = client.put("/todos/1?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "title": title, "completed": false, "user_id": 1 }).to_string());
+    if let Some(etag) = etag {
+        request = request.header(Header::new("If-Match", etag.to_string()));
+    }
+
+    let response = request.dispatch();
+    (response.status(), response.headers().get_one("ETag").map(str::to_string))
+}
+
+#[test]
+fn test_get_todo_returns_etag() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let (etag, todo) = get_todo(&client, 1);
+    assert_eq!(etag, "\"1\"");
+    assert_eq!(todo.version, 1);
+    assert_eq!(todo.title, "Test Todo 1");
+
+    let response = client.get("/todos/999?user_id=1").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_concurrent_updates() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    // Two clients fetch the same todo
+    let (first, _) = get_todo(&client, 1);
+    let (second, _) = get_todo(&client, 1);
+
+    let (status, etag) = update_title(&client, Some(&first), "First edit");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(etag.as_deref(), Some("\"2\""));
+
+    // The second client is working from a stale copy
+    let (status, _) = update_title(&client, Some(&second), "Second edit");
+    assert_eq!(status, Status::PreconditionFailed);
+
+    let (etag, todo) = get_todo(&client, 1);
+    assert_eq!(etag, "\"2\"");
+    assert_eq!(todo.title, "First edit");
+
+    // After refetching, the second client's edit goes through
+    let (status, _) = update_title(&client, Some(&etag), "Second edit");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(get_todo(&client, 1).1.title, "Second edit");
+}
+
+#[test]
+fn test_update_without_if_match() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let (status, etag) = update_title(&client, None, "Unconditional");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(etag.as_deref(), Some("\"2\""));
+
+    let (status, _) = update_title(&client, Some("*"), "Any version");
+    assert_eq!(status, Status::Ok);
+
+    let (status, _) = update_title(&client, Some("not-an-etag"), "Bad header");
+    assert_eq!(status, Status::BadRequest);
+
+    assert_eq!(get_todo(&client, 1).1.version, 3);
+}
+
+#[test]
+fn test_complete_and_delete_with_if_match() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let (stale, _) = get_todo(&client, 1);
+    let (status, _) = update_title(&client, Some(&stale), "Edited");
+    assert_eq!(status, Status::Ok);
+
+    let response = client.put("/todos/1/complete?user_id=1")
+        .header(Header::new("If-Match", stale.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::PreconditionFailed);
+
+    let (current, _) = get_todo(&client, 1);
+    let response = client.put("/todos/1/complete?user_id=1")
+        .header(Header::new("If-Match", current.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let (etag, todo) = get_todo(&client, 1);
+    assert!(todo.completed);
+    assert_eq!(todo.version, 3);
+
+    let response = client.delete("/todos/1?user_id=1")
+        .header(Header::new("If-Match", current))
+        .dispatch();
+    assert_eq!(response.status(), Status::PreconditionFailed);
+
+    let response = client.delete("/todos/1?user_id=1")
+        .header(Header::new("If-Match", etag.clone()))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.delete("/todos/1?user_id=1")
+        .header(Header::new("If-Match", etag))
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_other_writes_bump_version() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = client.post("/todos/1/move?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "after": 2 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.post("/todos/bulk")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "operations": [{ "action": "set_priority", "priority": "low", "ids": [1] }] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    assert_eq!(get_todo(&client, 1).1.version, 3);
+    assert_eq!(get_todo(&client, 2).1.version, 1);
+}
+
+#[test]
+fn test_move_and_transition_with_if_match() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let (stale, _) = get_todo(&client, 1);
+    let (status, _) = update_title(&client, Some(&stale), "Edited");
+    assert_eq!(status, Status::Ok);
+
+    let response = client.post("/todos/1/move?user_id=1")
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", stale.clone()))
+        .body(json!({ "after": 2 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::PreconditionFailed);
+
+    let statuses: Vec<serde_json::Value> = client.get("/statuses?user_id=1").dispatch().into_json().unwrap();
+    let response = client.put("/todos/1/status?user_id=1")
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", stale))
+        .body(json!({ "status_id": statuses[1]["id"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::PreconditionFailed);
+
+    let (current, todo) = get_todo(&client, 1);
+    assert_eq!(todo.status_id, None);
+    let response = client.put("/todos/1/status?user_id=1")
+        .header(ContentType::JSON)
+        .header(Header::new("If-Match", current))
+        .body(json!({ "status_id": statuses[1]["id"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn test_weak_if_match_fails() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    // If-Match uses strong comparison, so even the current version's weak tag does not match
+    let (status, _) = update_title(&client, Some("W/\"1\""), "Edited");
+    assert_eq!(status, Status::PreconditionFailed);
+    let (status, _) = update_title(&client, Some("\"1\""), "Edited");
+    assert_eq!(status, Status::Ok);
+}
@@ -10,7 +10,7 @@ fn test_delete_todo() {
     let client = setup_rocket();
 
     // Assuming a todo item with ID 1 exists
-    let response = client.delete("/todos/1").dispatch();
+    let response = client.delete("/todos/1?user_id=1").dispatch();
 
     assert_eq!(response.status(), Status::Ok);
     assert_eq!(response.into_string().unwrap(), "Todo deleted successfully!");
@@ -0,0 +1,134 @@
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use dooly::helpers::{cleanup_database, establish_test_connection, run_seed_script, setup_rocket};
+use dooly::views::DateGroup;
+use chrono::{Duration, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use rocket::http::ContentType;
+
+fn get_json(client: &Client, uri: &str) -> Value {
+    let response = client.get(uri.to_string()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    serde_json::from_str(&response.into_string().unwrap()).unwrap()
+}
+
+#[test]
+fn test_add_todo_with_due_time() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let new_todo = json!({
+        "title": "Call the bank",
+        "completed": false,
+        "user_id": 1,
+        "due_at": "2030-05-01T15:00:00-05:00"
+    });
+
+    let response = client.post("/todos")
+        .header(ContentType::JSON)
+        .body(new_todo.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // The deadline is stored in UTC and the due date is derived from it
+    let todos = get_json(&client, "/todos?user_id=1");
+    assert_eq!(todos[2]["due_at"], "2030-05-01T20:00:00Z");
+    assert_eq!(todos[2]["due_date"], "2030-05-01");
+
+    // Existing todos keep a plain due date without a time
+    assert_eq!(todos[0]["due_at"], Value::Null);
+}
+
+#[test]
+fn test_due_date_follows_user_timezone() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let settings = json!({
+        "timezone": "Pacific/Kiritimati",
+        "week_start": "monday",
+        "date_format": "%Y-%m-%d"
+    });
+    let response = client.put("/users/1/settings")
+        .header(ContentType::JSON)
+        .body(settings.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let updated_todo = json!({
+        "title": "Late night deadline",
+        "completed": false,
+        "user_id": 1,
+        "due_at": "2030-05-01T20:00:00Z"
+    });
+    let response = client.put("/todos/1?user_id=1")
+        .header(ContentType::JSON)
+        .body(updated_todo.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // 20:00 UTC is already the next day fourteen hours ahead
+    let todos = get_json(&client, "/todos?user_id=1");
+    assert_eq!(todos[0]["due_at"], "2030-05-01T20:00:00Z");
+    assert_eq!(todos[0]["due_date"], "2030-05-02");
+}
+
+#[test]
+fn test_past_due_time_is_overdue() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let an_hour_ago = (Utc::now() - Duration::hours(1)).to_rfc3339_opts(SecondsFormat::Secs, true);
+    let new_todo = json!({
+        "title": "Missed meeting prep",
+        "completed": false,
+        "user_id": 1,
+        "due_at": an_hour_ago
+    });
+    let response = client.post("/todos")
+        .header(ContentType::JSON)
+        .body(new_todo.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let overdue: Vec<DateGroup> = serde_json::from_value(get_json(&client, "/views/overdue?user_id=1")).unwrap();
+    assert_eq!(overdue.len(), 1);
+    assert_eq!(overdue[0].todos[0].title, "Missed meeting prep");
+
+    let today: Vec<DateGroup> = serde_json::from_value(get_json(&client, "/views/today?user_id=1")).unwrap();
+    assert!(today.is_empty());
+
+    let stats = get_json(&client, "/stats?user_id=1");
+    assert_eq!(stats["users"][0]["overdue"], 1);
+}
+
+#[test]
+fn test_add_todo_invalid_due_time() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let new_todo = json!({
+        "title": "Sometime",
+        "completed": false,
+        "user_id": 1,
+        "due_at": "tomorrow at 3"
+    });
+
+    let response = client.post("/todos")
+        .header(ContentType::JSON)
+        .body(new_todo.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
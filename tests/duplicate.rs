@@ -0,0 +1,90 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use dooly::attachments::Attachment;
+use dooly::helpers::setup_with_friend;
+use dooly::todos::TodoItem;
+use serde_json::json;
+
+const BOUNDARY: &str = "dooly-test-boundary";
+
+fn duplicate(client: &Client, id: i32, body: serde_json::Value) -> (Status, Option<TodoItem>) {
+    let response = client.post(format!("/todos/{}/duplicate", id))
+        .header(ContentType::JSON)
+        .body(body.to_string())
+        .dispatch();
+    let status = response.status();
+    (status, if status == Status::Ok { response.into_json() } else { None })
+}
+
+#[test]
+fn test_duplicate_completed_todo() {
+    let client = setup_with_friend();
+    client.put("/todos/2?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Test Todo 2", "description": "Details", "priority": "high", "completed": true, "user_id": 1, "estimate_minutes": 30 }).to_string())
+        .dispatch();
+
+    let (status, copy) = duplicate(&client, 2, json!({ "user_id": 1 }));
+    assert_eq!(status, Status::Ok);
+    let copy = copy.unwrap();
+    assert_eq!(copy.id, 3);
+    assert_eq!(copy.title, "Test Todo 2 (copy)");
+    assert_eq!(copy.description.as_deref(), Some("Details"));
+    assert_eq!(copy.estimate_minutes, Some(30));
+    assert!(!copy.completed);
+    assert_eq!(copy.completed_at, None);
+
+    let (_, copy) = duplicate(&client, 2, json!({ "user_id": 1, "title": "Fresh start", "description": false }));
+    let copy = copy.unwrap();
+    assert_eq!(copy.title, "Fresh start");
+    assert_eq!(copy.description, None);
+}
+
+#[test]
+fn test_duplicate_requires_access() {
+    let client = setup_with_friend();
+
+    assert_eq!(duplicate(&client, 1, json!({ "user_id": 2 })).0, Status::NotFound);
+    assert_eq!(duplicate(&client, 42, json!({ "user_id": 1 })).0, Status::NotFound);
+
+    client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "username": "friend", "todo_id": 1, "role": "viewer" }).to_string())
+        .dispatch();
+
+    // A viewer's copy lands in their own list
+    let (status, copy) = duplicate(&client, 1, json!({ "user_id": 2 }));
+    assert_eq!(status, Status::Ok);
+    assert_eq!(copy.unwrap().user_id, 2);
+}
+
+#[test]
+fn test_duplicate_with_attachments() {
+    let client = setup_with_friend();
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"user_id\"\r\n\r\n1\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\nContent-Type: text/plain\r\n\r\nRemember the milk\r\n\
+         --{b}--\r\n",
+        b = BOUNDARY,
+    );
+    let response = client.post("/todos/1/attachments")
+        .header(ContentType::new("multipart", "form-data").with_params(("boundary", BOUNDARY)))
+        .body(body)
+        .dispatch();
+    let original: Attachment = response.into_json().unwrap();
+
+    let (_, copy) = duplicate(&client, 1, json!({ "user_id": 1 }));
+    let listed: Vec<Attachment> = client.get(format!("/todos/{}/attachments?user_id=1", copy.unwrap().id)).dispatch().into_json().unwrap();
+    assert!(listed.is_empty());
+
+    let (_, copy) = duplicate(&client, 1, json!({ "user_id": 1, "attachments": true }));
+    let copy = copy.unwrap();
+    let listed: Vec<Attachment> = client.get(format!("/todos/{}/attachments?user_id=1", copy.id)).dispatch().into_json().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].digest, original.digest);
+
+    // The copy's attachment outlives the original todo
+    client.delete("/todos/1?user_id=1").dispatch();
+    let response = client.get(format!("/todos/{}/attachments/{}?user_id=1", copy.id, listed[0].id)).dispatch();
+    assert_eq!(response.into_string().unwrap(), "Remember the milk");
+}
@@ -0,0 +1,131 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::{Client, LocalResponse};
+use dooly::events::TodoEvent;
+use dooly::helpers::{cleanup_database, establish_test_connection, run_seed_script, setup_rocket};
+use dooly::sync::ChangeKind;
+use serde_json::json;
+use std::io::{BufRead, BufReader};
+
+// Read `count` events off a stream that otherwise stays open
+fn read_events(response: LocalResponse<'_>, count: usize) -> Vec<(String, TodoEvent)> {
+    let mut reader = BufReader::new(response);
+    let mut events = Vec::new();
+    let mut name = String::new();
+
+    while events.len() < count {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if let Some(value) = line.strip_prefix("event:") {
+            name = value.trim().to_string();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            events.push((name.clone(), serde_json::from_str(value.trim()).unwrap()));
+        }
+    }
+
+    events
+}
+
+fn add_todo(client: &Client, title: &str, user_id: i32) {
+    let response = client.post("/todos")
+        .header(ContentType::JSON)
+        .body(json!({ "title": title, "completed": false, "user_id": user_id }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn test_stream_receives_live_changes() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let stream = client.get("/events?user_id=1").dispatch();
+    assert_eq!(stream.status(), Status::Ok);
+    assert_eq!(stream.content_type(), Some(ContentType::EventStream));
+
+    add_todo(&client, "Streamed", 1);
+    client.put("/todos/3/complete?user_id=1").dispatch();
+    client.put("/todos/1?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Renamed", "completed": false, "user_id": 1 }).to_string())
+        .dispatch();
+    client.delete("/todos/2?user_id=1").dispatch();
+
+    let events = read_events(stream, 4);
+    let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec!["created", "completed", "updated", "deleted"]);
+
+    assert_eq!(events[0].1.kind, ChangeKind::Created);
+    assert_eq!(events[0].1.todo_id, 3);
+    assert_eq!(events[2].1.todo.as_ref().unwrap().title, "Renamed");
+    assert_eq!(events[3].1.todo_id, 2);
+    assert!(events[3].1.todo.is_none());
+}
+
+#[test]
+fn test_stream_only_sends_own_todos() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = client.post("/users")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "other_user", "password_hash": "hash" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let stream = client.get("/events?user_id=1").dispatch();
+
+    add_todo(&client, "Someone else's", 2);
+    add_todo(&client, "Mine", 1);
+
+    let events = read_events(stream, 1);
+    assert_eq!(events[0].1.user_id, 1);
+    assert_eq!(events[0].1.todo.as_ref().unwrap().title, "Mine");
+}
+
+#[test]
+fn test_stream_resumes_from_last_event_id() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    add_todo(&client, "Seen", 1);
+    add_todo(&client, "Missed", 1);
+    client.put("/todos/4/complete?user_id=1").dispatch();
+
+    // The client saw event 1 before disconnecting
+    let stream = client.get("/events?user_id=1")
+        .header(Header::new("Last-Event-ID", "1"))
+        .dispatch();
+    assert_eq!(stream.status(), Status::Ok);
+
+    add_todo(&client, "Live", 1);
+
+    let events = read_events(stream, 3);
+    let ids: Vec<i32> = events.iter().map(|(_, event)| event.id).collect();
+    assert_eq!(ids, vec![2, 3, 4]);
+    assert_eq!(events[0].1.todo.as_ref().unwrap().title, "Missed");
+    assert_eq!(events[1].0, "completed");
+    assert_eq!(events[2].1.todo.as_ref().unwrap().title, "Live");
+}
+
+#[test]
+fn test_invalid_last_event_id() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = client.get("/events?user_id=1")
+        .header(Header::new("Last-Event-ID", "yesterday"))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
@@ -10,7 +10,7 @@ fn test_get_todos() {
     let client = setup_rocket();
 
     // Send a GET request to the /todos endpoint
-    let response = client.get("/todos").dispatch();
+    let response = client.get("/todos?user_id=1").dispatch();
 
     // Assert that the response status is OK (200)
     assert_eq!(response.status(), Status::Ok);
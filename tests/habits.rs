@@ -0,0 +1,159 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use chrono::{Datelike, Duration, NaiveDate, Utc, Weekday};
+use dooly::habits::{streaks, CheckIn, Frequency, HabitPeriod, HabitSummary, Heatmap, Streaks};
+use dooly::helpers::setup_seeded;
+use serde_json::json;
+
+fn add_habit(client: &Client, body: serde_json::Value) -> HabitSummary {
+    let response = client.post("/habits")
+        .header(ContentType::JSON)
+        .body(body.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().unwrap()
+}
+
+fn check_in(client: &Client, id: i32, body: serde_json::Value) -> (Status, Option<HabitSummary>) {
+    let response = client.post(format!("/habits/{}/checkins", id))
+        .header(ContentType::JSON)
+        .body(body.to_string())
+        .dispatch();
+    let status = response.status();
+    (status, if status == Status::Ok { response.into_json() } else { None })
+}
+
+fn date(value: &str) -> NaiveDate {
+    value.parse().unwrap()
+}
+
+fn days(dates: &[&str]) -> Vec<CheckIn> {
+    dates.iter().map(|value| CheckIn { date: date(value), count: 1 }).collect()
+}
+
+#[test]
+fn test_daily_streaks() {
+    let today = date("2024-05-10");
+    let checkins = days(&["2024-05-01", "2024-05-02", "2024-05-03", "2024-05-08", "2024-05-09"]);
+
+    // Today isn't done yet, so the streak up to yesterday still counts
+    assert_eq!(streaks(Frequency::Daily, 1, &checkins, today, Weekday::Mon), Streaks { current: 2, longest: 3 });
+
+    let mut checkins = checkins;
+    checkins.push(CheckIn { date: today, count: 1 });
+    assert_eq!(streaks(Frequency::Daily, 1, &checkins, today, Weekday::Mon), Streaks { current: 3, longest: 3 });
+
+    // A day without check-ins breaks it
+    assert_eq!(streaks(Frequency::Daily, 1, &checkins, date("2024-05-12"), Weekday::Mon), Streaks { current: 0, longest: 3 });
+    assert_eq!(streaks(Frequency::Daily, 2, &checkins, today, Weekday::Mon), Streaks { current: 0, longest: 0 });
+}
+
+#[test]
+fn test_weekly_streaks() {
+    // Friday; weeks start on Monday the 6th, 29th of April and so on
+    let today = date("2024-05-10");
+    let checkins = days(&["2024-04-28", "2024-04-29", "2024-05-05", "2024-05-06"]);
+
+    assert_eq!(streaks(Frequency::Weekly, 2, &checkins, today, Weekday::Mon), Streaks { current: 1, longest: 1 });
+
+    // With Sunday-based weeks the same check-ins pair up differently
+    assert_eq!(streaks(Frequency::Weekly, 2, &checkins, today, Weekday::Sun), Streaks { current: 2, longest: 2 });
+}
+
+#[test]
+fn test_check_in() {
+    let client = setup_seeded();
+    let habit = add_habit(&client, json!({ "user_id": 1, "name": "Drink water", "frequency": "daily", "target": 3 }));
+    assert_eq!(habit.streaks, Streaks { current: 0, longest: 0 });
+
+    let today = habit.period_start;
+    let yesterday = today - Duration::days(1);
+    let (_, summary) = check_in(&client, habit.habit.id, json!({ "user_id": 1 }));
+    assert_eq!(summary.unwrap().period_count, 1);
+    let (_, summary) = check_in(&client, habit.habit.id, json!({ "user_id": 1, "count": 2 }));
+    let summary = summary.unwrap();
+    assert_eq!(summary.period_count, 3);
+    assert_eq!(summary.streaks, Streaks { current: 1, longest: 1 });
+
+    let (_, summary) = check_in(&client, habit.habit.id, json!({ "user_id": 1, "date": yesterday, "count": 3 }));
+    assert_eq!(summary.unwrap().streaks, Streaks { current: 2, longest: 2 });
+
+    let tomorrow = today + Duration::days(1);
+    assert_eq!(check_in(&client, habit.habit.id, json!({ "user_id": 1, "date": tomorrow })).0, Status::BadRequest);
+    assert_eq!(check_in(&client, habit.habit.id, json!({ "user_id": 1, "count": 0 })).0, Status::BadRequest);
+    assert_eq!(check_in(&client, habit.habit.id, json!({ "user_id": 1, "count": 1001 })).0, Status::BadRequest);
+    assert_eq!(check_in(&client, habit.habit.id, json!({ "user_id": 1, "count": 998 })).0, Status::BadRequest);
+    assert_eq!(check_in(&client, habit.habit.id, json!({ "user_id": 1, "count": 997 })).0, Status::Ok);
+    assert_eq!(check_in(&client, habit.habit.id, json!({ "user_id": 2 })).0, Status::NotFound);
+
+    let response = client.delete(format!("/habits/{}/checkins/{}?user_id=1", habit.habit.id, yesterday)).dispatch();
+    let summary: HabitSummary = response.into_json().unwrap();
+    assert_eq!(summary.streaks, Streaks { current: 1, longest: 1 });
+    let response = client.delete(format!("/habits/{}/checkins/{}?user_id=1", habit.habit.id, yesterday)).dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    // A retried check-in is only counted once
+    for _ in 0..2 {
+        let response = client.post(format!("/habits/{}/checkins", habit.habit.id))
+            .header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "check-in-retry"))
+            .body(json!({ "user_id": 1, "date": yesterday }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+    let history: Vec<HabitPeriod> = client.get(format!("/habits/{}/history?user_id=1&from={}&to={}", habit.habit.id, yesterday, yesterday))
+        .dispatch().into_json().unwrap();
+    assert_eq!(history[0].count, 1);
+}
+
+#[test]
+fn test_check_in_uses_local_date() {
+    let client = setup_seeded();
+    client.put("/users/1/settings")
+        .header(ContentType::JSON)
+        .body(json!({ "timezone": "Pacific/Kiritimati", "week_start": "monday", "date_format": "%Y-%m-%d" }).to_string())
+        .dispatch();
+
+    let habit = add_habit(&client, json!({ "user_id": 1, "name": "Stretch", "frequency": "daily" }));
+    let (_, summary) = check_in(&client, habit.habit.id, json!({ "user_id": 1 }));
+    let summary = summary.unwrap();
+
+    // Kiritimati is 14 hours ahead of UTC
+    assert_eq!(summary.period_start, (Utc::now() + Duration::hours(14)).date_naive());
+    assert_eq!(summary.period_count, 1);
+}
+
+#[test]
+fn test_history_and_heatmap() {
+    let client = setup_seeded();
+    let habit = add_habit(&client, json!({ "user_id": 1, "name": "Run", "frequency": "weekly", "target": 2 }));
+    let id = habit.habit.id;
+    let today = Utc::now().date_naive();
+    let yesterday = today - Duration::days(1);
+    check_in(&client, id, json!({ "user_id": 1, "date": today, "count": 4 }));
+    check_in(&client, id, json!({ "user_id": 1, "date": yesterday }));
+
+    let uri = format!("/habits/{}/history?user_id=1&from={}&to={}", id, today - Duration::days(13), today);
+    let history: Vec<HabitPeriod> = client.get(uri).dispatch().into_json().unwrap();
+    assert!(history.len() == 2 || history.len() == 3);
+    assert_eq!(history.last().unwrap().start, habit.period_start);
+    assert_eq!(history.iter().map(|period| period.count).sum::<i32>(), 5);
+    assert!(history.last().unwrap().met);
+    let uri = format!("/habits/{}/history?user_id=1&from={}&to={}", id, today - Duration::days(731), today);
+    assert_eq!(client.get(uri).dispatch().status(), Status::BadRequest);
+
+    let heatmap: Heatmap = client.get(format!("/habits/{}/heatmap?user_id=1&weeks=2", id)).dispatch().into_json().unwrap();
+    assert_eq!(heatmap.to, today);
+    assert_eq!(heatmap.from.weekday(), Weekday::Mon);
+    assert_eq!(heatmap.days.len() as i64, (today - heatmap.from).num_days() + 1);
+    assert_eq!(heatmap.max, 4);
+    let level = |date: NaiveDate| heatmap.days.iter().find(|day| day.date == date).unwrap().level;
+    assert_eq!(level(today), 4);
+    assert_eq!(level(yesterday), 1);
+    assert_eq!(level(heatmap.from), 0);
+
+    assert_eq!(client.get(format!("/habits/{}/heatmap?user_id=1&weeks=0", id)).dispatch().status(), Status::BadRequest);
+    assert_eq!(client.delete(format!("/habits/{}?user_id=1", id)).dispatch().status(), Status::Ok);
+    let listed: Vec<HabitSummary> = client.get("/habits?user_id=1").dispatch().into_json().unwrap();
+    assert!(listed.is_empty());
+}
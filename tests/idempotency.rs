@@ -0,0 +1,148 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::{Client, LocalResponse};
+use diesel::prelude::*;
+use dooly::{todos::TodoItem, helpers::{cleanup_database, establish_test_connection, run_seed_script, setup_rocket}};
+use serde_json::{json, Value};
+
+fn post_with_key<'c>(client: &'c Client, uri: &'static str, key: &str, body: Value) -> LocalResponse<'c> {
+    client.post(uri)
+        .header(ContentType::JSON)
+        .header(Header::new("Idempotency-Key", key.to_string()))
+        .body(body.to_string())
+        .dispatch()
+}
+
+fn todo_count(client: &Client) -> usize {
+    let response = client.get("/todos?user_id=1").dispatch();
+    let todos: Vec<TodoItem> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    todos.len()
+}
+
+#[test]
+fn test_retried_add_todo_is_replayed() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    let new_todo = json!({ "title": "Buy milk", "completed": false, "user_id": 1 });
+
+    let response = post_with_key(&client, "/todos", "retry-1", new_todo.clone());
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.headers().get_one("Idempotent-Replayed").is_none());
+    assert_eq!(response.into_string().unwrap(), "Todo added successfully!");
+
+    // The retry gets the same response without creating another todo
+    let response = post_with_key(&client, "/todos", "retry-1", new_todo.clone());
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Idempotent-Replayed"), Some("true"));
+    assert_eq!(response.into_string().unwrap(), "Todo added successfully!");
+    assert_eq!(todo_count(&client), 3);
+
+    // A new key is a new request
+    let response = post_with_key(&client, "/todos", "retry-2", new_todo);
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(todo_count(&client), 4);
+}
+
+#[test]
+fn test_key_reused_with_different_body() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = post_with_key(&client, "/todos", "reused", json!({ "title": "First", "completed": false, "user_id": 1 }));
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = post_with_key(&client, "/todos", "reused", json!({ "title": "Second", "completed": false, "user_id": 1 }));
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(response.into_string().unwrap(), "Idempotency-Key was already used for a different request");
+
+    // Same body on a different endpoint is a different request too
+    let response = post_with_key(&client, "/users", "reused", json!({ "username": "other", "password_hash": "hash" }));
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    // So is the same body for a different acting user
+    let response = post_with_key(&client, "/todos/1/move?user_id=1", "moved", json!({ "after": 2 }));
+    assert_eq!(response.status(), Status::Ok);
+    let response = post_with_key(&client, "/todos/1/move?user_id=2", "moved", json!({ "after": 2 }));
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    assert_eq!(todo_count(&client), 3);
+}
+
+#[test]
+fn test_client_errors_are_replayed() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    let new_user = json!({ "username": "", "password_hash": "hash" });
+
+    for _ in 0..2 {
+        let response = post_with_key(&client, "/users", "bad-user", new_user.clone());
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(response.into_string().unwrap(), "Username cannot be empty");
+    }
+}
+
+#[test]
+fn test_json_responses_are_replayed() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    let request = json!({ "user_id": 1, "operations": [{ "action": "delete", "ids": [1] }] });
+
+    let response = post_with_key(&client, "/todos/bulk", "bulk-delete", request.clone());
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let first = response.into_string().unwrap();
+
+    // Replaying reports the original success rather than a not-found for the deleted todo
+    let response = post_with_key(&client, "/todos/bulk", "bulk-delete", request);
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    assert_eq!(response.into_string().unwrap(), first);
+}
+
+#[test]
+fn test_expired_keys_are_forgotten() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    let new_todo = json!({ "title": "Water plants", "completed": false, "user_id": 1 });
+
+    let response = post_with_key(&client, "/todos", "old-key", new_todo.clone());
+    assert_eq!(response.status(), Status::Ok);
+
+    // Age the key past the retention window
+    let mut connection = pool.get().unwrap();
+    diesel::sql_query("UPDATE idempotency_keys SET created_at = datetime('now', '-2 days')")
+        .execute(&mut connection)
+        .unwrap();
+
+    let response = post_with_key(&client, "/todos", "old-key", new_todo);
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.headers().get_one("Idempotent-Replayed").is_none());
+    assert_eq!(todo_count(&client), 4);
+}
+
+#[test]
+fn test_invalid_idempotency_key() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = post_with_key(&client, "/todos", &"k".repeat(256), json!({ "title": "Too long", "completed": false, "user_id": 1 }));
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(todo_count(&client), 2);
+}
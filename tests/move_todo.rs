@@ -0,0 +1,145 @@
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use dooly::helpers::{cleanup_database, establish_test_connection, run_seed_script, setup_rocket};
+use dooly::sync::SyncResponse;
+use dooly::todos::TodoItem;
+use serde_json::{json, Value};
+use rocket::http::ContentType;
+
+fn add_todos(client: &Client, titles: &[&str]) {
+    for title in titles {
+        let new_todo = json!({
+            "title": title,
+            "completed": false,
+            "user_id": 1
+        });
+
+        let response = client.post("/todos")
+            .header(ContentType::JSON)
+            .body(new_todo.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
+
+fn move_todo(client: &Client, id: i32, request: Value) -> Status {
+    client.post(format!("/todos/{}/move?user_id=1", id))
+        .header(ContentType::JSON)
+        .body(request.to_string())
+        .dispatch()
+        .status()
+}
+
+fn get_todo(client: &Client, id: i32) -> TodoItem {
+    let response = client.get(format!("/todos/{}?user_id=1", id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    serde_json::from_str(&response.into_string().unwrap()).unwrap()
+}
+
+fn listed_ids(client: &Client) -> Vec<i32> {
+    let response = client.get("/todos/search?user_id=1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let todos: Vec<TodoItem> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    todos.iter().map(|todo| todo.id).collect()
+}
+
+#[test]
+fn test_new_todos_are_appended() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    add_todos(&client, &["Third", "Fourth"]);
+
+    assert_eq!(listed_ids(&client), [1, 2, 3, 4]);
+}
+
+#[test]
+fn test_move_todo_before_and_after() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    add_todos(&client, &["Third", "Fourth", "Fifth"]);
+
+    assert_eq!(move_todo(&client, 5, json!({ "before": 1 })), Status::Ok);
+    assert_eq!(listed_ids(&client), [5, 1, 2, 3, 4]);
+
+    assert_eq!(move_todo(&client, 1, json!({ "after": 4 })), Status::Ok);
+    assert_eq!(listed_ids(&client), [5, 2, 3, 4, 1]);
+
+    assert_eq!(move_todo(&client, 5, json!({ "after": 2, "before": 3 })), Status::Ok);
+    assert_eq!(listed_ids(&client), [2, 5, 3, 4, 1]);
+
+    // The full listing uses the same manual order
+    let response = client.get("/todos?user_id=1").dispatch();
+    let todos: Vec<TodoItem> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    let ids: Vec<i32> = todos.iter().map(|todo| todo.id).collect();
+    assert_eq!(ids, [2, 5, 3, 4, 1]);
+}
+
+#[test]
+fn test_repeated_moves_rebalance() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    add_todos(&client, &["Third"]);
+    let before = get_todo(&client, 1);
+
+    // Keep squeezing todos into the same gap until a key would be longer than ten digits
+    for round in 0..100 {
+        let (moving, target) = if round % 2 == 0 { (3, 2) } else { (2, 3) };
+        assert_eq!(move_todo(&client, moving, json!({ "after": 1, "before": target })), Status::Ok);
+    }
+
+    // The last round moved todo 2 back in front of todo 3
+    assert_eq!(listed_ids(&client), [1, 2, 3]);
+
+    let response = client.get("/todos?user_id=1").dispatch();
+    let todos: Vec<TodoItem> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert!(todos.iter().all(|todo| todo.position.len() <= 10));
+
+    // Todo 1 never moved, so a new key means the list was respaced without editing it
+    let after = get_todo(&client, 1);
+    assert_ne!(after.position, before.position);
+    assert_eq!(after.version, before.version);
+
+    // Clients still hear about the new key
+    let response = client.get("/sync?user_id=1&since=1").dispatch();
+    let sync: SyncResponse = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    let respaced = sync.updated.iter().find(|todo| todo.id == 1).unwrap();
+    assert_eq!(respaced.position, after.position);
+}
+
+#[test]
+fn test_move_todo_invalid() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let new_user = json!({ "username": "other_user", "password_hash": "hashed_password" });
+    client.post("/users")
+        .header(ContentType::JSON)
+        .body(new_user.to_string())
+        .dispatch();
+    let other_todo = json!({ "title": "Other list", "completed": false, "user_id": 2 });
+    client.post("/todos")
+        .header(ContentType::JSON)
+        .body(other_todo.to_string())
+        .dispatch();
+
+    assert_eq!(move_todo(&client, 1, json!({})), Status::BadRequest);
+    assert_eq!(move_todo(&client, 1, json!({ "before": 1 })), Status::BadRequest);
+    assert_eq!(move_todo(&client, 1, json!({ "before": 3 })), Status::BadRequest);
+    assert_eq!(move_todo(&client, 1, json!({ "after": 2, "before": 2 })), Status::BadRequest);
+    assert_eq!(move_todo(&client, 1, json!({ "after": 999 })), Status::NotFound);
+    assert_eq!(move_todo(&client, 999, json!({ "after": 1 })), Status::NotFound);
+
+    assert_eq!(listed_ids(&client), [1, 2]);
+}
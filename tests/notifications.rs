@@ -0,0 +1,163 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use dooly::helpers::setup_with_friend;
+use dooly::notifications::{mentions, Notification, NotificationKind, NotificationList, NotificationPreferences};
+use serde_json::json;
+
+// Seeded todos belong to user 1; user 2 is added to notify
+fn share_list(client: &Client) {
+    let status = client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "username": "friend", "role": "editor" }).to_string())
+        .dispatch()
+        .status();
+    assert_eq!(status, Status::Ok);
+}
+
+fn notifications(client: &Client, user_id: i32) -> NotificationList {
+    let response = client.get(format!("/notifications?user_id={}", user_id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().unwrap()
+}
+
+fn comment(client: &Client, user_id: i32, body: &str) {
+    let status = client.post("/todos/1/comments")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": user_id, "body": body }).to_string())
+        .dispatch()
+        .status();
+    assert_eq!(status, Status::Ok);
+}
+
+#[test]
+fn test_mentions_are_parsed() {
+    assert_eq!(mentions("@friend and @test_user, thanks @friend."), vec!["friend", "test_user"]);
+    assert!(mentions("mail someone@example.com or @ nobody").is_empty());
+}
+
+#[test]
+fn test_share_and_assignment_notify() {
+    let client = setup_with_friend();
+    share_list(&client);
+
+    let status = client.put("/todos/1/assignee")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "assignee_id": 2 }).to_string())
+        .dispatch()
+        .status();
+    assert_eq!(status, Status::Ok);
+
+    let list = notifications(&client, 2);
+    assert_eq!(list.unread, 2);
+    let kinds: Vec<_> = list.notifications.iter().map(|notification| notification.kind).collect();
+    assert_eq!(kinds, vec![NotificationKind::Assigned, NotificationKind::Shared]);
+    assert_eq!(list.notifications[0].message, "test_user assigned you \"Test Todo 1\"");
+    assert_eq!(list.notifications[0].actor_id, Some(1));
+    assert_eq!(list.notifications[1].message, "test_user shared their list with you as editor");
+
+    // Taking a todo on yourself is not news
+    client.put("/todos/2/assignee")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 2, "assignee_id": 2 }).to_string())
+        .dispatch();
+    assert_eq!(notifications(&client, 2).unread, 2);
+    assert_eq!(notifications(&client, 1).unread, 0);
+}
+
+#[test]
+fn test_mentions_notify_users_who_can_see_the_todo() {
+    let client = setup_with_friend();
+    client.post("/users")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "stranger", "password_hash": "hashed_password" }).to_string())
+        .dispatch();
+
+    // Only the list's owner and users it is shared with hear about mentions
+    comment(&client, 1, "Thoughts @friend? cc @stranger @test_user");
+    assert_eq!(notifications(&client, 2).unread, 0);
+
+    share_list(&client);
+    comment(&client, 1, "Thoughts @friend? cc @stranger @test_user");
+    assert_eq!(notifications(&client, 3).unread, 0);
+    assert_eq!(notifications(&client, 1).unread, 0);
+
+    let list = notifications(&client, 2);
+    let mentioned: Vec<_> = list.notifications.iter().filter(|notification| notification.kind == NotificationKind::Mentioned).collect();
+    assert_eq!(mentioned.len(), 1);
+    assert_eq!(mentioned[0].todo_id, Some(1));
+    assert!(mentioned[0].comment_id.is_some());
+    assert_eq!(mentioned[0].message, "test_user mentioned you on \"Test Todo 1\"");
+}
+
+#[test]
+fn test_mark_read() {
+    let client = setup_with_friend();
+    share_list(&client);
+    comment(&client, 1, "@friend one");
+    comment(&client, 1, "@friend two");
+
+    let list = notifications(&client, 2);
+    assert_eq!(list.unread, 3);
+
+    let id = list.notifications[0].id;
+    assert_eq!(client.put(format!("/notifications/{}/read?user_id=1", id)).dispatch().status(), Status::NotFound);
+    let response = client.put(format!("/notifications/{}/read?user_id=2", id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.into_json::<Notification>().unwrap().read_at.is_some());
+
+    let response = client.get("/notifications?user_id=2&unread=true").dispatch();
+    let list: NotificationList = response.into_json().unwrap();
+    assert_eq!(list.unread, 2);
+    assert_eq!(list.notifications.len(), 2);
+
+    assert_eq!(client.put("/notifications/read?user_id=2").dispatch().status(), Status::Ok);
+    let list = notifications(&client, 2);
+    assert_eq!(list.unread, 0);
+    assert_eq!(list.notifications.len(), 3);
+}
+
+#[test]
+fn test_due_soon_is_raised_once() {
+    let client = setup_with_friend();
+    let today = chrono::Utc::now().date_naive();
+
+    client.post("/todos")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Pay rent", "completed": false, "user_id": 1, "due_date": today }).to_string())
+        .dispatch();
+    client.post("/todos")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Next month", "completed": false, "user_id": 1, "due_date": today + chrono::Duration::days(30) }).to_string())
+        .dispatch();
+
+    let list = notifications(&client, 1);
+    assert_eq!(list.notifications.len(), 1);
+    assert_eq!(list.notifications[0].kind, NotificationKind::DueSoon);
+    assert_eq!(list.notifications[0].message, "\"Pay rent\" is due soon");
+
+    assert_eq!(notifications(&client, 1).notifications.len(), 1);
+}
+
+#[test]
+fn test_preferences_silence_a_kind() {
+    let client = setup_with_friend();
+
+    let preferences: NotificationPreferences = client.get("/notifications/preferences?user_id=2").dispatch().into_json().unwrap();
+    assert!(preferences.shared && preferences.mentioned);
+
+    let response = client.put("/notifications/preferences?user_id=2")
+        .header(ContentType::JSON)
+        .body(json!({ "assigned": true, "mentioned": true, "shared": false, "due_soon": true }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(!response.into_json::<NotificationPreferences>().unwrap().shared);
+
+    share_list(&client);
+    comment(&client, 1, "Welcome @friend");
+
+    let list = notifications(&client, 2);
+    let kinds: Vec<_> = list.notifications.iter().map(|notification| notification.kind).collect();
+    assert_eq!(kinds, vec![NotificationKind::Mentioned]);
+
+    assert_eq!(client.get("/notifications/preferences?user_id=42").dispatch().status(), Status::NotFound);
+}
@@ -0,0 +1,107 @@
+use rocket::http::Status;
+use dooly::helpers::{cleanup_database, establish_test_connection, run_seed_script, setup_rocket};
+use dooly::priority::{Priority, INVALID_PRIORITY};
+use dooly::todos::TodoItem;
+use serde_json::json;
+use rocket::http::ContentType;
+
+#[test]
+fn test_add_todo_with_priority_name_or_number() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    for priority in [json!("urgent"), json!(1)] {
+        let new_todo = json!({
+            "title": "Prioritized Todo",
+            "completed": false,
+            "user_id": 1,
+            "priority": priority
+        });
+
+        let response = client.post("/todos")
+            .header(ContentType::JSON)
+            .body(new_todo.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    let response = client.get("/todos?user_id=1").dispatch();
+    let body = response.into_string().unwrap();
+    let todos: Vec<TodoItem> = serde_json::from_str(&body).unwrap();
+
+    // Todos without a priority default to none
+    assert_eq!(todos[0].priority, Priority::None);
+    assert_eq!(todos[2].priority, Priority::Urgent);
+    assert_eq!(todos[3].priority, Priority::Low);
+
+    // Priorities are returned by name
+    let todos: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+    assert_eq!(todos[2]["priority"], "urgent");
+}
+
+#[test]
+fn test_add_todo_out_of_range_priority() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    for priority in [json!(5), json!(100), json!(-1), json!("critical")] {
+        let new_todo = json!({
+            "title": "Bad Priority",
+            "completed": false,
+            "user_id": 1,
+            "priority": priority
+        });
+
+        let response = client.post("/todos")
+            .header(ContentType::JSON)
+            .body(new_todo.to_string())
+            .dispatch();
+
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(response.into_string().unwrap(), INVALID_PRIORITY);
+    }
+}
+
+#[test]
+fn test_update_todo_priority() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let updated_todo = json!({
+        "title": "Test Todo 1",
+        "completed": false,
+        "user_id": 1,
+        "priority": "high"
+    });
+    let response = client.put("/todos/1?user_id=1")
+        .header(ContentType::JSON)
+        .body(updated_todo.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/todos?user_id=1").dispatch();
+    let todos: Vec<TodoItem> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(todos[0].priority, Priority::High);
+
+    let updated_todo = json!({
+        "title": "Test Todo 1",
+        "completed": false,
+        "user_id": 1,
+        "priority": 7
+    });
+    let response = client.put("/todos/1?user_id=1")
+        .header(ContentType::JSON)
+        .body(updated_todo.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(response.into_string().unwrap(), INVALID_PRIORITY);
+}
@@ -0,0 +1,213 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use dooly::helpers::setup_with_user;
+use dooly::notifications::{NotificationKind, NotificationList};
+use dooly::priority::Priority;
+use dooly::rules::{Rule, RulePreview};
+use dooly::todos::TodoItem;
+use serde_json::json;
+
+fn add_rule(client: &Client, rule: serde_json::Value) -> Rule {
+    let response = client.post("/rules")
+        .header(ContentType::JSON)
+        .body(rule.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().unwrap()
+}
+
+fn add_todo(client: &Client, title: &str) -> TodoItem {
+    client.post("/todos")
+        .header(ContentType::JSON)
+        .body(json!({ "title": title, "completed": false, "user_id": 1 }).to_string())
+        .dispatch();
+    let todos: Vec<TodoItem> = client.get("/todos?user_id=1").dispatch().into_json().unwrap();
+    todos.into_iter().max_by_key(|todo| todo.id).unwrap()
+}
+
+#[test]
+fn test_rule_runs_on_create() {
+    let client = setup_with_user("on-call");
+    client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "username": "on-call", "role": "editor" }).to_string())
+        .dispatch();
+
+    add_rule(&client, json!({
+        "user_id": 1,
+        "name": "Urgent goes to on-call",
+        "trigger": "created",
+        "conditions": [{ "field": "title", "op": "contains", "value": "urgent" }],
+        "actions": [{ "type": "set_priority", "priority": "high" }, { "type": "assign", "user_id": 2 }],
+    }));
+
+    let todo = add_todo(&client, "URGENT: server down");
+    assert_eq!(todo.priority, Priority::High);
+    assert_eq!(todo.assignee_id, Some(2));
+    assert_eq!(todo.version, 2);
+
+    let todo = add_todo(&client, "Water the plants");
+    assert_eq!(todo.priority, Priority::None);
+    assert_eq!(todo.assignee_id, None);
+
+    let list: NotificationList = client.get("/notifications?user_id=2").dispatch().into_json().unwrap();
+    let assigned: Vec<_> = list.notifications.iter().filter(|notification| notification.kind == NotificationKind::Assigned).collect();
+    assert_eq!(assigned.len(), 1);
+    assert_eq!(assigned[0].message, "A rule assigned you \"URGENT: server down\"");
+}
+
+#[test]
+fn test_rules_stop_looping() {
+    let client = setup_with_user("on-call");
+    // Created in this order, each rule only matches after the other has run
+    for (from, to) in [("low", "high"), ("high", "low")] {
+        add_rule(&client, json!({
+            "user_id": 1,
+            "name": format!("{} to {}", from, to),
+            "trigger": "updated",
+            "conditions": [{ "field": "priority", "op": "eq", "value": from }],
+            "actions": [{ "type": "set_priority", "priority": to }],
+        }));
+    }
+
+    let response = client.put("/todos/1?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Test Todo 1", "priority": "high", "completed": false, "user_id": 1 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("ETag"), Some("\"4\""));
+
+    // Each rule ran once: low, then back to high
+    let todo: TodoItem = client.get("/todos/1?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(todo.priority, Priority::High);
+    assert_eq!(todo.version, 4);
+}
+
+#[test]
+fn test_rule_chains_into_completed_trigger() {
+    let client = setup_with_user("on-call");
+    add_rule(&client, json!({
+        "user_id": 1,
+        "name": "Done in title",
+        "trigger": "updated",
+        "conditions": [{ "field": "title", "op": "contains", "value": "[done]" }],
+        "actions": [{ "type": "complete" }],
+    }));
+    add_rule(&client, json!({
+        "user_id": 1,
+        "name": "Finished work is unimportant",
+        "trigger": "completed",
+        "conditions": [{ "field": "priority", "op": "gte", "value": 1 }],
+        "actions": [{ "type": "set_priority", "priority": "none" }],
+    }));
+
+    client.put("/todos/1?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Test Todo 1 [done]", "priority": "medium", "completed": false, "user_id": 1 }).to_string())
+        .dispatch();
+
+    let todo: TodoItem = client.get("/todos/1?user_id=1").dispatch().into_json().unwrap();
+    assert!(todo.completed);
+    assert!(todo.completed_at.is_some());
+    assert_eq!(todo.priority, Priority::None);
+}
+
+#[test]
+fn test_bulk_changes_run_rules() {
+    let client = setup_with_user("on-call");
+    add_rule(&client, json!({
+        "user_id": 1,
+        "name": "Finished work is unimportant",
+        "trigger": "completed",
+        "conditions": [{ "field": "priority", "op": "gte", "value": 1 }],
+        "actions": [{ "type": "set_priority", "priority": "none" }],
+    }));
+
+    let response = client.post("/todos/bulk")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "operations": [
+            { "action": "set_priority", "priority": "high", "ids": [1] },
+            { "action": "complete", "ids": [1] },
+        ] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let todo: TodoItem = client.get("/todos/1?user_id=1").dispatch().into_json().unwrap();
+    assert!(todo.completed);
+    assert_eq!(todo.priority, Priority::None);
+    assert_eq!(todo.version, 4);
+}
+
+#[test]
+fn test_manage_rules() {
+    let client = setup_with_user("on-call");
+    let rule = add_rule(&client, json!({
+        "user_id": 1,
+        "name": "Big tasks are important",
+        "trigger": "created",
+        "conditions": [{ "field": "estimate_minutes", "op": "gt", "value": 120 }],
+        "actions": [{ "type": "set_priority", "priority": "urgent" }],
+    }));
+    assert!(rule.enabled);
+
+    let invalid = [
+        json!({ "user_id": 1, "name": "Deleted", "trigger": "deleted", "actions": [{ "type": "complete" }] }),
+        json!({ "user_id": 1, "name": "No actions", "trigger": "created", "actions": [] }),
+        json!({ "user_id": 1, "name": "Bad op", "trigger": "created", "conditions": [{ "field": "priority", "op": "contains", "value": "high" }], "actions": [{ "type": "complete" }] }),
+        json!({ "user_id": 1, "name": "Bad value", "trigger": "created", "conditions": [{ "field": "priority", "op": "eq", "value": "extreme" }], "actions": [{ "type": "complete" }] }),
+    ];
+    for body in invalid {
+        let response = client.post("/rules").header(ContentType::JSON).body(body.to_string()).dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    let response = client.put(format!("/rules/{}", rule.id))
+        .header(ContentType::JSON)
+        .body(json!({
+            "user_id": 1,
+            "name": "Big tasks are important",
+            "trigger": "created",
+            "conditions": rule.conditions,
+            "actions": rule.actions,
+            "enabled": false,
+        }).to_string())
+        .dispatch();
+    let updated: Rule = response.into_json().unwrap();
+    assert!(!updated.enabled);
+
+    let listed: Vec<Rule> = client.get("/rules?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(client.get(format!("/rules/{}?user_id=2", rule.id)).dispatch().status(), Status::NotFound);
+    assert_eq!(client.delete(format!("/rules/{}?user_id=2", rule.id)).dispatch().status(), Status::NotFound);
+    assert_eq!(client.delete(format!("/rules/{}?user_id=1", rule.id)).dispatch().status(), Status::Ok);
+    let listed: Vec<Rule> = client.get("/rules?user_id=1").dispatch().into_json().unwrap();
+    assert!(listed.is_empty());
+}
+
+#[test]
+fn test_dry_run() {
+    let client = setup_with_user("on-call");
+    let response = client.post("/rules/dry-run")
+        .header(ContentType::JSON)
+        .body(json!({
+            "user_id": 1,
+            "name": "Escalate open work",
+            "trigger": "updated",
+            "conditions": [{ "field": "completed", "op": "eq", "value": false }, { "field": "priority", "op": "lt", "value": "high" }],
+            "actions": [{ "type": "set_priority", "priority": "urgent" }],
+        }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let previews: Vec<RulePreview> = response.into_json().unwrap();
+    assert_eq!(previews.len(), 1);
+    assert_eq!(previews[0].before.id, 1);
+    assert_eq!(previews[0].before.priority, Priority::None);
+    assert_eq!(previews[0].after.priority, Priority::Urgent);
+
+    // Nothing was saved
+    let todo: TodoItem = client.get("/todos/1?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(todo.priority, Priority::None);
+    let listed: Vec<Rule> = client.get("/rules?user_id=1").dispatch().into_json().unwrap();
+    assert!(listed.is_empty());
+}
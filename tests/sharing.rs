@@ -0,0 +1,162 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use dooly::helpers::setup_with_friend;
+use dooly::events::TodoEvent;
+use dooly::sharing::{Share, ShareList, ShareRole};
+use dooly::sync::{ChangeKind, SyncResponse};
+use dooly::todos::TodoItem;
+use serde_json::json;
+use std::io::{BufRead, BufReader};
+
+// Seeded todos belong to user 1; this adds user 2 to share them with
+fn share(client: &Client, todo_id: Option<i32>, role: &str) -> Share {
+    let response = client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "username": "friend", "todo_id": todo_id, "role": role }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().unwrap()
+}
+
+fn titles(client: &Client, uri: &str) -> Vec<String> {
+    let response = client.get(uri.to_string()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json::<Vec<TodoItem>>().unwrap().into_iter().map(|todo| todo.title).collect()
+}
+
+fn update(client: &Client, user_id: i32, owner_id: i32) -> Status {
+    client.put(format!("/todos/1?user_id={}", user_id))
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Renamed", "completed": false, "user_id": owner_id }).to_string())
+        .dispatch()
+        .status()
+}
+
+#[test]
+fn test_share_validation() {
+    let client = setup_with_friend();
+
+    let response = client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "username": "nobody", "role": "viewer" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "username": "test_user", "role": "viewer" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // Only the owner can share a todo
+    let response = client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 2, "username": "test_user", "todo_id": 1, "role": "viewer" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_shared_todo_is_listed_for_grantee() {
+    let client = setup_with_friend();
+    assert!(titles(&client, "/todos?user_id=2").is_empty());
+
+    share(&client, Some(1), "viewer");
+
+    assert_eq!(titles(&client, "/todos?user_id=2"), vec!["Test Todo 1"]);
+    assert_eq!(titles(&client, "/todos/search?user_id=2&query=Test"), vec!["Test Todo 1"]);
+    assert_eq!(client.get("/todos/1?user_id=2").dispatch().status(), Status::Ok);
+    assert_eq!(client.get("/todos/2?user_id=2").dispatch().status(), Status::NotFound);
+
+    let shares: ShareList = client.get("/shares?user_id=2").dispatch().into_json().unwrap();
+    assert!(shares.granted.is_empty());
+    assert_eq!(shares.received.len(), 1);
+    assert_eq!(shares.received[0].role, ShareRole::Viewer);
+}
+
+#[test]
+fn test_viewer_cannot_edit() {
+    let client = setup_with_friend();
+    share(&client, Some(1), "viewer");
+
+    assert_eq!(update(&client, 2, 1), Status::Forbidden);
+    assert_eq!(client.put("/todos/1/complete?user_id=2").dispatch().status(), Status::Forbidden);
+    assert_eq!(client.delete("/todos/1?user_id=2").dispatch().status(), Status::Forbidden);
+
+    // Leaving out the acting user doesn't get around the check
+    assert_eq!(client.put("/todos/1/complete").dispatch().status(), Status::UnprocessableEntity);
+    assert_eq!(client.delete("/todos/1").dispatch().status(), Status::UnprocessableEntity);
+}
+
+#[test]
+fn test_editor_can_edit_but_not_delete_or_reassign() {
+    let client = setup_with_friend();
+    share(&client, None, "viewer");
+    // Sharing the same list again changes the role rather than adding a grant
+    let grant = share(&client, None, "editor");
+    assert_eq!(grant.role, ShareRole::Editor);
+    let shares: ShareList = client.get("/shares?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(shares.granted.len(), 1);
+
+    assert_eq!(titles(&client, "/todos?user_id=2"), vec!["Test Todo 1", "Test Todo 2"]);
+    assert_eq!(update(&client, 2, 1), Status::Ok);
+    assert_eq!(update(&client, 2, 2), Status::Forbidden);
+    assert_eq!(client.put("/todos/1/complete?user_id=2").dispatch().status(), Status::Ok);
+    assert_eq!(client.delete("/todos/1?user_id=2").dispatch().status(), Status::Forbidden);
+    assert_eq!(client.delete("/todos/1?user_id=1").dispatch().status(), Status::Ok);
+}
+
+#[test]
+fn test_owner_revokes_share() {
+    let client = setup_with_friend();
+    let grant = share(&client, None, "editor");
+
+    let response = client.delete(format!("/shares/{}?user_id=2", grant.id)).dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client.delete(format!("/shares/{}?user_id=1", grant.id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    assert!(titles(&client, "/todos?user_id=2").is_empty());
+    assert_eq!(update(&client, 2, 1), Status::NotFound);
+}
+
+#[test]
+fn test_shared_todos_reach_grantee_sync_and_stream() {
+    let client = setup_with_friend();
+    let stream = client.get("/events?user_id=2").dispatch();
+    let sync = |since: i32| -> SyncResponse {
+        client.get(format!("/sync?user_id=2&since={}", since)).dispatch().into_json().unwrap()
+    };
+
+    let grant = share(&client, Some(1), "viewer");
+    let pulled: SyncResponse = client.get("/sync?user_id=2").dispatch().into_json().unwrap();
+    assert_eq!(pulled.created.iter().map(|todo| todo.id).collect::<Vec<_>>(), vec![1]);
+
+    // The owner's edits show up for the grantee
+    assert_eq!(update(&client, 1, 1), Status::Ok);
+    let changed = sync(pulled.token);
+    assert_eq!(changed.updated.iter().map(|todo| todo.title.as_str()).collect::<Vec<_>>(), vec!["Renamed"]);
+
+    // Revoking the share leaves a tombstone for the grantee only
+    let response = client.delete(format!("/shares/{}?user_id=1", grant.id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let revoked = sync(changed.token);
+    assert!(revoked.created.is_empty() && revoked.updated.is_empty());
+    assert_eq!(revoked.deleted.iter().map(|tombstone| tombstone.id).collect::<Vec<_>>(), vec![1]);
+    let owner: SyncResponse = client.get(format!("/sync?user_id=1&since={}", changed.token)).dispatch().into_json().unwrap();
+    assert!(owner.deleted.is_empty());
+
+    let mut reader = BufReader::new(stream);
+    let mut kinds = Vec::new();
+    while kinds.len() < 3 {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if let Some(value) = line.strip_prefix("data:") {
+            let event: TodoEvent = serde_json::from_str(value.trim()).unwrap();
+            assert_eq!(event.todo_id, 1);
+            kinds.push(event.kind);
+        }
+    }
+    assert_eq!(kinds, vec![ChangeKind::Updated, ChangeKind::Updated, ChangeKind::Deleted]);
+}
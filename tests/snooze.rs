@@ -0,0 +1,96 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use dooly::helpers::setup_seeded;
+use dooly::snooze::{preset_until, SnoozePreset};
+use dooly::todos::TodoItem;
+use dooly::user::UserSettings;
+use dooly::views::DateGroup;
+use serde_json::json;
+
+fn listed_ids(client: &Client, uri: &str) -> Vec<i32> {
+    let todos: Vec<TodoItem> = client.get(uri).dispatch().into_json().unwrap();
+    todos.iter().map(|todo| todo.id).collect()
+}
+
+fn utc(value: &str) -> NaiveDateTime {
+    DateTime::parse_from_rfc3339(value).unwrap().naive_utc()
+}
+
+#[test]
+fn test_snoozed_todo_is_hidden() {
+    let client = setup_seeded();
+
+    let response = client.post("/todos/1/snooze")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "preset": "tomorrow" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let todo: TodoItem = response.into_json().unwrap();
+    assert!(todo.defer_until.unwrap() > Utc::now().naive_utc());
+
+    assert_eq!(listed_ids(&client, "/todos?user_id=1"), vec![2]);
+    assert_eq!(listed_ids(&client, "/todos?user_id=1&deferred=true"), vec![1, 2]);
+    let someday: Vec<DateGroup> = client.get("/views/someday?user_id=1").dispatch().into_json().unwrap();
+    assert!(someday.is_empty());
+
+    // Still reachable directly
+    assert_eq!(client.get("/todos/1?user_id=1").dispatch().status(), Status::Ok);
+
+    let response = client.delete("/todos/1/snooze?user_id=1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(listed_ids(&client, "/todos?user_id=1"), vec![1, 2]);
+}
+
+#[test]
+fn test_deferral_expires() {
+    let client = setup_seeded();
+
+    client.put("/todos/1?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Test Todo 1", "completed": false, "user_id": 1, "defer_until": "2020-01-01T00:00:00Z" }).to_string())
+        .dispatch();
+
+    let todo: TodoItem = client.get("/todos/1?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(todo.defer_until, Some(utc("2020-01-01T00:00:00Z")));
+    assert_eq!(listed_ids(&client, "/todos?user_id=1"), vec![1, 2]);
+}
+
+#[test]
+fn test_invalid_snooze() {
+    let client = setup_seeded();
+    let snooze = |body: serde_json::Value| {
+        client.post("/todos/1/snooze")
+            .header(ContentType::JSON)
+            .body(body.to_string())
+            .dispatch()
+            .status()
+    };
+
+    assert_eq!(snooze(json!({ "user_id": 1 })), Status::BadRequest);
+    assert_eq!(snooze(json!({ "user_id": 1, "preset": "tomorrow", "until": "2999-01-01T00:00:00Z" })), Status::BadRequest);
+    assert_eq!(snooze(json!({ "user_id": 1, "until": "2020-01-01T00:00:00Z" })), Status::BadRequest);
+    assert_eq!(snooze(json!({ "user_id": 1, "until": "2999-01-01T00:00:00Z" })), Status::Ok);
+
+    client.post("/users")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "friend", "password_hash": "hashed_password" }).to_string())
+        .dispatch();
+    assert_eq!(snooze(json!({ "user_id": 2, "preset": "tomorrow" })), Status::NotFound);
+}
+
+#[test]
+fn test_presets_use_local_time() {
+    let mut settings = UserSettings::defaults(1);
+    settings.timezone = "America/New_York".to_string();
+
+    // Saturday 15:10 local, the evening before clocks go forward
+    let now = DateTime::parse_from_rfc3339("2024-03-09T20:10:00Z").unwrap().with_timezone(&Utc);
+
+    assert_eq!(preset_until(SnoozePreset::LaterToday, &settings, now), utc("2024-03-10T00:00:00Z"));
+    assert_eq!(preset_until(SnoozePreset::Tomorrow, &settings, now), utc("2024-03-10T13:00:00Z"));
+    assert_eq!(preset_until(SnoozePreset::NextWeek, &settings, now), utc("2024-03-11T13:00:00Z"));
+
+    settings.week_start = "sunday".to_string();
+    assert_eq!(preset_until(SnoozePreset::NextWeek, &settings, now), utc("2024-03-10T13:00:00Z"));
+}
@@ -0,0 +1,176 @@
+use rocket::http::Status;
+use dooly::helpers::{cleanup_database, establish_test_connection, run_seed_script, setup_rocket};
+use dooly::priority::Priority;
+use dooly::stats::StatsReport;
+use chrono::{Duration, Utc};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use serde_json::json;
+use rocket::http::ContentType;
+
+#[test]
+fn test_stats_counts_and_completions() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    let today = Utc::now().date_naive();
+
+    // One overdue item, and one high priority item that gets completed
+    let overdue_todo = json!({
+        "title": "Overdue Todo",
+        "completed": false,
+        "user_id": 1,
+        "due_date": today - Duration::days(1)
+    });
+    let priority_todo = json!({
+        "title": "Priority Todo",
+        "completed": false,
+        "user_id": 1,
+        "priority": 3
+    });
+
+    for new_todo in [overdue_todo, priority_todo] {
+        let response = client.post("/todos")
+            .header(ContentType::JSON)
+            .body(new_todo.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    let response = client.put("/todos/4/complete?user_id=1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/stats?user_id=1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let stats: StatsReport = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+    assert_eq!(stats.to, today);
+    assert_eq!(stats.users.len(), 1);
+    assert_eq!(stats.users[0].username, "test_user");
+    assert_eq!(stats.users[0].open, 2);
+    assert_eq!(stats.users[0].completed, 2);
+    assert_eq!(stats.users[0].overdue, 1);
+
+    // Both the seeded completed todo and the one completed above count for today
+    assert_eq!(stats.completions_per_day.len(), 1);
+    assert_eq!(stats.completions_per_day[0].period_start, today);
+    assert_eq!(stats.completions_per_day[0].completed, 2);
+    assert_eq!(stats.completions_per_week.len(), 1);
+    assert_eq!(stats.completions_per_week[0].completed, 2);
+    assert!(stats.average_seconds_to_complete.is_some());
+
+    assert_eq!(stats.by_priority.len(), 2);
+    assert_eq!(stats.by_priority[0].priority, Priority::None);
+    assert_eq!(stats.by_priority[0].open, 2);
+    assert_eq!(stats.by_priority[0].completed, 1);
+    assert_eq!(stats.by_priority[1].priority, Priority::High);
+    assert_eq!(stats.by_priority[1].completed, 1);
+}
+
+#[test]
+fn test_stats_excludes_completions_outside_range() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = client.get("/stats?user_id=1&from=2020-01-01&to=2020-01-31").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let stats: StatsReport = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+    assert!(stats.completions_per_day.is_empty());
+    assert!(stats.completions_per_week.is_empty());
+    assert_eq!(stats.average_seconds_to_complete, None);
+
+    // Current counts are not limited by the reporting range
+    assert_eq!(stats.users[0].completed, 1);
+}
+
+#[test]
+fn test_stats_invalid_range() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = client.get("/stats?from=2024-02-01&to=2024-01-01").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.into_string().unwrap(), "From date must not be after to date");
+
+    let response = client.get("/stats?from=yesterday").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.into_string().unwrap(), "Dates must be formatted as YYYY-MM-DD");
+}
+
+#[test]
+fn test_stats_buckets_by_local_date_across_dst() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    client.put("/users/1/settings")
+        .header(ContentType::JSON)
+        .body(json!({ "timezone": "America/New_York", "week_start": "monday", "date_format": "%Y-%m-%d" }).to_string())
+        .dispatch();
+
+    // 04:30 UTC is late the evening before in winter (UTC-5) but just after midnight in summer (UTC-4)
+    let mut connection = pool.get().unwrap();
+    diesel::sql_query("UPDATE todos SET completed = 1, completed_at = '2024-01-15 04:30:00' WHERE id = 1")
+        .execute(&mut connection)
+        .unwrap();
+    diesel::sql_query("UPDATE todos SET completed = 1, completed_at = '2024-07-01 04:30:00' WHERE id = 2")
+        .execute(&mut connection)
+        .unwrap();
+
+    let response = client.get("/stats?user_id=1&from=2024-01-01&to=2024-07-31").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let stats: StatsReport = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+    let days: Vec<String> = stats.completions_per_day.iter().map(|period| period.period_start.to_string()).collect();
+    assert_eq!(days, vec!["2024-01-14", "2024-07-01"]);
+    let weeks: Vec<String> = stats.completions_per_week.iter().map(|period| period.period_start.to_string()).collect();
+    assert_eq!(weeks, vec!["2024-01-08", "2024-07-01"]);
+}
+
+#[test]
+fn test_team_stats_overdue_per_user_calendar() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    client.post("/users")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "islander", "password_hash": "hashed_password" }).to_string())
+        .dispatch();
+    client.put("/users/2/settings")
+        .header(ContentType::JSON)
+        .body(json!({ "timezone": "Pacific/Kiritimati", "week_start": "monday", "date_format": "%Y-%m-%d" }).to_string())
+        .dispatch();
+
+    // Due today in UTC, which is already past for a user fourteen hours ahead once UTC reaches 10:00
+    let utc_today = Utc::now().date_naive();
+    for user_id in [1, 2] {
+        let response = client.post("/todos")
+            .header(ContentType::JSON)
+            .body(json!({ "title": "Due today", "completed": false, "user_id": user_id, "due_date": utc_today }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+    let islander_today = Utc::now().with_timezone(&Tz::Pacific__Kiritimati).date_naive();
+
+    let response = client.get("/stats").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let stats: StatsReport = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+    let overdue: Vec<(String, i64)> = stats.users.iter().map(|user| (user.username.clone(), user.overdue)).collect();
+    assert_eq!(overdue, vec![
+        ("test_user".to_string(), 0),
+        ("islander".to_string(), i64::from(utc_today < islander_today)),
+    ]);
+}
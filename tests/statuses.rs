@@ -0,0 +1,158 @@
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use dooly::helpers::{cleanup_database, establish_test_connection, run_seed_script, setup_rocket};
+use dooly::statuses::{BoardColumn, StatusCategory, TodoStatus};
+use dooly::todos::TodoItem;
+use serde_json::json;
+use rocket::http::ContentType;
+
+fn get_board(client: &Client) -> Vec<BoardColumn> {
+    let response = client.get("/board?user_id=1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    serde_json::from_str(&response.into_string().unwrap()).unwrap()
+}
+
+fn add_review_status(client: &Client) -> TodoStatus {
+    let new_status = json!({
+        "user_id": 1,
+        "name": "In Review",
+        "category": "in_progress",
+        "position": 2
+    });
+
+    let response = client.post("/statuses")
+        .header(ContentType::JSON)
+        .body(new_status.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    serde_json::from_str(&response.into_string().unwrap()).unwrap()
+}
+
+#[test]
+fn test_default_statuses() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = client.get("/statuses?user_id=1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let statuses: Vec<TodoStatus> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+    let names: Vec<&str> = statuses.iter().map(|status| status.name.as_str()).collect();
+    assert_eq!(names, ["To Do", "In Progress", "Done"]);
+    assert_eq!(statuses[2].category, StatusCategory::Done);
+
+    // Reading never creates statuses; users get them when they sign up
+    let statuses: Vec<TodoStatus> = client.get("/statuses?user_id=42").dispatch().into_json().unwrap();
+    assert!(statuses.is_empty());
+
+    // Seeded todos are placed by their completed flag
+    let board = get_board(&client);
+    assert_eq!(board[0].todos[0].title, "Test Todo 1");
+    assert!(board[1].todos.is_empty());
+    assert_eq!(board[2].todos[0].title, "Test Todo 2");
+}
+
+#[test]
+fn test_add_status_in_position() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let review = add_review_status(&client);
+    assert_eq!(review.position, 2);
+
+    let board = get_board(&client);
+    let names: Vec<&str> = board.iter().map(|column| column.status.name.as_str()).collect();
+    assert_eq!(names, ["To Do", "In Progress", "In Review", "Done"]);
+
+    let new_status = json!({ "user_id": 1, "name": " ", "category": "todo" });
+    let response = client.post("/statuses")
+        .header(ContentType::JSON)
+        .body(new_status.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.into_string().unwrap(), "Status name cannot be empty");
+}
+
+#[test]
+fn test_transition_drives_completed() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    let review = add_review_status(&client);
+
+    let response = client.put("/todos/1/status?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "status_id": review.id }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "Todo status updated successfully!");
+
+    let board = get_board(&client);
+    assert!(board[0].todos.is_empty());
+    assert_eq!(board[2].todos[0].title, "Test Todo 1");
+    assert!(!board[2].todos[0].completed);
+
+    // Moving into a done column completes the todo
+    let done = &board[3].status;
+    let response = client.put("/todos/1/status?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "status_id": done.id }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/todos?user_id=1").dispatch();
+    let todos: Vec<TodoItem> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert!(todos[0].completed);
+    assert!(todos[0].completed_at.is_some());
+    assert_eq!(todos[0].status_id, Some(done.id));
+
+    // A column still holding todos cannot be deleted
+    let response = client.delete(format!("/statuses/{}?user_id=1", done.id)).dispatch();
+    assert_eq!(response.status(), Status::Conflict);
+
+    // Only the owner can delete a column
+    let response = client.delete(format!("/statuses/{}?user_id=2", review.id)).dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    let response = client.delete(format!("/statuses/{}?user_id=1", review.id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn test_transition_to_other_users_status() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let new_user = json!({ "username": "other_user", "password_hash": "hashed_password" });
+    let response = client.post("/users")
+        .header(ContentType::JSON)
+        .body(new_user.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/statuses?user_id=2").dispatch();
+    let statuses: Vec<TodoStatus> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+
+    let response = client.put("/todos/1/status?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "status_id": statuses[0].id }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.into_string().unwrap(), "Status does not belong to the todo's owner");
+
+    let response = client.put("/todos/999/status?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "status_id": statuses[0].id }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
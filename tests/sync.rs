@@ -0,0 +1,238 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use dooly::helpers::{cleanup_database, establish_test_connection, run_seed_script, setup_rocket};
+use dooly::sync::{SyncOutcome, SyncPushResponse, SyncResponse};
+use serde_json::{json, Value};
+
+fn pull(client: &Client, since: Option<i32>) -> SyncResponse {
+    let uri = match since {
+        Some(since) => format!("/sync?user_id=1&since={}", since),
+        None => "/sync?user_id=1".to_string(),
+    };
+    let response = client.get(uri).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    serde_json::from_str(&response.into_string().unwrap()).unwrap()
+}
+
+fn push(client: &Client, changes: Value) -> SyncPushResponse {
+    let response = client.post("/sync")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "changes": changes }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    serde_json::from_str(&response.into_string().unwrap()).unwrap()
+}
+
+#[test]
+fn test_initial_sync_returns_everything() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let sync = pull(&client, None);
+    assert_eq!(sync.token, 0);
+    assert_eq!(sync.created.len(), 2);
+    assert!(sync.updated.is_empty());
+    assert!(sync.deleted.is_empty());
+}
+
+#[test]
+fn test_delta_sync_since_token() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = client.post("/todos")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Before", "completed": false, "user_id": 1 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let token = pull(&client, None).token;
+    assert_eq!(token, 1);
+
+    // Nothing has happened since the last pull
+    let sync = pull(&client, Some(token));
+    assert!(sync.created.is_empty() && sync.updated.is_empty() && sync.deleted.is_empty());
+
+    client.post("/todos")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "After", "completed": false, "user_id": 1 }).to_string())
+        .dispatch();
+    client.put("/todos/1/complete?user_id=1").dispatch();
+    client.delete("/todos/2?user_id=1").dispatch();
+
+    let sync = pull(&client, Some(token));
+    assert_eq!(sync.created.len(), 1);
+    assert_eq!(sync.created[0].title, "After");
+    assert_eq!(sync.updated.len(), 1);
+    assert_eq!(sync.updated[0].id, 1);
+    assert!(sync.updated[0].completed);
+    assert_eq!(sync.deleted.len(), 1);
+    assert_eq!(sync.deleted[0].id, 2);
+    assert!(sync.token > token);
+
+    // A todo created and deleted between pulls only shows up as a tombstone
+    let token = sync.token;
+    client.post("/todos")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Short-lived", "completed": false, "user_id": 1 }).to_string())
+        .dispatch();
+    client.delete("/todos/5?user_id=1").dispatch();
+
+    let sync = pull(&client, Some(token));
+    assert!(sync.created.is_empty());
+    assert_eq!(sync.deleted.len(), 1);
+    assert_eq!(sync.deleted[0].id, 5);
+}
+
+#[test]
+fn test_unknown_sync_token() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = client.get("/sync?user_id=1&since=42").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.into_string().unwrap(), "Unknown sync token");
+}
+
+#[test]
+fn test_push_client_changes() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = push(&client, json!([
+        { "op": "create", "client_id": "local-1", "todo": { "title": "Made offline", "priority": "high" } },
+        { "op": "update", "id": 1, "version": 1, "todo": { "title": "Renamed offline", "completed": true } },
+        { "op": "delete", "id": 2, "version": 1 },
+        { "op": "create", "todo": { "title": "" } }
+    ]));
+
+    let results = response.results;
+    assert_eq!(results[0].outcome, SyncOutcome::Applied);
+    assert_eq!(results[0].client_id.as_deref(), Some("local-1"));
+    assert_eq!(results[0].id, Some(3));
+    assert_eq!(results[0].version, Some(1));
+    assert_eq!(results[1].outcome, SyncOutcome::Applied);
+    assert_eq!(results[1].version, Some(2));
+    assert_eq!(results[2].outcome, SyncOutcome::Applied);
+    assert_eq!(results[3].outcome, SyncOutcome::Rejected);
+    assert_eq!(results[3].error.as_deref(), Some("Title cannot be empty"));
+
+    let sync = pull(&client, None);
+    let titles: Vec<&str> = sync.created.iter().map(|todo| todo.title.as_str()).collect();
+    assert_eq!(titles, vec!["Renamed offline", "Made offline"]);
+}
+
+#[test]
+fn test_push_reports_conflicts() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    // Another client edits the todo first
+    let response = client.put("/todos/1?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Edited online", "completed": false, "user_id": 1 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = push(&client, json!([
+        { "op": "update", "id": 1, "version": 1, "todo": { "title": "Edited offline" } },
+        { "op": "delete", "id": 1, "version": 1 },
+        { "op": "update", "id": 999, "version": 1, "todo": { "title": "Gone" } }
+    ]));
+
+    let results = response.results;
+    assert_eq!(results[0].outcome, SyncOutcome::Conflict);
+    assert_eq!(results[0].version, Some(2));
+    assert_eq!(results[0].current.as_ref().unwrap().title, "Edited online");
+    assert_eq!(results[1].outcome, SyncOutcome::Conflict);
+    assert_eq!(results[2].outcome, SyncOutcome::NotFound);
+
+    let sync = pull(&client, None);
+    assert_eq!(sync.created[0].title, "Edited online");
+}
+
+#[test]
+fn test_handing_a_todo_over_moves_it_between_syncs() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    client.post("/users")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "friend", "password_hash": "hashed_password" }).to_string())
+        .dispatch();
+    client.post("/todos")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Before", "completed": false, "user_id": 1 }).to_string())
+        .dispatch();
+    let token = pull(&client, None).token;
+
+    let response = client.put("/todos/1?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Test Todo 1", "completed": false, "user_id": 2 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let sync = pull(&client, Some(token));
+    assert!(sync.created.is_empty() && sync.updated.is_empty());
+    assert_eq!(sync.deleted.len(), 1);
+    assert_eq!(sync.deleted[0].id, 1);
+
+    let response = client.get("/sync?user_id=2").dispatch();
+    let sync: SyncResponse = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    let ids: Vec<i32> = sync.created.iter().map(|todo| todo.id).collect();
+    assert_eq!(ids, vec![1]);
+    assert_eq!(sync.created[0].user_id, 2);
+}
+
+#[test]
+fn test_editors_push_to_shared_todos() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    client.post("/users")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "friend", "password_hash": "hashed_password" }).to_string())
+        .dispatch();
+    let response = client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "username": "friend", "role": "editor" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.post("/sync")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 2, "changes": [
+            { "op": "update", "id": 1, "version": 1, "todo": { "title": "Edited by a friend", "completed": false } },
+            { "op": "delete", "id": 2, "version": 1 },
+        ] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let pushed: SyncPushResponse = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(pushed.results[0].outcome, SyncOutcome::Applied);
+    // Only the owner may delete
+    assert_eq!(pushed.results[1].outcome, SyncOutcome::Rejected);
+
+    let sync = pull(&client, None);
+    let edited = sync.created.iter().find(|todo| todo.id == 1).unwrap();
+    assert_eq!(edited.title, "Edited by a friend");
+    assert_eq!(edited.user_id, 1);
+    assert_eq!(sync.created.len(), 2);
+}
@@ -0,0 +1,137 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use dooly::helpers::setup_seeded;
+use dooly::templates::{substitute, variables, TemplateDetails};
+use dooly::todos::TodoItem;
+use chrono::NaiveDate;
+use serde_json::json;
+use std::collections::HashMap;
+
+fn onboarding(client: &Client) -> TemplateDetails {
+    let response = client.post("/templates")
+        .header(ContentType::JSON)
+        .body(json!({
+            "user_id": 1,
+            "name": "Onboarding",
+            "items": [
+                { "title": "Welcome {{name}}", "priority": "high", "due_offset_days": 0 },
+                { "title": "Set up laptop for {{ name }}", "description": "Ask {{buddy}} for help", "due_offset_days": 2, "estimate_minutes": 60 },
+                { "title": "Read the handbook" },
+            ],
+        }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().unwrap()
+}
+
+#[test]
+fn test_substitution() {
+    let values = HashMap::from([("name".to_string(), "Ada".to_string())]);
+    assert_eq!(substitute("Hi {{name}}, {{ name }}!", &values), Ok("Hi Ada, Ada!".to_string()));
+    assert_eq!(substitute("Keep {{not a var}} and {{", &values), Ok("Keep {{not a var}} and {{".to_string()));
+    assert_eq!(substitute("Hi {{who}}", &values), Err("who".to_string()));
+    assert_eq!(variables("{{a}} {{b}} {{a}}"), vec!["a", "b"]);
+}
+
+#[test]
+fn test_create_and_list_templates() {
+    let client = setup_seeded();
+    let template = onboarding(&client);
+    assert_eq!(template.template.name, "Onboarding");
+    assert_eq!(template.items.len(), 3);
+    assert_eq!(template.variables, vec!["name", "buddy"]);
+
+    let response = client.post("/templates")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "name": "Empty", "items": [] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client.post("/templates")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "name": "Far off", "items": [{ "title": "Later", "due_offset_days": 2_000_000_000 }] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.into_string().unwrap(), "Due offset must be within ten years");
+
+    let listed: Vec<TemplateDetails> = client.get("/templates?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(client.get(format!("/templates/{}?user_id=2", template.template.id)).dispatch().status(), Status::NotFound);
+
+    let response = client.delete(format!("/templates/{}?user_id=1", template.template.id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let listed: Vec<TemplateDetails> = client.get("/templates?user_id=1").dispatch().into_json().unwrap();
+    assert!(listed.is_empty());
+}
+
+#[test]
+fn test_instantiate_template() {
+    let client = setup_seeded();
+    let template = onboarding(&client);
+    let uri = format!("/templates/{}/instantiate", template.template.id);
+
+    // Nothing is created when a variable has no value
+    let response = client.post(uri.clone())
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "anchor": "2024-05-06", "variables": { "name": "Ada" } }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(client.get("/todos?user_id=1").dispatch().into_json::<Vec<TodoItem>>().unwrap().len(), 2);
+
+    let response = client.post(uri)
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "anchor": "2024-05-06", "variables": { "name": "Ada", "buddy": "Grace" } }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let created: Vec<TodoItem> = response.into_json().unwrap();
+
+    let titles: Vec<_> = created.iter().map(|todo| todo.title.as_str()).collect();
+    assert_eq!(titles, vec!["Welcome Ada", "Set up laptop for Ada", "Read the handbook"]);
+    assert_eq!(created[1].description.as_deref(), Some("Ask Grace for help"));
+    assert_eq!(created[0].due_date, NaiveDate::from_ymd_opt(2024, 5, 6));
+    assert_eq!(created[1].due_date, NaiveDate::from_ymd_opt(2024, 5, 8));
+    assert_eq!(created[2].due_date, None);
+    assert_eq!(created[1].estimate_minutes, Some(60));
+    assert!(created.iter().all(|todo| !todo.completed && todo.user_id == 1));
+
+    // An anchor at the end of the calendar leaves no room for the offsets
+    let response = client.post(format!("/templates/{}/instantiate", template.template.id))
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "anchor": NaiveDate::MAX, "variables": { "name": "Ada", "buddy": "Grace" } }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.into_string().unwrap(), "Due date is out of range");
+}
+
+#[test]
+fn test_save_todo_as_template() {
+    let client = setup_seeded();
+
+    let response = client.post("/todos/1/template")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "name": "Weekly review" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let template: TemplateDetails = response.into_json().unwrap();
+    assert_eq!(template.items.len(), 1);
+    assert_eq!(template.items[0].title, "Test Todo 1");
+
+    // Saving again under the same key replays the template instead of making another
+    for _ in 0..2 {
+        let response = client.post("/todos/2/template")
+            .header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "save-template"))
+            .body(json!({ "user_id": 1, "name": "Done list" }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+    let templates: Vec<TemplateDetails> = client.get("/templates?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(templates.iter().filter(|template| template.template.name == "Done list").count(), 1);
+
+    // Only todos the user can see can be saved
+    let response = client.post("/todos/1/template")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 2, "name": "Stolen" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
@@ -0,0 +1,133 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use dooly::helpers::setup_seeded;
+use dooly::time_tracking::{TimeEntry, Timesheet};
+use dooly::todos::TodoItem;
+use serde_json::json;
+
+fn log_time(client: &Client, todo_id: i32, started_at: &str, ended_at: &str) -> Status {
+    client.post(format!("/todos/{}/time", todo_id))
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "started_at": started_at, "ended_at": ended_at, "note": "Work" }).to_string())
+        .dispatch()
+        .status()
+}
+
+#[test]
+fn test_estimates() {
+    let client = setup_seeded();
+
+    let response = client.post("/todos")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Estimated", "completed": false, "user_id": 1, "estimate_minutes": 90 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let todo: TodoItem = client.get("/todos/3?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(todo.estimate_minutes, Some(90));
+    assert_eq!(todo.tracked_minutes, 0);
+
+    let response = client.put("/todos/3?user_id=1")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Estimated", "completed": false, "user_id": 1, "estimate_minutes": -5 }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_one_running_timer_per_user() {
+    let client = setup_seeded();
+
+    let response = client.post("/todos/1/timer?user_id=1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let started: TimeEntry = response.into_json().unwrap();
+    assert_eq!(started.ended_at, None);
+
+    assert_eq!(client.post("/todos/2/timer?user_id=1").dispatch().status(), Status::Conflict);
+
+    let running: Option<TimeEntry> = client.get("/timer?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(running.unwrap().id, started.id);
+
+    let response = client.delete("/timer?user_id=1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let stopped: TimeEntry = response.into_json().unwrap();
+    assert!(stopped.ended_at.unwrap() >= stopped.started_at);
+
+    assert_eq!(client.delete("/timer?user_id=1").dispatch().status(), Status::NotFound);
+    let running: Option<TimeEntry> = client.get("/timer?user_id=1").dispatch().into_json().unwrap();
+    assert!(running.is_none());
+
+    // A retried start gets the same timer back rather than a conflict
+    let start = || client.post("/todos/2/timer?user_id=1").header(Header::new("Idempotency-Key", "start-timer")).dispatch();
+    let first: TimeEntry = start().into_json().unwrap();
+    let response = start();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_json::<TimeEntry>().unwrap().id, first.id);
+}
+
+#[test]
+fn test_manual_entries_add_up() {
+    let client = setup_seeded();
+
+    assert_eq!(log_time(&client, 1, "2024-03-01T09:00:00Z", "2024-03-01T10:00:00Z"), Status::Ok);
+    assert_eq!(log_time(&client, 1, "2024-03-02T09:00:00+01:00", "2024-03-02T09:30:00+01:00"), Status::Ok);
+    assert_eq!(log_time(&client, 1, "2024-03-02T09:00:00Z", "2024-03-02T08:00:00Z"), Status::BadRequest);
+
+    // Retrying with the same key doesn't log the time twice
+    for _ in 0..2 {
+        let response = client.post("/todos/2/time")
+            .header(ContentType::JSON)
+            .header(Header::new("Idempotency-Key", "log-time"))
+            .body(json!({ "user_id": 1, "started_at": "2024-03-03T09:00:00Z", "ended_at": "2024-03-03T09:15:00Z" }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+    let entries: Vec<TimeEntry> = client.get("/todos/2/time?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(entries.len(), 1);
+
+    let todo: TodoItem = client.get("/todos/1?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(todo.tracked_minutes, 90);
+
+    let entries: Vec<TimeEntry> = client.get("/todos/1/time?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(client.delete(format!("/time/{}?user_id=2", entries[0].id)).dispatch().status(), Status::NotFound);
+    assert_eq!(client.delete(format!("/time/{}?user_id=1", entries[0].id)).dispatch().status(), Status::Ok);
+
+    let todo: TodoItem = client.get("/todos/1?user_id=1").dispatch().into_json().unwrap();
+    assert_eq!(todo.tracked_minutes, 30);
+}
+
+#[test]
+fn test_timesheet_by_local_day() {
+    let client = setup_seeded();
+    client.put("/users/1/settings")
+        .header(ContentType::JSON)
+        .body(json!({ "timezone": "America/New_York", "week_start": "monday", "date_format": "%Y-%m-%d" }).to_string())
+        .dispatch();
+    client.post("/todos")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Invoice, March", "completed": false, "user_id": 1 }).to_string())
+        .dispatch();
+
+    // Late on the 9th in New York, though already the 10th in UTC
+    log_time(&client, 1, "2024-03-10T03:30:00Z", "2024-03-10T04:30:00Z");
+    log_time(&client, 3, "2024-03-10T15:00:00Z", "2024-03-10T15:30:00Z");
+    log_time(&client, 3, "2024-03-12T15:00:00Z", "2024-03-12T15:30:00Z");
+
+    let response = client.get("/timesheet?user_id=1&from=2024-03-09&to=2024-03-10").dispatch();
+    let timesheet: Timesheet = response.into_json().unwrap();
+    assert_eq!(timesheet.total_minutes, 90);
+    let days: Vec<_> = timesheet.days.iter().map(|day| (day.date.to_string(), day.minutes)).collect();
+    assert_eq!(days, vec![("2024-03-09".to_string(), 60), ("2024-03-10".to_string(), 30)]);
+    assert_eq!(timesheet.lists.len(), 1);
+    assert_eq!(timesheet.lists[0].owner, "test_user");
+    assert_eq!(timesheet.rows.len(), 2);
+
+    let response = client.get("/timesheet?user_id=1&from=2024-03-09&to=2024-03-10&format=csv").dispatch();
+    assert_eq!(response.content_type(), Some(ContentType::CSV));
+    assert_eq!(response.into_string().unwrap(), "date,list_id,todo_id,title,minutes\n\
+        2024-03-09,1,1,Test Todo 1,60\n\
+        2024-03-10,1,3,\"Invoice, March\",30\n");
+
+    assert_eq!(client.get("/timesheet?user_id=1&format=xml").dispatch().status(), Status::BadRequest);
+    assert_eq!(client.get("/timesheet?user_id=1&from=2024-03-10&to=2024-03-09").dispatch().status(), Status::BadRequest);
+}
@@ -33,7 +33,7 @@ fn test_valid_update_todo() {
         "user_id": 1  // Ensure the user_id stays the same
     });
 
-    let response = client.put("/todos/1")
+    let response = client.put("/todos/1?user_id=1")
         .header(ContentType::JSON)
         .body(updated_todo.to_string())
         .dispatch();
@@ -42,7 +42,7 @@ fn test_valid_update_todo() {
     assert_eq!(response.into_string().unwrap(), "Todo updated successfully!");
 
     // Fetch the updated todo to verify the changes
-    let response = client.get("/todos")
+    let response = client.get("/todos?user_id=1")
         .dispatch();
 
     assert_eq!(response.status(), Status::Ok);
@@ -81,7 +81,7 @@ fn test_invalid_update_todo_empty_title() {
         "user_id": 1  // Ensure the user_id stays the same
     });
 
-    let response = client.put("/todos/1")
+    let response = client.put("/todos/1?user_id=1")
         .header(ContentType::JSON)
         .body(updated_todo.to_string())
         .dispatch();
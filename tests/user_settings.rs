@@ -0,0 +1,143 @@
+use rocket::http::Status;
+use dooly::helpers::{cleanup_database, establish_test_connection, run_seed_script, setup_rocket};
+use dooly::priority::Priority;
+use dooly::todos::TodoItem;
+use dooly::user::UserSettings;
+use dooly::views::DateGroup;
+use chrono::Utc;
+use serde_json::json;
+use rocket::http::ContentType;
+
+#[test]
+fn test_get_default_settings() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let response = client.get("/users/1/settings").dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let settings: UserSettings = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(settings.user_id, 1);
+    assert_eq!(settings.timezone, "UTC");
+    assert_eq!(settings.week_start, "monday");
+    assert_eq!(settings.date_format, "%Y-%m-%d");
+    assert_eq!(settings.default_priority, None);
+}
+
+#[test]
+fn test_update_settings() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let updated_settings = json!({
+        "timezone": "America/Chicago",
+        "week_start": "Sun",
+        "date_format": "%d/%m/%Y",
+        "default_priority": 2
+    });
+
+    let response = client.put("/users/1/settings")
+        .header(ContentType::JSON)
+        .body(updated_settings.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // Saving again replaces the existing record
+    let response = client.put("/users/1/settings")
+        .header(ContentType::JSON)
+        .body(updated_settings.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/users/1/settings").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let settings: UserSettings = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(settings.timezone, "America/Chicago");
+    assert_eq!(settings.week_start, "sunday");
+    assert_eq!(settings.date_format, "%d/%m/%Y");
+    assert_eq!(settings.default_priority, Some(Priority::Medium));
+}
+
+#[test]
+fn test_update_settings_invalid() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let cases = [
+        (json!({ "timezone": "Mars/Olympus", "week_start": "monday", "date_format": "%Y-%m-%d" }), "Unknown timezone"),
+        (json!({ "timezone": "UTC", "week_start": "someday", "date_format": "%Y-%m-%d" }), "Week start must be a day of the week"),
+        (json!({ "timezone": "UTC", "week_start": "monday", "date_format": "%Q" }), "Invalid date format"),
+        (json!({ "timezone": "UTC", "week_start": "monday", "date_format": "%H:%M" }), "Invalid date format"),
+    ];
+
+    for (body, message) in cases {
+        let response = client.put("/users/1/settings")
+            .header(ContentType::JSON)
+            .body(body.to_string())
+            .dispatch();
+
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(response.into_string().unwrap(), message);
+    }
+
+    let response = client.put("/users/999/settings")
+        .header(ContentType::JSON)
+        .body(json!({ "timezone": "UTC", "week_start": "monday", "date_format": "%Y-%m-%d" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_settings_apply_to_new_todos_and_views() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+
+    let updated_settings = json!({
+        "timezone": "Pacific/Kiritimati",
+        "week_start": "monday",
+        "date_format": "%d.%m.%Y",
+        "default_priority": 3
+    });
+    let response = client.put("/users/1/settings")
+        .header(ContentType::JSON)
+        .body(updated_settings.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // "Today" is the current date fourteen hours ahead of UTC
+    let local_today = Utc::now().with_timezone(&chrono_tz::Pacific::Kiritimati).date_naive();
+    let new_todo = json!({
+        "title": "Due Today Locally",
+        "completed": false,
+        "user_id": 1,
+        "due_date": local_today
+    });
+    let response = client.post("/todos")
+        .header(ContentType::JSON)
+        .body(new_todo.to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/todos?user_id=1").dispatch();
+    let todos: Vec<TodoItem> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(todos[2].priority, Priority::High);
+
+    let response = client.get("/views/today?user_id=1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let groups: Vec<DateGroup> = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].todos[0].title, "Due Today Locally");
+    assert_eq!(groups[0].label, Some(local_today.format("%d.%m.%Y").to_string()));
+}
@@ -0,0 +1,150 @@
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use dooly::helpers::{cleanup_database, establish_test_connection, run_seed_script, setup_rocket, setup_with_friend};
+use dooly::views::DateGroup;
+use chrono::{Duration, NaiveDate, Utc};
+use serde_json::json;
+use rocket::http::ContentType;
+
+fn add_todo_due(client: &Client, title: &str, due_date: Option<NaiveDate>) {
+    let new_todo = json!({
+        "title": title,
+        "completed": false,
+        "user_id": 1,
+        "due_date": due_date
+    });
+
+    let response = client.post("/todos")
+        .header(ContentType::JSON)
+        .body(new_todo.to_string())
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+}
+
+fn seed_dated_todos(client: &Client) -> NaiveDate {
+    let today = Utc::now().date_naive();
+
+    add_todo_due(client, "Overdue Two Days", Some(today - Duration::days(2)));
+    add_todo_due(client, "Overdue Yesterday", Some(today - Duration::days(1)));
+    add_todo_due(client, "Due Today", Some(today));
+    add_todo_due(client, "Due In Three Days", Some(today + Duration::days(3)));
+    add_todo_due(client, "Due In Ten Days", Some(today + Duration::days(10)));
+
+    today
+}
+
+fn get_view(client: &Client, uri: &str) -> Vec<DateGroup> {
+    let response = client.get(uri.to_string()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    serde_json::from_str(&response.into_string().unwrap()).unwrap()
+}
+
+#[test]
+fn test_today_view() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    let today = seed_dated_todos(&client);
+
+    let groups = get_view(&client, "/views/today?user_id=1");
+
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].date, Some(today));
+    assert_eq!(groups[0].todos.len(), 1);
+    assert_eq!(groups[0].todos[0].title, "Due Today");
+}
+
+#[test]
+fn test_overdue_view_grouped_by_date() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    let today = seed_dated_todos(&client);
+
+    let groups = get_view(&client, "/views/overdue?user_id=1");
+
+    // Oldest date comes first, one group per date
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].date, Some(today - Duration::days(2)));
+    assert_eq!(groups[0].todos[0].title, "Overdue Two Days");
+    assert_eq!(groups[1].date, Some(today - Duration::days(1)));
+    assert_eq!(groups[1].todos[0].title, "Overdue Yesterday");
+}
+
+#[test]
+fn test_upcoming_view_respects_days() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    seed_dated_todos(&client);
+
+    // Default window is one week, which excludes the todo due in ten days
+    let groups = get_view(&client, "/views/upcoming?user_id=1");
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].todos[0].title, "Due In Three Days");
+
+    let groups = get_view(&client, "/views/upcoming?user_id=1&days=14");
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[1].todos[0].title, "Due In Ten Days");
+
+    let response = client.get("/views/upcoming?user_id=1&days=0").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.into_string().unwrap(), "Days must be between 1 and 365");
+    let response = client.get(format!("/views/upcoming?user_id=1&days={}", i64::MAX)).dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_someday_view_excludes_completed() {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let client = setup_rocket();
+    seed_dated_todos(&client);
+
+    // Seeded "Test Todo 1" has no due date; seeded "Test Todo 2" is completed
+    let groups = get_view(&client, "/views/someday?user_id=1");
+
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].date, None);
+    assert_eq!(groups[0].todos.len(), 1);
+    assert_eq!(groups[0].todos[0].title, "Test Todo 1");
+}
+
+#[test]
+fn test_views_follow_the_viewers_timezone() {
+    let client = setup_with_friend();
+    client.post("/shares")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "username": "friend", "role": "viewer" }).to_string())
+        .dispatch();
+    let response = client.put("/users/2/settings")
+        .header(ContentType::JSON)
+        .body(json!({ "timezone": "Pacific/Kiritimati", "week_start": "monday", "date_format": "%Y-%m-%d" }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // Midday in UTC is already the next morning at UTC+14
+    let due_day = Utc::now().date_naive() + Duration::days(3);
+    let response = client.post("/todos")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Call at noon UTC", "completed": false, "user_id": 1, "due_at": format!("{}T12:00:00Z", due_day) }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let groups = get_view(&client, "/views/upcoming?user_id=1");
+    assert_eq!(groups[0].date, Some(due_day));
+
+    let groups = get_view(&client, "/views/upcoming?user_id=2");
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].date, Some(due_day + Duration::days(1)));
+    assert_eq!(groups[0].todos[0].title, "Call at noon UTC");
+}
@@ -0,0 +1,255 @@
+use rocket::config::{Config, LogLevel};
+use rocket::http::{ContentType, Status};
+use rocket::local::blocking::Client;
+use rocket::request::{self, FromRequest, Request};
+use rocket::{post, routes, State};
+use dooly::helpers::{build_rocket, cleanup_database, establish_test_connection, run_seed_script};
+use dooly::webhooks::{sign, CreatedWebhook, DeliveryStatus, Webhook, WebhookDelivery, WebhookPayload, EVENT_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use serde_json::json;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A request the stub receiver was sent
+#[derive(Debug, Clone)]
+struct Received {
+    event: String,
+    timestamp: i64,
+    signature: String,
+    body: String,
+}
+
+type Inbox = Arc<Mutex<Vec<Received>>>;
+
+struct Headers {
+    event: String,
+    timestamp: i64,
+    signature: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Headers {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let headers = request.headers();
+        request::Outcome::Success(Headers {
+            event: headers.get_one(EVENT_HEADER).unwrap_or_default().to_string(),
+            timestamp: headers.get_one(TIMESTAMP_HEADER).and_then(|value| value.parse().ok()).unwrap_or_default(),
+            signature: headers.get_one(SIGNATURE_HEADER).unwrap_or_default().to_string(),
+        })
+    }
+}
+
+#[post("/hook", data = "<body>")]
+fn hook(inbox: &State<Inbox>, headers: Headers, body: String) -> Status {
+    inbox.lock().unwrap().push(Received { event: headers.event, timestamp: headers.timestamp, signature: headers.signature, body });
+    Status::NoContent
+}
+
+#[post("/fail")]
+fn fail() -> Status {
+    Status::InternalServerError
+}
+
+// Start a stub receiver on a free port, returning its base URL and what it receives
+fn launch_receiver() -> (String, Inbox) {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let inbox = Inbox::default();
+    let receiver = rocket::custom(Config { port, log_level: LogLevel::Off, ..Config::debug_default() })
+        .manage(inbox.clone())
+        .mount("/", routes![hook, fail]);
+    thread::spawn(move || {
+        rocket::execute(receiver.launch()).expect("receiver failed to launch");
+    });
+
+    for _ in 0..100 {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            return (format!("http://127.0.0.1:{}", port), inbox);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("receiver did not start on port {}", port);
+}
+
+// The app with a worker that polls and retries quickly
+fn setup_client() -> Client {
+    let pool = establish_test_connection();
+    cleanup_database(&pool).unwrap(); // Clean up the database before starting the test
+    run_seed_script(&pool).unwrap(); // Seed the database with initial data
+
+    let rocket = build_rocket();
+    let figment = rocket.figment().clone()
+        .merge(("webhooks.poll_interval_ms", 50))
+        .merge(("webhooks.retry_base_ms", 0))
+        .merge(("webhooks.max_attempts", 2))
+        .merge(("webhooks.disable_after", 3));
+    Client::tracked(rocket.configure(figment)).expect("valid rocket instance")
+}
+
+fn register(client: &Client, url: &str, events: &[&str]) -> CreatedWebhook {
+    let response = client.post("/webhooks")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "url": url, "events": events }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().unwrap()
+}
+
+fn deliveries(client: &Client, id: i32) -> Vec<WebhookDelivery> {
+    let response = client.get(format!("/webhooks/{}/deliveries?user_id=1", id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().unwrap()
+}
+
+// Poll until `done` holds, since deliveries happen on the worker thread
+fn wait_for(mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for the webhook worker");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn test_register_webhook_validates() {
+    let client = setup_client();
+
+    let response = client.post("/webhooks")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "url": "ftp://example.com", "events": ["todo.created"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client.post("/webhooks")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "url": "http://example.com", "events": ["todo.renamed"] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let created = register(&client, "http://example.com/hook", &["todo.completed", "todo.created"]);
+    assert_eq!(created.secret.len(), 64);
+    assert_eq!(created.webhook.events.0, vec!["todo.completed", "todo.created"]);
+
+    // The secret is not shown again
+    let response = client.get("/webhooks?user_id=1").dispatch();
+    let body = response.into_string().unwrap();
+    assert!(!body.contains(&created.secret));
+    let listed: Vec<Webhook> = serde_json::from_str(&body).unwrap();
+    assert_eq!(listed.len(), 1);
+    assert!(listed[0].active);
+
+    let response = client.get(format!("/webhooks/{}/deliveries?user_id=2", created.webhook.id)).dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn test_deliveries_are_signed_and_filtered() {
+    let (base, inbox) = launch_receiver();
+    let client = setup_client();
+    let created = register(&client, &format!("{}/hook", base), &["todo.completed"]);
+
+    // Only the completion matches the webhook's events
+    client.post("/todos")
+        .header(ContentType::JSON)
+        .body(json!({ "title": "Hooked", "completed": false, "user_id": 1 }).to_string())
+        .dispatch();
+    client.put("/todos/1/complete?user_id=1").dispatch();
+
+    wait_for(|| !inbox.lock().unwrap().is_empty());
+    thread::sleep(Duration::from_millis(200));
+    let received = inbox.lock().unwrap().clone();
+    assert_eq!(received.len(), 1);
+
+    let request = &received[0];
+    assert_eq!(request.event, "todo.completed");
+    assert_eq!(request.signature, sign(&created.secret, request.timestamp, &request.body));
+    let payload: WebhookPayload = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(payload.event, "todo.completed");
+    assert_eq!(payload.change.todo_id, 1);
+    assert!(payload.change.todo.unwrap().completed);
+
+    wait_for(|| deliveries(&client, created.webhook.id).iter().all(|delivery| delivery.status == DeliveryStatus::Delivered));
+    let log = deliveries(&client, created.webhook.id);
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].attempts, 1);
+    assert_eq!(log[0].response_status, Some(204));
+}
+
+#[test]
+fn test_failing_webhook_is_retried_then_disabled() {
+    let (base, _) = launch_receiver();
+    let client = setup_client();
+    let created = register(&client, &format!("{}/fail", base), &["todo.completed", "todo.deleted"]);
+
+    client.put("/todos/1/complete?user_id=1").dispatch();
+    wait_for(|| deliveries(&client, created.webhook.id).first().is_some_and(|delivery| delivery.status == DeliveryStatus::Failed));
+
+    let log = deliveries(&client, created.webhook.id);
+    assert_eq!(log[0].attempts, 2);
+    assert_eq!(log[0].response_status, Some(500));
+
+    // A third failure in a row disables the webhook
+    client.delete("/todos/2?user_id=1").dispatch();
+    wait_for(|| {
+        let response = client.get("/webhooks?user_id=1").dispatch();
+        !response.into_json::<Vec<Webhook>>().unwrap()[0].active
+    });
+
+    // New changes are not queued for a disabled webhook
+    client.delete("/todos/1?user_id=1").dispatch();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(deliveries(&client, created.webhook.id).len(), 2);
+
+    let response = client.put(format!("/webhooks/{}/enable?user_id=1", created.webhook.id)).dispatch();
+    let webhook: Webhook = response.into_json().unwrap();
+    assert!(webhook.active);
+    assert_eq!(webhook.consecutive_failures, 0);
+}
+
+#[test]
+fn test_failures_in_one_pass_add_up() {
+    let (base, _) = launch_receiver();
+    let client = setup_client();
+    for title in ["Third", "Fourth", "Fifth"] {
+        client.post("/todos")
+            .header(ContentType::JSON)
+            .body(json!({ "title": title, "completed": false, "user_id": 1 }).to_string())
+            .dispatch();
+    }
+    let created = register(&client, &format!("{}/fail", base), &["todo.completed"]);
+
+    // Four deliveries fall due together; the third failure disables the webhook before the fourth is sent
+    let response = client.post("/todos/bulk")
+        .header(ContentType::JSON)
+        .body(json!({ "user_id": 1, "operations": [{ "action": "complete", "ids": [1, 3, 4, 5] }] }).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    wait_for(|| {
+        let response = client.get("/webhooks?user_id=1").dispatch();
+        !response.into_json::<Vec<Webhook>>().unwrap()[0].active
+    });
+    thread::sleep(Duration::from_millis(200));
+
+    let response = client.get("/webhooks?user_id=1").dispatch();
+    assert_eq!(response.into_json::<Vec<Webhook>>().unwrap()[0].consecutive_failures, 3);
+    let log = deliveries(&client, created.webhook.id);
+    assert_eq!(log.len(), 4);
+    assert_eq!(log.iter().filter(|delivery| delivery.attempts > 0).count(), 3);
+}
+
+#[test]
+fn test_delete_webhook() {
+    let client = setup_client();
+    let created = register(&client, "http://example.com/hook", &["todo.created"]);
+
+    let response = client.delete(format!("/webhooks/{}?user_id=2", created.webhook.id)).dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client.delete(format!("/webhooks/{}?user_id=1", created.webhook.id)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/webhooks?user_id=1").dispatch();
+    assert_eq!(response.into_json::<Vec<Webhook>>().unwrap().len(), 0);
+}